@@ -2,8 +2,8 @@ use anyhow::Result;
 use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 use ignore::{ParallelVisitor, ParallelVisitorBuilder, WalkBuilder, WalkState};
 use minidex::{
-    CompactorConfig, CompactorConfigBuilder, FilesystemEntry, Index, Kind, SearchOptions,
-    SearchResult, VolumeType, category,
+    CategoryClassifier, CompactorConfig, CompactorConfigBuilder, FilesystemEntry, Index, Kind,
+    SearchOptions, SearchResult, VolumeType, category,
 };
 use ratatui::{
     DefaultTerminal, Frame,
@@ -95,7 +95,10 @@ impl App {
 
         let index_exists = std::path::Path::new(&abs_index_path).exists();
         let index = if index_exists {
-            Some(Arc::new(Index::open_with_config(&abs_index_path, config)?))
+            Some(Arc::new(
+                Index::open_with_config(&abs_index_path, config)?
+                    .with_classifier(CategoryClassifier::default()),
+            ))
         } else {
             None
         };
@@ -146,7 +149,10 @@ impl App {
             .tombstone_threshold(self.edit_tombstone_threshold.parse().unwrap_or(2500))
             .build();
 
-        let index = Arc::new(Index::open_with_config(&self.index_path, config)?);
+        let index = Arc::new(
+            Index::open_with_config(&self.index_path, config)?
+                .with_classifier(CategoryClassifier::default()),
+        );
         self.index = Some(Arc::clone(&index));
         Ok(index)
     }
@@ -174,7 +180,10 @@ impl App {
         self.edit_index_path = self.index_path.clone();
 
         // Index handles its own drop (sync + join threads)
-        let index = Arc::new(Index::open_with_config(&self.index_path, config)?);
+        let index = Arc::new(
+            Index::open_with_config(&self.index_path, config)?
+                .with_classifier(CategoryClassifier::default()),
+        );
         self.index = Some(Arc::clone(&index));
         self.results.clear();
         self.search_latencies_us.clear();
@@ -536,25 +545,6 @@ impl App {
     }
 }
 
-fn detect_category(path: &std::path::Path) -> u8 {
-    let ext = path
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or_default()
-        .to_lowercase();
-
-    match ext.as_str() {
-        "jpg" | "jpeg" | "png" | "gif" | "webp" | "svg" => category::IMAGE,
-        "mp4" | "mkv" | "mov" | "avi" | "webm" => category::VIDEO,
-        "mp3" | "wav" | "ogg" | "flac" | "aac" => category::AUDIO,
-        "pdf" | "doc" | "docx" | "ppt" | "pptx" | "xls" | "xlsx" | "odt" => category::DOCUMENT,
-        "zip" | "tar" | "gz" | "bz2" | "xz" | "7z" | "rar" => category::ARCHIVE,
-        "txt" | "md" | "rs" | "js" | "ts" | "c" | "cpp" | "h" | "hpp" | "py" | "go" | "rb"
-        | "json" | "yaml" | "toml" | "html" | "css" => category::TEXT,
-        _ => category::OTHER,
-    }
-}
-
 struct ChannelScanner {
     tx: std::sync::mpsc::Sender<FilesystemEntry>,
     file_count: Arc<AtomicU64>,
@@ -596,7 +586,50 @@ impl ParallelVisitor for ChannelScanner {
                 .unwrap_or_default()
                 .as_micros() as u64;
 
-            let cat = detect_category(entry.path());
+            // Not every platform or filesystem records a birth time
+            let created = metadata
+                .created()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_micros() as u64);
+
+            let size = if metadata.is_file() {
+                metadata.len()
+            } else {
+                0
+            };
+
+            let symlink_target = if kind == Kind::Symlink {
+                std::fs::read_link(entry.path()).ok()
+            } else {
+                None
+            };
+
+            #[cfg(unix)]
+            let (uid, gid, mode, file_id) = {
+                use std::os::unix::fs::MetadataExt;
+                (
+                    Some(metadata.uid()),
+                    Some(metadata.gid()),
+                    Some(metadata.mode()),
+                    Some(minidex::FileId {
+                        dev: metadata.dev(),
+                        inode: metadata.ino(),
+                    }),
+                )
+            };
+            #[cfg(not(unix))]
+            let (uid, gid, mode, file_id) = (None, None, None, None);
+
+            // Dotfiles are always hidden, this only adds the Windows attribute
+            #[cfg(windows)]
+            let hidden = {
+                use std::os::windows::fs::MetadataExt;
+                const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+                metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+            };
+            #[cfg(not(windows))]
+            let hidden = false;
 
             let _ = self.tx.send(FilesystemEntry {
                 path: entry.path().to_path_buf(),
@@ -605,7 +638,17 @@ impl ParallelVisitor for ChannelScanner {
                 kind,
                 last_modified,
                 last_accessed,
-                category: cat,
+                // Assigned by the index classifier
+                category: category::OTHER,
+                size,
+                created,
+                symlink_target,
+                uid,
+                gid,
+                mode,
+                hidden,
+                file_id,
+                ..Default::default()
             });
             self.file_count.fetch_add(1, Ordering::SeqCst);
             WalkState::Continue
@@ -948,7 +991,7 @@ fn ui_search(f: &mut Frame, app: &mut App, area: Rect) {
                     .map(|dt| dt.format("%y-%m-%d %H:%M:%S").to_string())
                     .unwrap_or_else(|| "N/A".to_string());
 
-            let content = Line::from(vec![
+            let mut spans = vec![
                 Span::styled(
                     format!("{: <5} ", kind_str),
                     Style::default().fg(Color::Cyan),
@@ -967,8 +1010,14 @@ fn ui_search(f: &mut Frame, app: &mut App, area: Rect) {
                     Style::default().fg(Color::DarkGray),
                 ),
                 Span::raw(res.path.to_string_lossy()),
-            ]);
-            ListItem::new(content)
+            ];
+            if let Some(target) = &res.symlink_target {
+                spans.push(Span::styled(
+                    format!(" -> {}", target.to_string_lossy()),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            ListItem::new(Line::from(spans))
         })
         .collect();
 
@@ -4,7 +4,7 @@ use std::sync::{
 };
 
 use ignore::{ParallelVisitor, ParallelVisitorBuilder, WalkBuilder, WalkState};
-use minidex::{FilesystemEntry, Index, Kind, SearchOptions, VolumeType, category};
+use minidex::{CategoryClassifier, FilesystemEntry, Index, Kind, SearchOptions, category};
 
 struct Scanner<'a> {
     index: &'a Index,
@@ -46,8 +46,23 @@ impl<'a> ParallelVisitor for Scanner<'a> {
                 kind,
                 last_modified,
                 last_accessed,
-                category: category::OTHER, // This should also be properly extracted by a magic number
-                volume_type: VolumeType::Local,
+                category: category::OTHER, // Assigned by the index classifier
+                size: if metadata.is_file() {
+                    metadata.len()
+                } else {
+                    0
+                },
+                created: metadata
+                    .created()
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_micros() as u64),
+                symlink_target: if kind == Kind::Symlink {
+                    std::fs::read_link(entry.path()).ok()
+                } else {
+                    None
+                },
+                ..Default::default()
             });
             self.file_count.fetch_add(1, Ordering::SeqCst);
             WalkState::Continue
@@ -60,7 +75,10 @@ impl<'a> ParallelVisitor for Scanner<'a> {
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let index_path = "./index";
 
-    let index = Index::open(index_path)?;
+    // Classify files by their content as well as their extension
+    let mut classifier = CategoryClassifier::default();
+    classifier.set_sniffing(true);
+    let index = Index::open(index_path)?.with_classifier(classifier);
 
     let home_dir = if cfg!(windows) {
         std::env::var("USERPROFILE")
@@ -25,6 +25,16 @@ fn bench_dat_compression(c: &mut Criterion) {
                                     last_accessed: 1000,
                                     category: 1,
                                     volume_type: VolumeType::Local,
+                                    size: 0,
+                                    created: 0,
+                                    tags: Vec::new(),
+                                    content_hash: None,
+                                    symlink_target: None,
+                                    uid: None,
+                                    gid: None,
+                                    mode: None,
+                                    hidden: false,
+                                    file_id: None,
                                 },
                             )
                         })
@@ -1,8 +1,5 @@
 use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
-use minidex::{
-    CompactorConfigBuilder, FilesystemEntry, Index, Kind, SearchOptions, VolumeType, category,
-    tokenize,
-};
+use minidex::{CompactorConfigBuilder, FilesystemEntry, Index, SearchOptions, category, tokenize};
 use std::hint::black_box;
 use std::path::PathBuf;
 use tempfile::tempdir;
@@ -34,11 +31,10 @@ fn create_entry(i: usize) -> FilesystemEntry {
     FilesystemEntry {
         path: PathBuf::from(format!("/foo/bar_{}.txt", i)),
         volume: "vol1".to_string(),
-        kind: Kind::File,
         last_modified: 1000,
         last_accessed: 1000,
         category: category::TEXT,
-        volume_type: VolumeType::Local,
+        ..Default::default()
     }
 }
 
@@ -140,11 +136,10 @@ fn bench_index_search(c: &mut Criterion) {
                 .insert(FilesystemEntry {
                     path: PathBuf::from(&path),
                     volume: "vol1".to_string(),
-                    kind: Kind::File,
                     last_modified: 1000,
                     last_accessed: 1000,
                     category: category::TEXT,
-                    volume_type: VolumeType::Local,
+                    ..Default::default()
                 })
                 .expect("failed to insert");
 
@@ -0,0 +1,348 @@
+//! File categories, stored as bit flags so that searches can match any of
+//! several categories at once (e.g. `cat:image|video`).
+
+use std::{collections::HashMap, io::Read, path::Path};
+
+mod sniff;
+pub use sniff::{SNIFF_LEN, sniff};
+
+pub const OTHER: u8 = 0;
+pub const ARCHIVE: u8 = 1 << 0;
+pub const DOCUMENT: u8 = 1 << 1;
+pub const IMAGE: u8 = 1 << 2;
+pub const VIDEO: u8 = 1 << 3;
+pub const AUDIO: u8 = 1 << 4;
+pub const TEXT: u8 = 1 << 5;
+
+/// Flags left for custom categories, see `CategoryClassifier::add_category`
+const CUSTOM: [u8; 2] = [1 << 6, 1 << 7];
+
+/// Names of the built-in categories, as understood by the `cat:` query operator
+const NAMES: [(&str, u8); 11] = [
+    ("archive", ARCHIVE),
+    ("document", DOCUMENT),
+    ("doc", DOCUMENT),
+    ("image", IMAGE),
+    ("img", IMAGE),
+    ("video", VIDEO),
+    ("vid", VIDEO),
+    ("audio", AUDIO),
+    ("aud", AUDIO),
+    ("text", TEXT),
+    ("txt", TEXT),
+];
+
+const DEFAULT_EXTENSIONS: [(&[&str], u8); 6] = [
+    (&["jpg", "jpeg", "png", "gif", "webp", "svg"], IMAGE),
+    (&["mp4", "mkv", "mov", "avi", "webm"], VIDEO),
+    (&["mp3", "wav", "ogg", "flac", "aac"], AUDIO),
+    (
+        &["pdf", "doc", "docx", "ppt", "pptx", "xls", "xlsx", "odt"],
+        DOCUMENT,
+    ),
+    (&["zip", "tar", "gz", "bz2", "xz", "7z", "rar"], ARCHIVE),
+    (
+        &[
+            "txt", "md", "rs", "js", "ts", "c", "cpp", "h", "hpp", "py", "go", "rb", "json",
+            "yaml", "toml", "html", "css",
+        ],
+        TEXT,
+    ),
+];
+
+const DEFAULT_NAMES: [(&str, u8); 4] = [
+    ("makefile", TEXT),
+    ("dockerfile", TEXT),
+    ("readme", TEXT),
+    ("license", TEXT),
+];
+
+/// Flag of the built-in category with the given name (case insensitive),
+/// e.g. `image` or `img`
+pub fn from_name(name: &str) -> Option<u8> {
+    NAMES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|(_, category)| *category)
+}
+
+/// Assigns categories to paths by their file name or extension.
+///
+/// The default classifier knows common extensions (`jpg`, `mp4`, `pdf`...)
+/// and a few extensionless file names (`Makefile`, `README`...), which can
+/// be overridden or extended with `set_extension` and `set_name`.
+/// Up to two custom categories can be registered by name with
+/// `add_category`, and are then understood by `cat:` queries of an index
+/// using the classifier (see `Index::with_classifier`).
+///
+/// With sniffing enabled (see `set_sniffing`), files are also recognized by
+/// their content, which catches extensionless and misnamed files.
+#[derive(Debug, Clone)]
+pub struct CategoryClassifier {
+    extensions: HashMap<String, u8>,
+    names: HashMap<String, u8>,
+    custom: Vec<(String, u8)>,
+    sniffing: bool,
+}
+
+impl Default for CategoryClassifier {
+    fn default() -> Self {
+        let mut classifier = Self::empty();
+        for (extensions, category) in DEFAULT_EXTENSIONS {
+            for extension in extensions {
+                classifier.set_extension(extension, category);
+            }
+        }
+        for (name, category) in DEFAULT_NAMES {
+            classifier.set_name(name, category);
+        }
+        classifier
+    }
+}
+
+impl CategoryClassifier {
+    /// A classifier with the default extensions and file names
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// A classifier without any extension or file name, classifying every
+    /// path as `OTHER` until some are set
+    pub fn empty() -> Self {
+        Self {
+            extensions: HashMap::new(),
+            names: HashMap::new(),
+            custom: Vec::new(),
+            sniffing: false,
+        }
+    }
+
+    /// Classifies files with the given extension (case insensitive, with or
+    /// without the leading `.`) as `category`, replacing any previous mapping
+    pub fn set_extension(&mut self, extension: &str, category: u8) {
+        self.extensions
+            .insert(extension.trim_start_matches('.').to_lowercase(), category);
+    }
+
+    /// Classifies files with the given name (case insensitive) as `category`,
+    /// whatever their extension
+    pub fn set_name(&mut self, name: &str, category: u8) {
+        self.names.insert(name.to_lowercase(), category);
+    }
+
+    /// Registers a custom category, returning its flag. Registering a name
+    /// twice, or the name of a built-in category, returns the existing flag.
+    /// Returns `None` once all the category flags are taken.
+    pub fn add_category(&mut self, name: &str) -> Option<u8> {
+        if let Some(category) = self.category_named(name) {
+            return Some(category);
+        }
+
+        let category = CUSTOM
+            .into_iter()
+            .find(|flag| self.custom.iter().all(|(_, taken)| taken != flag))?;
+        self.custom.push((name.to_lowercase(), category));
+        Some(category)
+    }
+
+    /// Flag of the built-in or custom category with the given name (case
+    /// insensitive)
+    pub fn category_named(&self, name: &str) -> Option<u8> {
+        from_name(name).or_else(|| {
+            self.custom
+                .iter()
+                .find(|(custom, _)| custom.eq_ignore_ascii_case(name))
+                .map(|(_, category)| *category)
+        })
+    }
+
+    /// Category of the file at `path`, by its name first, then by its
+    /// extension. Returns `OTHER` for unknown files.
+    pub fn classify(&self, path: &Path) -> u8 {
+        let Some(file_name) = path.file_name().map(|name| name.to_string_lossy()) else {
+            return OTHER;
+        };
+
+        if let Some(category) = self.names.get(&file_name.to_lowercase()) {
+            return *category;
+        }
+
+        path.extension()
+            .and_then(|ext| self.extensions.get(&ext.to_string_lossy().to_lowercase()))
+            .copied()
+            .unwrap_or(OTHER)
+    }
+
+    /// Enables reading the first bytes of files in `classify_file`, to
+    /// classify them by their content
+    pub fn set_sniffing(&mut self, enabled: bool) {
+        self.sniffing = enabled;
+    }
+
+    /// Category of the file at `path` starting with `header` (see `sniff`).
+    /// The content takes precedence over the name, except for custom
+    /// categories, and for documents stored as ZIP files (e.g. `docx`) that
+    /// are not recognized as such.
+    pub fn classify_content(&self, path: &Path, header: &[u8]) -> u8 {
+        let by_path = self.classify(path);
+        if CUSTOM.contains(&by_path) {
+            return by_path;
+        }
+
+        match sniff(header) {
+            Some(ARCHIVE) if by_path == DOCUMENT => by_path,
+            Some(category) => category,
+            None => by_path,
+        }
+    }
+
+    /// Category of the file at `path`, reading its first bytes if sniffing
+    /// is enabled. Unreadable files, and anything but regular files (such as
+    /// FIFOs, which would block), are classified by their name only.
+    pub fn classify_file(&self, path: &Path) -> u8 {
+        if !self.sniffing || !std::fs::metadata(path).is_ok_and(|meta| meta.is_file()) {
+            return self.classify(path);
+        }
+
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        match std::fs::File::open(path)
+            .and_then(|file| file.take(SNIFF_LEN as u64).read_to_end(&mut header))
+        {
+            Ok(_) => self.classify_content(path, &header),
+            Err(_) => self.classify(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_defaults() {
+        let classifier = CategoryClassifier::default();
+        assert_eq!(classifier.classify(Path::new("/home/me/photo.JPG")), IMAGE);
+        assert_eq!(
+            classifier.classify(Path::new("/home/me/report.pdf")),
+            DOCUMENT
+        );
+        assert_eq!(classifier.classify(Path::new("backup.tar.gz")), ARCHIVE);
+        assert_eq!(classifier.classify(Path::new("/src/Makefile")), TEXT);
+        assert_eq!(classifier.classify(Path::new("/src/unknown.xyz")), OTHER);
+        assert_eq!(classifier.classify(Path::new("/src/noext")), OTHER);
+        assert_eq!(classifier.classify(Path::new("/")), OTHER);
+
+        let empty = CategoryClassifier::empty();
+        assert_eq!(empty.classify(Path::new("photo.jpg")), OTHER);
+    }
+
+    #[test]
+    fn test_classify_overrides() {
+        let mut classifier = CategoryClassifier::default();
+        classifier.set_extension(".SVG", TEXT);
+        classifier.set_extension("heic", IMAGE);
+        classifier.set_name("notes.md", DOCUMENT);
+
+        assert_eq!(classifier.classify(Path::new("logo.svg")), TEXT);
+        assert_eq!(classifier.classify(Path::new("photo.HEIC")), IMAGE);
+        assert_eq!(classifier.classify(Path::new("/home/Notes.md")), DOCUMENT);
+        assert_eq!(classifier.classify(Path::new("/home/todo.md")), TEXT);
+    }
+
+    #[test]
+    fn test_custom_categories() {
+        let mut classifier = CategoryClassifier::default();
+        let model = classifier.add_category("Model").unwrap();
+        let font = classifier.add_category("font").unwrap();
+        assert_ne!(model, font);
+        assert_eq!(
+            model & (ARCHIVE | DOCUMENT | IMAGE | VIDEO | AUDIO | TEXT),
+            0
+        );
+
+        // Existing names return their flag, and flags run out after two
+        assert_eq!(classifier.add_category("model"), Some(model));
+        assert_eq!(classifier.add_category("img"), Some(IMAGE));
+        assert_eq!(classifier.add_category("ebook"), None);
+
+        assert_eq!(classifier.category_named("MODEL"), Some(model));
+        assert_eq!(classifier.category_named("video"), Some(VIDEO));
+        assert_eq!(classifier.category_named("ebook"), None);
+
+        classifier.set_extension("gguf", model);
+        assert_eq!(classifier.classify(Path::new("llama.gguf")), model);
+    }
+
+    #[test]
+    fn test_classify_content() {
+        let mut classifier = CategoryClassifier::default();
+        let png = b"\x89PNG\r\n\x1A\n\x00\x00\x00\rIHDR";
+        let zip = b"PK\x03\x04\x14\x00\x00\x00\x08\x00";
+
+        assert_eq!(classifier.classify_content(Path::new("scan"), png), IMAGE);
+        assert_eq!(
+            classifier.classify_content(Path::new("scan.txt"), png),
+            IMAGE
+        );
+        assert_eq!(
+            classifier.classify_content(Path::new("notes.txt"), b"hello"),
+            TEXT
+        );
+        assert_eq!(
+            classifier.classify_content(Path::new("report.docx"), zip),
+            DOCUMENT
+        );
+        assert_eq!(
+            classifier.classify_content(Path::new("bundle"), zip),
+            ARCHIVE
+        );
+
+        let app = classifier.add_category("app").unwrap();
+        classifier.set_extension("apk", app);
+        assert_eq!(classifier.classify_content(Path::new("game.apk"), zip), app);
+    }
+
+    #[test]
+    fn test_classify_file() -> std::io::Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "minidex_test_classify_{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::write(&path, b"%PDF-1.7\n")?;
+
+        let mut classifier = CategoryClassifier::default();
+        assert_eq!(classifier.classify_file(&path), OTHER);
+        classifier.set_sniffing(true);
+        assert_eq!(classifier.classify_file(&path), DOCUMENT);
+        // Missing files are classified by name
+        assert_eq!(
+            classifier.classify_file(Path::new("/missing/photo.png")),
+            IMAGE
+        );
+
+        // And so are FIFOs, without blocking on them
+        #[cfg(unix)]
+        {
+            let fifo = path.with_extension("png");
+            let c_path = std::ffi::CString::new(fifo.as_os_str().as_encoded_bytes())?;
+            if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            assert_eq!(classifier.classify_file(&fifo), IMAGE);
+            std::fs::remove_file(fifo)?;
+        }
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(from_name("Image"), Some(IMAGE));
+        assert_eq!(from_name("doc"), Some(DOCUMENT));
+        assert_eq!(from_name("spreadsheet"), None);
+    }
+}
@@ -0,0 +1,182 @@
+use super::{ARCHIVE, AUDIO, DOCUMENT, IMAGE, VIDEO};
+
+/// Number of leading bytes of a file `sniff` looks at. Shorter headers are
+/// fine, but formats identified further in (e.g. tar) are then missed.
+pub const SNIFF_LEN: usize = 512;
+
+/// Parts of a signature, as the bytes a header must contain at an offset
+type Signature = &'static [(usize, &'static [u8])];
+
+/// Signatures of common formats, whose parts a header must all contain. More
+/// specific signatures come first, e.g. documents stored as ZIP files before
+/// ZIP archives.
+const SIGNATURES: &[(Signature, u8)] = &[
+    // Images
+    (&[(0, b"\xFF\xD8\xFF")], IMAGE),
+    (&[(0, b"\x89PNG\r\n\x1A\n")], IMAGE),
+    (&[(0, b"GIF87a")], IMAGE),
+    (&[(0, b"GIF89a")], IMAGE),
+    (&[(0, b"RIFF"), (8, b"WEBP")], IMAGE),
+    (&[(0, b"II*\x00")], IMAGE),
+    (&[(0, b"MM\x00*")], IMAGE),
+    (&[(4, b"ftypheic")], IMAGE),
+    (&[(4, b"ftypheix")], IMAGE),
+    (&[(4, b"ftypmif1")], IMAGE),
+    (&[(4, b"ftypavif")], IMAGE),
+    // Audio
+    (&[(0, b"ID3")], AUDIO),
+    (&[(0, b"\xFF\xFB")], AUDIO),
+    (&[(0, b"\xFF\xF3")], AUDIO),
+    (&[(0, b"\xFF\xF2")], AUDIO),
+    (&[(0, b"\xFF\xF1")], AUDIO),
+    (&[(0, b"\xFF\xF9")], AUDIO),
+    (&[(0, b"RIFF"), (8, b"WAVE")], AUDIO),
+    (&[(0, b"FORM"), (8, b"AIFF")], AUDIO),
+    (&[(0, b"OggS")], AUDIO),
+    (&[(0, b"fLaC")], AUDIO),
+    (&[(0, b"MThd")], AUDIO),
+    (&[(4, b"ftypM4A ")], AUDIO),
+    // Video
+    (&[(4, b"ftyp")], VIDEO),
+    (&[(0, b"\x1A\x45\xDF\xA3")], VIDEO),
+    (&[(0, b"RIFF"), (8, b"AVI ")], VIDEO),
+    (&[(0, b"FLV\x01")], VIDEO),
+    (&[(0, b"\x00\x00\x01\xBA")], VIDEO),
+    // Documents
+    (&[(0, b"%PDF-")], DOCUMENT),
+    (&[(0, b"%!PS")], DOCUMENT),
+    (&[(0, b"{\\rtf")], DOCUMENT),
+    (&[(0, b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1")], DOCUMENT),
+    // OpenDocument and EPUB files store their uncompressed MIME type first,
+    // and Office Open XML files usually start with their content types
+    (
+        &[
+            (0, b"PK\x03\x04"),
+            (30, b"mimetypeapplication/vnd.oasis.opendocument"),
+        ],
+        DOCUMENT,
+    ),
+    (
+        &[(0, b"PK\x03\x04"), (30, b"mimetypeapplication/epub+zip")],
+        DOCUMENT,
+    ),
+    (
+        &[(0, b"PK\x03\x04"), (30, b"[Content_Types].xml")],
+        DOCUMENT,
+    ),
+    // Archives
+    (&[(0, b"PK\x03\x04")], ARCHIVE),
+    (&[(0, b"PK\x05\x06")], ARCHIVE),
+    (&[(0, b"\x1F\x8B")], ARCHIVE),
+    (&[(0, b"BZh")], ARCHIVE),
+    (&[(0, b"\xFD7zXZ\x00")], ARCHIVE),
+    (&[(0, b"7z\xBC\xAF\x27\x1C")], ARCHIVE),
+    (&[(0, b"Rar!\x1A\x07")], ARCHIVE),
+    (&[(0, b"\x28\xB5\x2F\xFD")], ARCHIVE),
+    (&[(257, b"ustar")], ARCHIVE),
+];
+
+/// Category of a file by the magic number at the start of its content,
+/// given its first bytes (up to `SNIFF_LEN`). Returns `None` for unknown
+/// formats, including plain text.
+pub fn sniff(header: &[u8]) -> Option<u8> {
+    SIGNATURES
+        .iter()
+        .find(|(parts, _)| {
+            parts.iter().all(|(offset, magic)| {
+                header
+                    .get(*offset..offset + magic.len())
+                    .is_some_and(|bytes| bytes == *magic)
+            })
+        })
+        .map(|(_, category)| *category)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header of a ZIP file whose first entry is `name`
+    fn zip_header(name: &[u8]) -> Vec<u8> {
+        let mut header = b"PK\x03\x04".to_vec();
+        header.resize(26, 0);
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&[0, 0]);
+        header.extend_from_slice(name);
+        header
+    }
+
+    #[test]
+    fn test_sniff_media() {
+        assert_eq!(sniff(b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00"), Some(IMAGE));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1A\n\x00\x00\x00\rIHDR"), Some(IMAGE));
+        assert_eq!(sniff(b"GIF89a\x01\x00\x01\x00"), Some(IMAGE));
+        assert_eq!(sniff(b"RIFF\x24\x00\x00\x00WEBPVP8 "), Some(IMAGE));
+        assert_eq!(
+            sniff(b"\x00\x00\x00\x18ftypheic\x00\x00\x00\x00"),
+            Some(IMAGE)
+        );
+
+        assert_eq!(sniff(b"ID3\x04\x00\x00\x00\x00\x00\x00"), Some(AUDIO));
+        assert_eq!(sniff(b"RIFF\x24\x00\x00\x00WAVEfmt "), Some(AUDIO));
+        assert_eq!(sniff(b"fLaC\x00\x00\x00\x22"), Some(AUDIO));
+        assert_eq!(
+            sniff(b"\x00\x00\x00\x20ftypM4A \x00\x00\x00\x00"),
+            Some(AUDIO)
+        );
+
+        assert_eq!(
+            sniff(b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00"),
+            Some(VIDEO)
+        );
+        assert_eq!(
+            sniff(b"\x00\x00\x00\x14ftypqt  \x00\x00\x00\x00"),
+            Some(VIDEO)
+        );
+        assert_eq!(sniff(b"\x1A\x45\xDF\xA3\x9F\x42\x86\x81"), Some(VIDEO));
+        assert_eq!(sniff(b"RIFF\x24\x00\x00\x00AVI LIST"), Some(VIDEO));
+    }
+
+    #[test]
+    fn test_sniff_documents_and_archives() {
+        assert_eq!(sniff(b"%PDF-1.7\n%\xE2\xE3\xCF\xD3"), Some(DOCUMENT));
+        assert_eq!(
+            sniff(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1\x00\x00"),
+            Some(DOCUMENT)
+        );
+        assert_eq!(sniff(&zip_header(b"[Content_Types].xml")), Some(DOCUMENT));
+        assert_eq!(
+            sniff(&zip_header(
+                b"mimetypeapplication/vnd.oasis.opendocument.text"
+            )),
+            Some(DOCUMENT)
+        );
+        assert_eq!(
+            sniff(&zip_header(b"mimetypeapplication/epub+zip")),
+            Some(DOCUMENT)
+        );
+
+        assert_eq!(sniff(&zip_header(b"src/main.rs")), Some(ARCHIVE));
+        assert_eq!(sniff(b"\x1F\x8B\x08\x00\x00\x00\x00\x00"), Some(ARCHIVE));
+        assert_eq!(sniff(b"\xFD7zXZ\x00\x00\x04"), Some(ARCHIVE));
+        assert_eq!(sniff(b"7z\xBC\xAF\x27\x1C\x00\x04"), Some(ARCHIVE));
+        assert_eq!(sniff(b"Rar!\x1A\x07\x01\x00"), Some(ARCHIVE));
+
+        let mut tar = vec![0u8; SNIFF_LEN];
+        tar[..8].copy_from_slice(b"file.txt");
+        tar[257..263].copy_from_slice(b"ustar\x00");
+        assert_eq!(sniff(&tar), Some(ARCHIVE));
+        // The tar magic lies beyond a short header
+        assert_eq!(sniff(&tar[..200]), None);
+    }
+
+    #[test]
+    fn test_sniff_unknown() {
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"fn main() {}\n"), None);
+        assert_eq!(sniff(b"RIFF\x24\x00\x00\x00XXXX"), None);
+        // Truncated signatures don't match
+        assert_eq!(sniff(b"\x89PNG"), None);
+        assert_eq!(sniff(b"PK\x03"), None);
+    }
+}
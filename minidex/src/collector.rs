@@ -1,46 +1,83 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, hash_map::Entry},
+};
 
-use crate::{common::is_tombstoned, entry::IndexEntry};
+use crate::{
+    common::{PrefixRename, resolve_prefix_ops},
+    entry::IndexEntry,
+};
 
 pub(crate) struct LsmCollector<'a> {
     candidates: HashMap<Cow<'a, str>, (Cow<'a, str>, IndexEntry)>,
     active_tombstones: &'a [(Option<String>, String, u64)],
+    active_renames: &'a [PrefixRename],
 }
 
 impl<'a> LsmCollector<'a> {
-    pub(crate) fn new(active_tombstones: &'a [(Option<String>, String, u64)]) -> Self {
+    pub(crate) fn new(
+        active_tombstones: &'a [(Option<String>, String, u64)],
+        active_renames: &'a [PrefixRename],
+    ) -> Self {
         Self {
             candidates: HashMap::new(),
             active_tombstones,
+            active_renames,
         }
     }
 
     #[inline]
-    pub(crate) fn insert<P, V>(&mut self, path: P, volume: V, entry: IndexEntry)
+    pub(crate) fn insert<P, V>(&mut self, path: P, volume: V, mut entry: IndexEntry)
     where
         P: Into<Cow<'a, str>>,
         V: Into<Cow<'a, str>>,
     {
         let path_cow = path.into();
         let volume_cow = volume.into();
-        if is_tombstoned(
+
+        // Only moved entries need their path replaced
+        let Some(moved) = resolve_prefix_ops(
             &volume_cow,
-            path_cow.as_bytes(),
+            &path_cow,
             entry.opstamp.sequence(),
             self.active_tombstones,
-        ) {
+            self.active_renames,
+        )
+        .map(|(path, sequence)| match path {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(path) => Some((path, sequence)),
+        }) else {
             return;
-        }
+        };
 
-        self.candidates
-            .entry(path_cow)
-            .and_modify(|(current_volume, current_entry)| {
+        let path_cow = match moved {
+            None => path_cow,
+            Some((path, sequence)) => {
+                entry.opstamp = entry.opstamp.with_sequence(sequence);
+                Cow::Owned(path)
+            }
+        };
+
+        match self.candidates.entry(path_cow) {
+            Entry::Occupied(mut occupied) => {
+                let (current_volume, current_entry) = occupied.get_mut();
                 if entry.opstamp.sequence() > current_entry.opstamp.sequence() {
                     *current_entry = entry;
-                    *current_volume = volume_cow.clone();
+                    *current_volume = volume_cow;
                 }
-            })
-            .or_insert((volume_cow, entry));
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert((volume_cow, entry));
+            }
+        }
+    }
+
+    /// Paths of the live entries collected so far
+    pub(crate) fn live_paths(&self) -> impl Iterator<Item = &str> {
+        self.candidates
+            .iter()
+            .filter(|(_, (_, entry))| !entry.opstamp.is_deletion())
+            .map(|(path, _)| path.as_ref())
     }
 
     #[inline]
@@ -55,22 +92,13 @@ impl<'a> LsmCollector<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Kind;
-    use crate::common::VolumeType;
     use crate::opstamp::Opstamp;
 
     #[test]
     fn test_collector_basic_insertion() {
         let sep = std::path::MAIN_SEPARATOR_STR;
-        let mut collector = LsmCollector::new(&[]);
-        let entry = IndexEntry {
-            opstamp: Opstamp::insertion(10),
-            kind: Kind::File,
-            last_modified: 100,
-            last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
-        };
+        let mut collector = LsmCollector::new(&[], &[]);
+        let entry = IndexEntry::file(Opstamp::insertion(10), 100);
         collector.insert(format!("{}a", sep), "vol1".to_string(), entry);
 
         let results: Vec<_> = collector.finish().collect();
@@ -83,23 +111,9 @@ mod tests {
     #[test]
     fn test_collector_version_resolution() {
         let sep = std::path::MAIN_SEPARATOR_STR;
-        let mut collector = LsmCollector::new(&[]);
-        let entry1 = IndexEntry {
-            opstamp: Opstamp::insertion(10),
-            kind: Kind::File,
-            last_modified: 100,
-            last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
-        };
-        let entry2 = IndexEntry {
-            opstamp: Opstamp::insertion(20),
-            kind: Kind::File,
-            last_modified: 200,
-            last_accessed: 200,
-            category: 0,
-            volume_type: VolumeType::Local,
-        };
+        let mut collector = LsmCollector::new(&[], &[]);
+        let entry1 = IndexEntry::file(Opstamp::insertion(10), 100);
+        let entry2 = IndexEntry::file(Opstamp::insertion(20), 200);
 
         // Out-of-order insertion
         collector.insert(format!("{}a", sep), "vol1".to_string(), entry2);
@@ -114,29 +128,15 @@ mod tests {
     fn test_collector_prefix_tombstone() {
         let sep = std::path::MAIN_SEPARATOR_STR;
         let tombstones = vec![(None, format!("{}foo", sep), 50)];
-        let mut collector = LsmCollector::new(&tombstones);
-
-        let entry_dead = IndexEntry {
-            opstamp: Opstamp::insertion(10),
-            kind: Kind::File,
-            last_modified: 100,
-            last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
-        };
-        let entry_alive = IndexEntry {
-            opstamp: Opstamp::insertion(100),
-            kind: Kind::File,
-            last_modified: 100,
-            last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
-        };
+        let mut collector = LsmCollector::new(&tombstones, &[]);
+
+        let entry_dead = IndexEntry::file(Opstamp::insertion(10), 100);
+        let entry_alive = IndexEntry::file(Opstamp::insertion(100), 100);
 
         collector.insert(
             format!("{}foo{}bar", sep, sep),
             "vol1".to_string(),
-            entry_dead,
+            entry_dead.clone(),
         );
         collector.insert(
             format!("{}foo{}baz", sep, sep),
@@ -158,23 +158,9 @@ mod tests {
     #[test]
     fn test_collector_deletion_resolution() {
         let sep = std::path::MAIN_SEPARATOR_STR;
-        let mut collector = LsmCollector::new(&[]);
-        let entry1 = IndexEntry {
-            opstamp: Opstamp::insertion(10),
-            kind: Kind::File,
-            last_modified: 100,
-            last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
-        };
-        let entry2 = IndexEntry {
-            opstamp: Opstamp::deletion(20),
-            kind: Kind::File,
-            last_modified: 0,
-            last_accessed: 0,
-            category: 0,
-            volume_type: VolumeType::Local,
-        };
+        let mut collector = LsmCollector::new(&[], &[]);
+        let entry1 = IndexEntry::file(Opstamp::insertion(10), 100);
+        let entry2 = IndexEntry::file(Opstamp::deletion(20), 0);
 
         collector.insert(format!("{}a", sep), "vol1".to_string(), entry1);
         collector.insert(format!("{}a", sep), "vol1".to_string(), entry2);
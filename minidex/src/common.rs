@@ -1,18 +1,24 @@
+use std::borrow::Cow;
+
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Kind {
+    #[default]
     File,
     Directory,
     Symlink,
 }
 
-impl From<u8> for Kind {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for Kind {
+    type Error = u8;
+
+    /// Fails with the given value if it's not a known kind
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0 => Self::File,
-            1 => Self::Directory,
-            2 => Self::Symlink,
-            _ => unreachable!(),
+            0 => Ok(Self::File),
+            1 => Ok(Self::Directory),
+            2 => Ok(Self::Symlink),
+            _ => Err(value),
         }
     }
 }
@@ -33,20 +39,26 @@ pub(crate) fn is_tombstoned(
     path_bytes: &[u8],
     sequence: u64,
     active_tombstones: &[(Option<String>, String, u64)],
+) -> bool {
+    is_tombstoned_before(volume, path_bytes, sequence, u64::MAX, active_tombstones)
+}
+
+/// Whether a prefix tombstone logged after `sequence`, but before `before`,
+/// covers the path
+#[inline]
+fn is_tombstoned_before(
+    volume: &str,
+    path_bytes: &[u8],
+    sequence: u64,
+    before: u64,
+    active_tombstones: &[(Option<String>, String, u64)],
 ) -> bool {
     if active_tombstones.is_empty() {
         return false;
     }
 
-    let sep = std::path::MAIN_SEPARATOR as u8;
-
     for (tombstone_volume, prefix, stamp) in active_tombstones {
-        if sequence >= *stamp {
-            continue;
-        }
-
-        let prefix_bytes = prefix.as_bytes();
-        if path_bytes.len() < prefix_bytes.len() {
+        if sequence >= *stamp || *stamp >= before {
             continue;
         }
 
@@ -56,30 +68,126 @@ pub(crate) fn is_tombstoned(
             continue;
         }
 
-        if path_bytes[..prefix_bytes.len()].eq_ignore_ascii_case(prefix_bytes)
-            && (path_bytes.len() == prefix_bytes.len() || path_bytes[prefix_bytes.len()] == sep)
-        {
+        if is_under_prefix(path_bytes, prefix.as_bytes()) {
             return true;
         }
     }
     false
 }
 
-pub mod category {
-    pub const OTHER: u8 = 0;
-    pub const ARCHIVE: u8 = 1 << 0;
-    pub const DOCUMENT: u8 = 1 << 1;
-    pub const IMAGE: u8 = 1 << 2;
-    pub const VIDEO: u8 = 1 << 3;
-    pub const AUDIO: u8 = 1 << 4;
-    pub const TEXT: u8 = 1 << 5;
+/// Whether the path is the prefix itself, or lives under it (ignoring ASCII case)
+#[inline]
+fn is_under_prefix(path_bytes: &[u8], prefix_bytes: &[u8]) -> bool {
+    let sep = std::path::MAIN_SEPARATOR as u8;
+
+    path_bytes.len() >= prefix_bytes.len()
+        && path_bytes[..prefix_bytes.len()].eq_ignore_ascii_case(prefix_bytes)
+        && (path_bytes.len() == prefix_bytes.len() || path_bytes[prefix_bytes.len()] == sep)
+}
+
+/// Whether the path is the prefix itself, or lives under it (matching bytes exactly)
+#[inline]
+fn is_under_exact_prefix(path_bytes: &[u8], prefix_bytes: &[u8]) -> bool {
+    let sep = std::path::MAIN_SEPARATOR as u8;
+
+    path_bytes.starts_with(prefix_bytes)
+        && (path_bytes.len() == prefix_bytes.len() || path_bytes[prefix_bytes.len()] == sep)
+}
+
+/// A move of every entry under `old_prefix` to `new_prefix`, logged at `seq`
+/// and applied lazily when reading entries, until compaction materializes it.
+/// Unlike tombstones, prefixes are matched exactly: on a case-sensitive
+/// filesystem, moving `/A` leaves `/a` in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PrefixRename {
+    pub volume: Option<String>,
+    pub old_prefix: String,
+    pub new_prefix: String,
+    pub seq: u64,
+}
+
+impl PrefixRename {
+    /// The path an entry at `path` is moved to, if the rename covers it
+    fn apply(&self, volume: &str, path: &str) -> Option<String> {
+        if let Some(v) = &self.volume
+            && v != volume
+        {
+            return None;
+        }
+
+        is_under_exact_prefix(path.as_bytes(), self.old_prefix.as_bytes())
+            .then(|| format!("{}{}", self.new_prefix, &path[self.old_prefix.len()..]))
+    }
+
+    /// The path an entry now at `path` had before the rename, if it was moved
+    pub(crate) fn revert(&self, path: &str) -> Option<String> {
+        is_under_exact_prefix(path.as_bytes(), self.new_prefix.as_bytes())
+            .then(|| format!("{}{}", self.old_prefix, &path[self.new_prefix.len()..]))
+    }
+}
+
+/// Adds a rename to a list ordered by sequence, skipping it if it's already
+/// there (e.g. when replayed from several WALs)
+pub(crate) fn insert_prefix_rename(renames: &mut Vec<PrefixRename>, rename: PrefixRename) {
+    if renames.contains(&rename) {
+        return;
+    }
+    let position = renames.partition_point(|r| r.seq <= rename.seq);
+    renames.insert(position, rename);
+}
+
+/// Resolves where an entry written at `sequence` lives now, replaying the
+/// prefix renames and tombstones logged after it in order. Moved entries take
+/// the sequence of the last rename moving them, so that they replace whatever
+/// was at their new path before the move.
+/// Returns `None` if a prefix tombstone deleted the entry. `renames` must be
+/// ordered by sequence.
+pub(crate) fn resolve_prefix_ops<'a>(
+    volume: &str,
+    path: &'a str,
+    sequence: u64,
+    active_tombstones: &[(Option<String>, String, u64)],
+    active_renames: &[PrefixRename],
+) -> Option<(Cow<'a, str>, u64)> {
+    let mut path = Cow::Borrowed(path);
+    let mut sequence = sequence;
+
+    for rename in active_renames {
+        if rename.seq <= sequence {
+            continue;
+        }
+        let Some(moved) = rename.apply(volume, &path) else {
+            continue;
+        };
+
+        // Tombstones logged before the move refer to the previous path
+        if is_tombstoned_before(
+            volume,
+            path.as_bytes(),
+            sequence,
+            rename.seq,
+            active_tombstones,
+        ) {
+            return None;
+        }
+
+        path = Cow::Owned(moved);
+        sequence = rename.seq;
+    }
+
+    if is_tombstoned(volume, path.as_bytes(), sequence, active_tombstones) {
+        return None;
+    }
+
+    Some((path, sequence))
 }
 
 /// Volume type, used to distinguish local volumes
 /// from remote and network volumes.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 #[repr(u8)]
 pub enum VolumeType {
+    #[default]
     Local = 0,
     Network = 1,
     Removable = 2,
@@ -103,9 +211,10 @@ mod tests {
 
     #[test]
     fn test_kind_conversions() {
-        assert_eq!(Kind::from(0), Kind::File);
-        assert_eq!(Kind::from(1), Kind::Directory);
-        assert_eq!(Kind::from(2), Kind::Symlink);
+        assert_eq!(Kind::try_from(0), Ok(Kind::File));
+        assert_eq!(Kind::try_from(1), Ok(Kind::Directory));
+        assert_eq!(Kind::try_from(2), Ok(Kind::Symlink));
+        assert_eq!(Kind::try_from(7), Err(7));
         assert_eq!(u8::from(Kind::File), 0);
         assert_eq!(u8::from(Kind::Directory), 1);
         assert_eq!(u8::from(Kind::Symlink), 2);
@@ -176,6 +285,52 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_resolve_prefix_ops() {
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let p = |path: &str| path.replace('/', sep);
+        let rename = |volume: Option<&str>, old: &str, new: &str, seq| PrefixRename {
+            volume: volume.map(str::to_string),
+            old_prefix: p(old),
+            new_prefix: p(new),
+            seq,
+        };
+        let renames = vec![
+            rename(None, "/a", "/b", 100),
+            rename(None, "/b", "/c/d", 200),
+            rename(Some("vol1"), "/x", "/y", 300),
+        ];
+        let tombstones = vec![(None, p("/a/gone"), 50), (None, p("/c/d/dead"), 250)];
+        let resolve = |volume, path: &str, sequence| {
+            resolve_prefix_ops(volume, &p(path), sequence, &tombstones, &renames)
+                .map(|(path, sequence)| (path.into_owned(), sequence))
+        };
+
+        // Chained renames, taking the sequence of the last one
+        assert_eq!(resolve("vol", "/a/f", 10), Some((p("/c/d/f"), 200)));
+        assert_eq!(resolve("vol", "/a", 10), Some((p("/c/d"), 200)));
+        assert_eq!(resolve("vol", "/b/f", 150), Some((p("/c/d/f"), 200)));
+
+        // Entries written after a rename aren't moved by it
+        assert_eq!(resolve("vol", "/a/f", 100), Some((p("/a/f"), 100)));
+        assert_eq!(resolve("vol", "/ab/f", 10), Some((p("/ab/f"), 10)));
+
+        // Prefixes are matched exactly, ignoring entries differing in case
+        assert_eq!(resolve("vol", "/A/f", 10), Some((p("/A/f"), 10)));
+        assert_eq!(renames[0].revert(&p("/b/f")), Some(p("/a/f")));
+        assert_eq!(renames[0].revert(&p("/B/f")), None);
+
+        // Tombstones apply to the path at the time they were logged
+        assert_eq!(resolve("vol", "/a/gone/f", 10), None);
+        assert_eq!(resolve("vol", "/a/dead", 10), None);
+        assert_eq!(resolve("vol", "/c/d/dead", 210), None);
+        assert_eq!(resolve("vol", "/b/gone", 60), Some((p("/c/d/gone"), 200)));
+
+        // Volume specific renames
+        assert_eq!(resolve("vol1", "/x/f", 10), Some((p("/y/f"), 300)));
+        assert_eq!(resolve("vol2", "/x/f", 10), Some((p("/x/f"), 10)));
+    }
+
     #[test]
     #[cfg(windows)]
     fn test_is_tombstoned_windows_paths() {
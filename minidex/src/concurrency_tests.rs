@@ -38,11 +38,9 @@ mod _concurrency_tests {
                                 .insert(FilesystemEntry {
                                     path: PathBuf::from(path),
                                     volume: "vol1".to_string(),
-                                    kind: Kind::File,
                                     last_modified: 100,
                                     last_accessed: 100,
-                                    category: 0,
-                                    volume_type: VolumeType::Local,
+                                    ..Default::default()
                                 })
                                 .unwrap();
                         }
@@ -81,11 +79,9 @@ mod _concurrency_tests {
                         .insert(FilesystemEntry {
                             path: PathBuf::from(format!("/pre_{}.txt", i)),
                             volume: "vol1".to_string(),
-                            kind: Kind::File,
                             last_modified: 100,
                             last_accessed: 100,
-                            category: 0,
-                            volume_type: VolumeType::Local,
+                            ..Default::default()
                         })
                         .unwrap();
                 }
@@ -137,11 +133,9 @@ mod _concurrency_tests {
                             .insert(FilesystemEntry {
                                 path: PathBuf::from(format!("/file_{}.txt", i)),
                                 volume: "vol1".to_string(),
-                                kind: Kind::File,
                                 last_modified: 100,
                                 last_accessed: 100,
-                                category: 0,
-                                volume_type: VolumeType::Local,
+                                ..Default::default()
                             })
                             .unwrap();
                     }
@@ -181,11 +175,9 @@ mod _concurrency_tests {
                             .insert(FilesystemEntry {
                                 path: PathBuf::from(format!("/dir1/file_{}.txt", i)),
                                 volume: "vol1".to_string(),
-                                kind: Kind::File,
                                 last_modified: 100,
                                 last_accessed: 100,
-                                category: 0,
-                                volume_type: VolumeType::Local,
+                                ..Default::default()
                             })
                             .unwrap();
                     }
@@ -214,4 +206,74 @@ mod _concurrency_tests {
             100,
         );
     }
+
+    #[test]
+    fn test_concurrent_rename_touch() {
+        check_random(
+            || {
+                let (_temp, index) = setup_index();
+                let index = Arc::new(index);
+
+                index
+                    .insert(FilesystemEntry {
+                        path: PathBuf::from("/src/report.txt"),
+                        volume: "vol1".to_string(),
+                        last_modified: 100,
+                        last_accessed: 100,
+                        ..Default::default()
+                    })
+                    .unwrap();
+
+                let mut handles = vec![];
+
+                // Renamer
+                let index_r = Arc::clone(&index);
+                handles.push(crate::sync::thread::spawn(move || {
+                    index_r
+                        .rename(
+                            &PathBuf::from("/src/report.txt"),
+                            &PathBuf::from("/dst/report.txt"),
+                        )
+                        .unwrap();
+                }));
+
+                // Toucher rewriting the source path
+                let index_t = Arc::clone(&index);
+                handles.push(crate::sync::thread::spawn(move || {
+                    index_t
+                        .insert(FilesystemEntry {
+                            path: PathBuf::from("/src/report.txt"),
+                            volume: "vol1".to_string(),
+                            last_modified: 200,
+                            last_accessed: 200,
+                            ..Default::default()
+                        })
+                        .unwrap();
+                }));
+
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+
+                // Either the touch landed before the rename and moved with it,
+                // or it recreated the source after the rename.
+                let results = index
+                    .search("report", 10, 0, SearchOptions::default())
+                    .unwrap();
+                let moved = results
+                    .iter()
+                    .find(|r| r.path == PathBuf::from("/dst/report.txt"))
+                    .expect("renamed entry is missing");
+                let recreated = results
+                    .iter()
+                    .any(|r| r.path == PathBuf::from("/src/report.txt"));
+                if recreated {
+                    assert_eq!(moved.last_modified, 100);
+                } else {
+                    assert_eq!(moved.last_modified, 200);
+                }
+            },
+            100,
+        );
+    }
 }
@@ -1,12 +1,11 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 use crate::{
     common::{Kind, VolumeType},
     opstamp::Opstamp,
 };
 
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct IndexEntry {
     pub opstamp: Opstamp,
     pub kind: Kind,
@@ -14,36 +13,255 @@ pub struct IndexEntry {
     pub last_accessed: u64,
     pub category: u8,
     pub volume_type: VolumeType,
+    pub size: u64,
+    pub created: u64,
+    pub tags: Vec<String>,
+    pub content_hash: Option<u128>,
+    pub symlink_target: Option<String>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub mode: Option<u32>,
+    pub hidden: bool,
+    pub file_id: Option<FileId>,
 }
 
+/// Stable identity of a file on its device, surviving renames: the device
+/// and inode numbers on UNIX, or the volume serial number and file index on
+/// Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    pub dev: u64,
+    pub inode: u64,
+}
+
+/// Flags of the ownership and permission fields, marking which are present
+const HAS_UID: u8 = 1;
+const HAS_GID: u8 = 1 << 1;
+const HAS_MODE: u8 = 1 << 2;
+const HIDDEN: u8 = 1 << 3;
+const HAS_FILE_ID: u8 = 1 << 4;
+
 impl IndexEntry {
-    pub const SIZE: usize = std::mem::size_of::<Self>();
+    /// Serialized size of the fixed size fields of an entry
+    pub const FIXED_SIZE: usize = 43;
+    /// Serialized size of the fields every format version carries
+    const CORE_SIZE: usize = 27;
+    /// Serialized size of entries in the first format version, zero padded
+    /// up to the in-memory size of the struct back then
+    pub(crate) const LEGACY_SIZE: usize = 40;
+
+    /// Entry recording the deletion of a path at `seq`. Deletions carry no
+    /// metadata, and shadow older versions of the path by its path alone.
+    pub(crate) fn deletion(seq: u64) -> Self {
+        Self::bare(Opstamp::deletion(seq))
+    }
 
-    pub fn as_bytes(&self) -> [u8; Self::SIZE] {
-        let mut buf = [0u8; Self::SIZE];
+    /// File entry without any metadata
+    fn bare(opstamp: Opstamp) -> Self {
+        Self {
+            opstamp,
+            kind: Kind::File,
+            last_modified: 0,
+            last_accessed: 0,
+            category: 0,
+            volume_type: VolumeType::Local,
+            size: 0,
+            created: 0,
+            tags: Vec::new(),
+            content_hash: None,
+            symlink_target: None,
+            uid: None,
+            gid: None,
+            mode: None,
+            hidden: false,
+            file_id: None,
+        }
+    }
+
+    /// Serialized size of the entry, including its variable size fields
+    pub fn serialized_len(&self) -> usize {
+        Self::FIXED_SIZE
+            + size_of::<u16>()
+            + self
+                .tags
+                .iter()
+                .map(|tag| size_of::<u16>() + tag.len())
+                .sum::<usize>()
+            + size_of::<u8>()
+            + self.content_hash.map_or(0, |_| size_of::<u128>())
+            + size_of::<u16>()
+            + self
+                .symlink_target
+                .as_ref()
+                .map_or(0, |target| target.len())
+            + size_of::<u8>()
+            + [self.uid, self.gid, self.mode].iter().flatten().count() * size_of::<u32>()
+            + self.file_id.map_or(0, |_| 2 * size_of::<u64>())
+    }
+
+    /// Whether the entry is hidden, either explicitly or because it lives
+    /// under a dotfile (a path component starting with `.`)
+    pub fn is_hidden(&self, path: &str) -> bool {
+        self.hidden || is_dotfile_path(path)
+    }
+
+    /// Serializes the entry. Tags are stored as a `u16` count followed by
+    /// `u16` length-prefixed strings, so callers must make sure the entry
+    /// fits (see `serialized_len`). The content hash follows, as a presence
+    /// byte and the hash if present, then the `u16` length-prefixed symlink
+    /// target (empty if there is none), and a flags byte followed by the
+    /// present ownership and permission fields and file identity.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; Self::FIXED_SIZE];
         buf[0..8].copy_from_slice(&self.opstamp.as_bytes());
         buf[8] = self.kind as u8;
         buf[9..17].copy_from_slice(&self.last_modified.to_le_bytes());
         buf[17..25].copy_from_slice(&self.last_accessed.to_le_bytes());
         buf[25] = self.category;
         buf[26] = self.volume_type as u8;
+        buf[27..35].copy_from_slice(&self.size.to_le_bytes());
+        buf[35..43].copy_from_slice(&self.created.to_le_bytes());
+
+        buf.reserve(self.serialized_len() - Self::FIXED_SIZE);
+        buf.extend_from_slice(&(self.tags.len() as u16).to_le_bytes());
+        for tag in &self.tags {
+            buf.extend_from_slice(&(tag.len() as u16).to_le_bytes());
+            buf.extend_from_slice(tag.as_bytes());
+        }
+
+        match self.content_hash {
+            Some(hash) => {
+                buf.push(1);
+                buf.extend_from_slice(&hash.to_le_bytes());
+            }
+            None => buf.push(0),
+        }
+
+        let target = self.symlink_target.as_deref().unwrap_or_default();
+        buf.extend_from_slice(&(target.len() as u16).to_le_bytes());
+        buf.extend_from_slice(target.as_bytes());
+
+        let mut flags = 0;
+        for (field, flag) in [
+            (self.uid, HAS_UID),
+            (self.gid, HAS_GID),
+            (self.mode, HAS_MODE),
+        ] {
+            if field.is_some() {
+                flags |= flag;
+            }
+        }
+        if self.hidden {
+            flags |= HIDDEN;
+        }
+        if self.file_id.is_some() {
+            flags |= HAS_FILE_ID;
+        }
+        buf.push(flags);
+        for value in [self.uid, self.gid, self.mode].into_iter().flatten() {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        if let Some(file_id) = self.file_id {
+            buf.extend_from_slice(&file_id.dev.to_le_bytes());
+            buf.extend_from_slice(&file_id.inode.to_le_bytes());
+        }
         buf
     }
 
-    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
-        Self {
+    /// Parses a serialized entry. Fields are only ever appended to the
+    /// serialized layout, so entries written by older versions are missing
+    /// the newer fields, which default to zero.
+    /// Returns `None` if `bytes` is too short to hold an entry, or if its
+    /// variable size fields are truncated.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::CORE_SIZE {
+            return None;
+        }
+
+        let read_u64 = |offset: usize| {
+            bytes
+                .get(offset..offset + size_of::<u64>())
+                .map_or(0, |b| u64::from_le_bytes(b.try_into().unwrap()))
+        };
+
+        let mut rest = bytes.get(Self::FIXED_SIZE..).unwrap_or_default();
+
+        let mut tags = Vec::new();
+        if let Some(count) = take::<2>(&mut rest).map(u16::from_le_bytes) {
+            tags.reserve(count as usize);
+            for _ in 0..count {
+                let len = u16::from_le_bytes(take(&mut rest)?) as usize;
+                let tag = rest.get(..len)?;
+                tags.push(String::from_utf8_lossy(tag).into_owned());
+                rest = &rest[len..];
+            }
+        }
+
+        let content_hash = match take::<1>(&mut rest) {
+            Some([0]) | None => None,
+            Some(_) => Some(u128::from_le_bytes(take(&mut rest)?)),
+        };
+
+        let symlink_target = match take::<2>(&mut rest).map(u16::from_le_bytes) {
+            Some(0) | None => None,
+            Some(len) => {
+                let target = rest.get(..len as usize)?;
+                rest = &rest[len as usize..];
+                Some(String::from_utf8_lossy(target).into_owned())
+            }
+        };
+
+        let flags = take::<1>(&mut rest).map_or(0, |[flags]| flags);
+        let mut take_field = |flag: u8| -> Option<Option<u32>> {
+            if flags & flag == 0 {
+                return Some(None);
+            }
+            take(&mut rest).map(|bytes| Some(u32::from_le_bytes(bytes)))
+        };
+        let uid = take_field(HAS_UID)?;
+        let gid = take_field(HAS_GID)?;
+        let mode = take_field(HAS_MODE)?;
+        let file_id = if flags & HAS_FILE_ID != 0 {
+            Some(FileId {
+                dev: u64::from_le_bytes(take(&mut rest)?),
+                inode: u64::from_le_bytes(take(&mut rest)?),
+            })
+        } else {
+            None
+        };
+
+        Some(Self {
             opstamp: Opstamp::from_bytes(&bytes[0..8]),
-            kind: Kind::from(bytes[8]),
-            last_modified: u64::from_le_bytes(bytes[9..17].try_into().unwrap()),
-            last_accessed: u64::from_le_bytes(bytes[17..25].try_into().unwrap()),
+            kind: Kind::try_from(bytes[8]).ok()?,
+            last_modified: read_u64(9),
+            last_accessed: read_u64(17),
             category: bytes[25],
             volume_type: VolumeType::from(bytes[26]),
-        }
+            size: read_u64(27),
+            created: read_u64(35),
+            tags,
+            content_hash,
+            symlink_target,
+            uid,
+            gid,
+            mode,
+            hidden: flags & HIDDEN != 0,
+            file_id,
+        })
     }
 }
 
+/// Splits the first `N` bytes off a serialized entry
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (value, rest) = bytes.split_first_chunk::<N>()?;
+    *bytes = rest;
+    Some(*value)
+}
+
 /// A filesystem entry in Minidex, containing information extracted
 /// from files, directories or symlinks by systems populating the index.
+/// Fields a crawler doesn't know about can be left to their defaults.
+#[derive(Debug, Clone, Default)]
 pub struct FilesystemEntry {
     /// Path of the entry
     pub path: PathBuf,
@@ -57,10 +275,191 @@ pub struct FilesystemEntry {
     pub last_modified: u64,
     /// Last accessed timestamp
     pub last_accessed: u64,
+    /// Creation (birth) timestamp (`0` if unknown)
+    pub created: u64,
     /// File category as a u8
     pub category: u8,
     /// Type of volume: Local, Network, Removable or Unknown
     pub volume_type: VolumeType,
+    /// Size in bytes (`0` if unknown, e.g. for directories)
+    pub size: u64,
+    /// User-defined tags (e.g. `starred` or `project:alpha`), matched case
+    /// insensitively by `SearchOptions::tags` and the `tag:` query operator
+    pub tags: Vec<String>,
+    /// Content hash computed by the crawler (e.g. a 128-bit xxhash), used to
+    /// find duplicate files
+    pub content_hash: Option<u128>,
+    /// Target of a symlink, as read from the link (possibly relative to the
+    /// directory containing it)
+    pub symlink_target: Option<PathBuf>,
+    /// Owner user id (POSIX uid), if known
+    pub uid: Option<u32>,
+    /// Owner group id (POSIX gid), if known
+    pub gid: Option<u32>,
+    /// POSIX mode bits (permissions and file type), if known
+    pub mode: Option<u32>,
+    /// Whether the entry is hidden (e.g. by a filesystem attribute). Entries
+    /// under a dotfile are always hidden.
+    pub hidden: bool,
+    /// Stable identity of the file (e.g. device and inode), if known
+    pub file_id: Option<FileId>,
+}
+
+/// A partial update of an entry's metadata, see `Index::update_fields`.
+/// Fields left to `None` keep their current value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryUpdate {
+    /// Last modified timestamp
+    pub last_modified: Option<u64>,
+    /// Last accessed timestamp
+    pub last_accessed: Option<u64>,
+    /// Creation (birth) timestamp
+    pub created: Option<u64>,
+    /// Size in bytes
+    pub size: Option<u64>,
+    /// File category as a u8
+    pub category: Option<u8>,
+    /// Content hash computed by the crawler
+    pub content_hash: Option<u128>,
+}
+
+/// Flags of the updated fields, marking which are present
+const UPDATE_LAST_MODIFIED: u8 = 1;
+const UPDATE_LAST_ACCESSED: u8 = 1 << 1;
+const UPDATE_CREATED: u8 = 1 << 2;
+const UPDATE_SIZE: u8 = 1 << 3;
+const UPDATE_CATEGORY: u8 = 1 << 4;
+const UPDATE_CONTENT_HASH: u8 = 1 << 5;
+
+impl EntryUpdate {
+    /// Whether the update leaves every field unchanged
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Overwrites the updated fields of `entry`
+    pub(crate) fn apply(&self, entry: &mut IndexEntry) {
+        if let Some(last_modified) = self.last_modified {
+            entry.last_modified = last_modified;
+        }
+        if let Some(last_accessed) = self.last_accessed {
+            entry.last_accessed = last_accessed;
+        }
+        if let Some(created) = self.created {
+            entry.created = created;
+        }
+        if let Some(size) = self.size {
+            entry.size = size;
+        }
+        if let Some(category) = self.category {
+            entry.category = category;
+        }
+        if let Some(hash) = self.content_hash {
+            entry.content_hash = Some(hash);
+        }
+    }
+
+    /// Serializes the update as a flags byte followed by the present fields,
+    /// so that touching an entry only takes a few bytes
+    pub(crate) fn as_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        let mut buf = vec![0u8];
+        for (field, flag) in [
+            (self.last_modified, UPDATE_LAST_MODIFIED),
+            (self.last_accessed, UPDATE_LAST_ACCESSED),
+            (self.created, UPDATE_CREATED),
+            (self.size, UPDATE_SIZE),
+        ] {
+            if let Some(value) = field {
+                flags |= flag;
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        if let Some(category) = self.category {
+            flags |= UPDATE_CATEGORY;
+            buf.push(category);
+        }
+        if let Some(hash) = self.content_hash {
+            flags |= UPDATE_CONTENT_HASH;
+            buf.extend_from_slice(&hash.to_le_bytes());
+        }
+        buf[0] = flags;
+        buf
+    }
+
+    /// Parses a serialized update, returning `None` if it is truncated
+    pub(crate) fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let [flags] = take::<1>(&mut bytes)?;
+        let mut take_u64 = |flag: u8| -> Option<Option<u64>> {
+            if flags & flag == 0 {
+                return Some(None);
+            }
+            take(&mut bytes).map(|bytes| Some(u64::from_le_bytes(bytes)))
+        };
+        let last_modified = take_u64(UPDATE_LAST_MODIFIED)?;
+        let last_accessed = take_u64(UPDATE_LAST_ACCESSED)?;
+        let created = take_u64(UPDATE_CREATED)?;
+        let size = take_u64(UPDATE_SIZE)?;
+        let category = match flags & UPDATE_CATEGORY {
+            0 => None,
+            _ => Some(take::<1>(&mut bytes)?[0]),
+        };
+        let content_hash = match flags & UPDATE_CONTENT_HASH {
+            0 => None,
+            _ => Some(u128::from_le_bytes(take(&mut bytes)?)),
+        };
+
+        Some(Self {
+            last_modified,
+            last_accessed,
+            created,
+            size,
+            category,
+            content_hash,
+        })
+    }
+}
+
+/// Whether any component of the path starts with a `.` (`.` and `..`
+/// excluded)
+pub(crate) fn is_dotfile_path(path: &str) -> bool {
+    path.split(['/', '\\'])
+        .any(|component| component.starts_with('.') && component != "." && component != "..")
+}
+
+/// Resolves the target of a symlink against the directory containing it.
+/// Resolution is lexical: `.` and `..` components are folded without
+/// touching the filesystem, so links inside the target are not followed.
+pub(crate) fn resolve_symlink_target(link: &Path, target: &Path) -> PathBuf {
+    let joined = match link.parent() {
+        Some(parent) if target.is_relative() => parent.join(target),
+        _ => target.to_path_buf(),
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+impl IndexEntry {
+    /// File entry last modified and accessed at `time`, without any other
+    /// metadata
+    pub(crate) fn file(opstamp: Opstamp, time: u64) -> Self {
+        Self {
+            last_modified: time,
+            last_accessed: time,
+            ..Self::bare(opstamp)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -70,16 +469,26 @@ mod tests {
     #[test]
     fn test_index_entry_serialization() {
         let entry = IndexEntry {
-            opstamp: Opstamp::insertion(123),
-            kind: Kind::File,
-            last_modified: 456,
             last_accessed: 789,
             category: 0xAB,
-            volume_type: VolumeType::Local,
+            size: 1 << 40,
+            created: 123_000,
+            tags: vec!["starred".to_string(), "project:αlpha".to_string()],
+            content_hash: Some(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef),
+            symlink_target: Some("../shared/lib.so".to_string()),
+            uid: Some(1000),
+            gid: None,
+            mode: Some(0o100755),
+            hidden: true,
+            file_id: Some(FileId {
+                dev: 2049,
+                inode: 1 << 33,
+            }),
+            ..IndexEntry::file(Opstamp::insertion(123), 456)
         };
 
         let bytes = entry.as_bytes();
-        let entry2 = IndexEntry::from_bytes(&bytes);
+        let entry2 = IndexEntry::from_bytes(&bytes).unwrap();
 
         assert_eq!(entry.opstamp.sequence(), entry2.opstamp.sequence());
         assert_eq!(entry.kind, entry2.kind);
@@ -87,24 +496,126 @@ mod tests {
         assert_eq!(entry.last_accessed, entry2.last_accessed);
         assert_eq!(entry.category, entry2.category);
         assert_eq!(entry.volume_type, entry2.volume_type);
+        assert_eq!(entry.size, entry2.size);
+        assert_eq!(entry.created, entry2.created);
+        assert_eq!(entry.tags, entry2.tags);
+        assert_eq!(entry.content_hash, entry2.content_hash);
+        assert_eq!(entry.symlink_target, entry2.symlink_target);
+        assert_eq!(entry.uid, entry2.uid);
+        assert_eq!(entry.gid, entry2.gid);
+        assert_eq!(entry.mode, entry2.mode);
+        assert!(entry2.hidden);
+        assert_eq!(entry.file_id, entry2.file_id);
+        assert_eq!(bytes.len(), entry.serialized_len());
+
+        // Truncated tags are rejected
+        assert!(IndexEntry::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+
+        // And so are unknown kinds
+        let mut unknown_kind = bytes.clone();
+        unknown_kind[8] = 0xFF;
+        assert!(IndexEntry::from_bytes(&unknown_kind).is_none());
     }
 
     #[test]
     fn test_index_entry_deletion_serialization() {
         let entry = IndexEntry {
-            opstamp: Opstamp::deletion(123),
-            kind: Kind::File,
-            last_modified: 0,
-            last_accessed: 0,
-            category: 0,
             volume_type: VolumeType::Unknown,
+            ..IndexEntry::file(Opstamp::deletion(123), 0)
         };
 
         let bytes = entry.as_bytes();
-        let entry2 = IndexEntry::from_bytes(&bytes);
+        let entry2 = IndexEntry::from_bytes(&bytes).unwrap();
 
         assert!(entry2.opstamp.is_deletion());
         assert_eq!(entry2.opstamp.sequence(), 123);
         assert_eq!(entry2.volume_type, VolumeType::Unknown);
     }
+
+    #[test]
+    fn test_entry_update_serialization() {
+        let touch = EntryUpdate {
+            last_accessed: Some(789),
+            ..Default::default()
+        };
+        let bytes = touch.as_bytes();
+        assert_eq!(bytes.len(), 1 + size_of::<u64>());
+        assert_eq!(EntryUpdate::from_bytes(&bytes), Some(touch));
+
+        let update = EntryUpdate {
+            last_modified: Some(1),
+            last_accessed: None,
+            created: Some(3),
+            size: Some(1 << 40),
+            category: Some(0xAB),
+            content_hash: Some(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef),
+        };
+        let bytes = update.as_bytes();
+        assert_eq!(EntryUpdate::from_bytes(&bytes), Some(update.clone()));
+        assert!(EntryUpdate::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+
+        let mut entry = IndexEntry {
+            last_accessed: 456,
+            tags: vec!["starred".to_string()],
+            ..IndexEntry::file(Opstamp::insertion(123), 0)
+        };
+        update.apply(&mut entry);
+        assert_eq!(entry.last_modified, 1);
+        assert_eq!(entry.last_accessed, 456);
+        assert_eq!(entry.created, 3);
+        assert_eq!(entry.size, 1 << 40);
+        assert_eq!(entry.category, 0xAB);
+        assert_eq!(entry.content_hash, update.content_hash);
+        assert_eq!(entry.tags, vec!["starred".to_string()]);
+        assert!(EntryUpdate::default().is_empty());
+    }
+
+    #[test]
+    fn test_index_entry_legacy_deserialization() {
+        let mut bytes = [0u8; IndexEntry::LEGACY_SIZE];
+        bytes[..IndexEntry::CORE_SIZE].copy_from_slice(
+            &IndexEntry {
+                kind: Kind::Directory,
+                last_accessed: 789,
+                category: 1,
+                volume_type: VolumeType::Network,
+                ..IndexEntry::file(Opstamp::insertion(7), 456)
+            }
+            .as_bytes()[..IndexEntry::CORE_SIZE],
+        );
+
+        let entry = IndexEntry::from_bytes(&bytes).unwrap();
+        assert_eq!(entry.opstamp.sequence(), 7);
+        assert_eq!(entry.kind, Kind::Directory);
+        assert_eq!(entry.last_accessed, 789);
+        assert_eq!(entry.volume_type, VolumeType::Network);
+        assert_eq!(entry.size, 0);
+        assert_eq!(entry.created, 0);
+        assert!(entry.tags.is_empty());
+        assert_eq!(entry.content_hash, None);
+        assert_eq!(entry.symlink_target, None);
+        assert_eq!((entry.uid, entry.gid, entry.mode), (None, None, None));
+        assert!(!entry.hidden);
+        assert_eq!(entry.file_id, None);
+
+        assert!(IndexEntry::from_bytes(&bytes[..IndexEntry::CORE_SIZE - 1]).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_symlink_target() {
+        let link = Path::new("/home/me/bin/tool");
+        assert_eq!(
+            resolve_symlink_target(link, Path::new("../opt/./tool-1.2")),
+            PathBuf::from("/home/me/opt/tool-1.2")
+        );
+        assert_eq!(
+            resolve_symlink_target(link, Path::new("/usr/bin/../lib/tool")),
+            PathBuf::from("/usr/lib/tool")
+        );
+        assert_eq!(
+            resolve_symlink_target(link, Path::new("../../../../..")),
+            PathBuf::from("/")
+        );
+    }
 }
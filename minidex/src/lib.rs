@@ -1,12 +1,13 @@
 use std::{
-    collections::BTreeMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
     ops::Bound,
     path::{Path, PathBuf},
 };
 
 mod sync;
 use crate::sync::{
-    Arc, RwLock,
+    Arc, Mutex, RwLock, RwLockWriteGuard,
     atomic::{AtomicU64, Ordering},
     thread::{JoinHandle, JoinHandleExt},
 };
@@ -15,30 +16,41 @@ use arc_swap::ArcSwap;
 use fst::{Automaton as _, IntoStreamer as _, Streamer, automaton::Str};
 
 use memtable::MemTable;
-use search::evaluate_candidate;
+use query::{Alternatives, Query};
+use search::{
+    CandidateFilter, FuzzyAutomaton, PathPattern, Recency, evaluate_candidate, narrow_candidates,
+};
 use thiserror::Error;
 
+pub mod category;
 mod collector;
 mod common;
-use common::is_tombstoned;
+use common::{PrefixRename, insert_prefix_rename, resolve_prefix_ops};
+mod crc32c;
 mod leb128;
+pub use category::CategoryClassifier;
 use collector::*;
-pub use common::{Kind, VolumeType, category};
+pub use common::{Kind, VolumeType};
 mod entry;
-pub use entry::{FilesystemEntry, IndexEntry};
+pub use entry::{EntryUpdate, FileId, FilesystemEntry, IndexEntry};
 mod memtable;
 pub mod segmented_index;
 pub use segmented_index::compactor::*;
 use segmented_index::*;
 pub mod opstamp;
 use opstamp::*;
+mod query;
+pub use query::QueryError;
 use wal::Wal;
 mod search;
 mod simd;
 mod tokenizer;
 pub use tokenizer::tokenize;
 mod wal;
-pub use search::{ScoringConfig, ScoringInputs, ScoringWeights, SearchOptions, SearchResult};
+pub use search::{
+    DuplicateGroup, MAX_FUZZINESS, ScoringConfig, ScoringInputs, ScoringWeights, SearchOptions,
+    SearchResult, SortOrder,
+};
 
 pub type Tombstone = (Option<String>, String, u64);
 
@@ -55,7 +67,12 @@ pub struct Index {
     compactor: Arc<RwLock<Option<JoinHandle<()>>>>,
     flusher: Arc<RwLock<Option<JoinHandle<()>>>>,
     prefix_tombstones: Arc<RwLock<Arc<Vec<Tombstone>>>>,
+    prefix_renames: Arc<RwLock<Arc<Vec<PrefixRename>>>>,
     recovery: Arc<RwLock<Option<JoinHandle<()>>>>,
+    /// Serializes manifest writes, so that the last one records the latest
+    /// state
+    manifest_lock: Arc<Mutex<()>>,
+    classifier: Option<CategoryClassifier>,
 }
 
 impl Index {
@@ -63,7 +80,7 @@ impl Index {
     /// This function will:
     /// 1. Create (if it doesn't exist) the directory at `path`
     /// 2. Try to obtain a lock on the directory
-    /// 3. Load the discovered segments, data and posting
+    /// 3. Load the segments listed in its manifest, data and posting
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IndexError> {
         Self::open_with_config(path, CompactorConfig::default())
     }
@@ -72,38 +89,57 @@ impl Index {
     /// This function will:
     /// 1. Create (if it doesn't exist) the directory at `path`
     /// 2. Try to obtain a lock on the directory
-    /// 3. Load the discovered segments, data and posting
+    /// 3. Load the segments listed in its manifest, data and posting
     pub fn open_with_config<P: AsRef<Path>>(
         path: P,
         compactor_config: CompactorConfig,
     ) -> Result<Self, IndexError> {
-        let base = SegmentedIndex::open(&path).map_err(IndexError::SegmentedIndex)?;
+        let (base, manifest) =
+            SegmentedIndex::open_with_manifest(&path).map_err(IndexError::SegmentedIndex)?;
         let base = Arc::new(ArcSwap::from_pointee(base));
 
         let mem_idx = MemTable::default();
-        let prefix_tombstones = Arc::new(RwLock::new(Arc::new(Vec::new())));
+        let prefix_tombstones = Arc::new(RwLock::new(Arc::new(manifest.tombstones)));
+        let prefix_renames = Arc::new(RwLock::new(Arc::new(manifest.renames)));
+        let manifest_lock = Arc::new(Mutex::new(()));
 
         let entries = path.as_ref().read_dir().map_err(IndexError::Io)?;
         let mut frozen_wals = Vec::new();
         let mut max_mtime = 0u64;
 
-        for entry in entries {
-            if let Ok(e) = entry
-                && let Ok(file_type) = e.file_type()
+        // Listed up front, as freezing WALs adds files to the directory
+        let entries: Vec<_> = entries.flatten().collect();
+
+        for e in entries {
+            if let Ok(file_type) = e.file_type()
                 && file_type.is_file()
             {
                 let file_name = e.file_name().to_string_lossy().into_owned();
                 let is_journal = file_name == "journal.wal";
                 let is_flushing = file_name.ends_with(".flushing.wal");
+                // Left by a recovery that failed or was interrupted
+                let is_frozen = file_name.ends_with(".frozen");
 
-                if is_journal || is_flushing {
-                    let frozen_name = format!("{}.frozen", file_name);
-                    let frozen_path = path.as_ref().join(&frozen_name);
+                if is_journal || is_flushing || is_frozen {
+                    let frozen_path = if is_frozen {
+                        e.path()
+                    } else {
+                        // Frozen WALs kept from an earlier recovery are never
+                        // overwritten
+                        let mut frozen_path = path.as_ref().join(format!("{}.frozen", file_name));
+                        let mut attempt = 1;
+                        while frozen_path.exists() {
+                            frozen_path = path
+                                .as_ref()
+                                .join(format!("{}.{}.frozen", file_name, attempt));
+                            attempt += 1;
+                        }
 
-                    std::fs::rename(e.path(), &frozen_path).map_err(IndexError::Io)?;
+                        std::fs::rename(e.path(), &frozen_path).map_err(IndexError::Io)?;
+                        frozen_path
+                    };
 
-                    let mtime = e
-                        .metadata()
+                    let mtime = std::fs::metadata(&frozen_path)
                         .and_then(|m| m.modified())
                         .unwrap_or(crate::sync::time::UNIX_EPOCH)
                         .duration_since(crate::sync::time::UNIX_EPOCH)
@@ -121,10 +157,24 @@ impl Index {
             .unwrap()
             .as_micros() as u64;
 
-        // Safety buffer: Start 1 second ahead of the last known disk write
-        let safe_seq = std::cmp::max(current_micros, max_mtime + 1_000_000);
+        // Safety buffer: Start 1 second ahead of the last known disk write,
+        // and never below the recorded high-water mark
+        let safe_seq =
+            std::cmp::max(current_micros, max_mtime + 1_000_000).max(manifest.next_op_seq);
         let next_op_seq = Arc::new(AtomicU64::new(safe_seq));
 
+        // Record the high-water mark, and the segments of indexes written
+        // before manifests existed
+        Self::store_manifest(
+            path.as_ref(),
+            &base,
+            &prefix_tombstones,
+            &prefix_renames,
+            &next_op_seq,
+            &manifest_lock,
+        )
+        .map_err(IndexError::Io)?;
+
         let wal_path = path.as_ref().join("journal.wal");
         let wal = Wal::open(&wal_path).map_err(IndexError::Io)?;
 
@@ -132,6 +182,9 @@ impl Index {
             let recovery_base = Arc::clone(&base);
             let recovery_path = path.as_ref().to_path_buf();
             let prefix_tombstones = Arc::clone(&prefix_tombstones);
+            let prefix_renames = Arc::clone(&prefix_renames);
+            let next_op_seq = Arc::clone(&next_op_seq);
+            let manifest_lock = Arc::clone(&manifest_lock);
 
             let handle = crate::sync::thread::Builder::new()
                 .name("minidex-recovery".to_owned())
@@ -142,6 +195,9 @@ impl Index {
                         frozen_wals,
                         recovery_base,
                         prefix_tombstones,
+                        prefix_renames,
+                        next_op_seq,
+                        manifest_lock,
                     );
                 })
                 .map_err(IndexError::Io)?;
@@ -161,12 +217,42 @@ impl Index {
             compactor: Arc::new(RwLock::new(None)),
             flusher: Arc::new(RwLock::new(None)),
             prefix_tombstones,
+            prefix_renames,
             recovery: Arc::new(RwLock::new(recovery)),
+            manifest_lock,
+            classifier: None,
         };
 
         Ok(index)
     }
 
+    /// Assigns categories with `classifier` to inserted entries left to
+    /// `category::OTHER` (directories excepted), and accepts its custom
+    /// categories in `cat:` queries. If the classifier sniffs content, the
+    /// first bytes of inserted files are read from their path.
+    pub fn with_classifier(mut self, classifier: CategoryClassifier) -> Self {
+        self.classifier = Some(classifier);
+        self
+    }
+
+    /// Category of an inserted entry, classified from its path if left unset
+    fn category_of(&self, item: &FilesystemEntry) -> u8 {
+        match &self.classifier {
+            Some(classifier) if item.category == category::OTHER => match item.kind {
+                Kind::File => classifier.classify_file(&item.path),
+                Kind::Symlink => classifier.classify(&item.path),
+                Kind::Directory => category::OTHER,
+            },
+            _ => item.category,
+        }
+    }
+
+    /// Segments found corrupt when opening the index. They are left out of
+    /// the index and moved to its `quarantine` directory for inspection.
+    pub fn quarantined_segments(&self) -> Vec<PathBuf> {
+        self.base.load().quarantined().to_vec()
+    }
+
     pub fn wait_for_completed_recovery(&self) {
         if let Ok(mut lock) = self.recovery.write()
             && let Some(handle) = lock.take()
@@ -181,6 +267,9 @@ impl Index {
         frozen_wals: Vec<PathBuf>,
         base: Arc<ArcSwap<SegmentedIndex>>,
         live_tombstones: Arc<RwLock<Arc<Vec<Tombstone>>>>,
+        live_renames: Arc<RwLock<Arc<Vec<PrefixRename>>>>,
+        next_op_seq: Arc<AtomicU64>,
+        manifest_lock: Arc<Mutex<()>>,
     ) {
         log::info!(
             "Starting background WAL recovery for {} files...",
@@ -189,17 +278,32 @@ impl Index {
 
         let mut local_mem = MemTable::default();
         let mut recovered_tombstones = Vec::new();
+        let mut recovered_renames = Vec::new();
+        let mut recovered_updates = Vec::new();
+        let mut unreadable_wals = Vec::new();
 
         for wal_path in &frozen_wals {
             match Wal::replay(wal_path) {
                 Ok(partial) => {
+                    if partial.dropped_bytes > 0 {
+                        log::warn!(
+                            "Dropped {} bytes of corrupt records at the end of WAL {:?}",
+                            partial.dropped_bytes,
+                            wal_path
+                        );
+                    }
                     for (p, v, e) in partial.inserts {
-                        let tokens = crate::tokenizer::extract_all_tokens(&p, &v);
+                        let tokens = crate::tokenizer::extract_all_tokens(&p, &v, &e);
                         local_mem.insert_with_tokens(p, v, e, tokens);
                     }
                     recovered_tombstones.extend(partial.tombstones);
+                    recovered_renames.extend(partial.renames);
+                    recovered_updates.extend(partial.updates);
+                }
+                Err(e) => {
+                    log::error!("Failed to replay WAL {:?}: {}", wal_path, e);
+                    unreadable_wals.push(wal_path.clone());
                 }
-                Err(e) => log::error!("Failed to replay WAL {:?}: {}", wal_path, e),
             }
         }
 
@@ -207,7 +311,53 @@ impl Index {
             let mut guard = live_tombstones
                 .write()
                 .expect("failed to acquire tombstone write lock");
-            Arc::make_mut(&mut guard).extend(recovered_tombstones);
+            // WALs re-log the tombstones they were rotated with, which the
+            // manifest may list already
+            let tombstones = Arc::make_mut(&mut guard);
+            for tombstone in recovered_tombstones {
+                if !tombstones.contains(&tombstone) {
+                    tombstones.push(tombstone);
+                }
+            }
+        }
+
+        if !recovered_renames.is_empty() {
+            let mut guard = live_renames
+                .write()
+                .expect("failed to acquire rename write lock");
+            for rename in recovered_renames {
+                insert_prefix_rename(Arc::make_mut(&mut guard), rename);
+            }
+        }
+
+        // Updates are merged into the version of the entry they were logged
+        // after, whether it was recovered above or is on disk already
+        recovered_updates.sort_by_key(|(_, seq, _)| *seq);
+        if !recovered_updates.is_empty() {
+            let segments = base.load();
+            let tombstones = live_tombstones
+                .read()
+                .expect("failed to acquire tombstone read lock")
+                .clone();
+            let renames = live_renames
+                .read()
+                .expect("failed to acquire rename read lock")
+                .clone();
+
+            for (path, seq, update) in recovered_updates {
+                // Operations made since opening the index came later
+                let tombstones: Vec<_> = tombstones.iter().filter(|t| t.2 < seq).cloned().collect();
+                let renames: Vec<_> = renames.iter().filter(|r| r.seq < seq).cloned().collect();
+
+                match Self::find_entry(&segments, &local_mem, &tombstones, &renames, &path) {
+                    Some((volume, mut entry)) if entry.opstamp.sequence() < seq => {
+                        update.apply(&mut entry);
+                        entry.opstamp = Opstamp::insertion(seq);
+                        local_mem.insert(path, volume, entry);
+                    }
+                    _ => log::debug!("Dropping update of {}, superseded or not found", path),
+                }
+            }
         }
 
         // We compile the WALs directly to a disk segment since we are cleanly split
@@ -223,6 +373,9 @@ impl Index {
 
             let snapshot = local_mem;
 
+            // The WALs are kept for the next open unless their data is in a
+            // loaded segment
+            let tmp_paths = Segment::paths_with_additional_extension(&tmp_segment_path);
             if let Err(e) = SegmentedIndex::build_segment_files(
                 &tmp_segment_path,
                 snapshot.entries.into_iter().map(|(p, (v, e))| (p, v, e)),
@@ -230,51 +383,129 @@ impl Index {
                 None,
             ) {
                 log::error!("Background recovery failed to write segment: {}", e);
-                let tmp_paths = Segment::paths_with_additional_extension(&tmp_segment_path);
                 Segment::remove_files(&tmp_paths);
-            } else {
-                let tmp_paths = Segment::paths_with_additional_extension(&tmp_segment_path);
-                let final_paths = Segment::paths_with_additional_extension(&final_segment_path);
+                return;
+            }
 
-                if let Err(e) = Segment::rename_files(&tmp_paths, &final_paths) {
-                    log::error!("Background recovery failed to rename segment files: {}", e);
-                    Segment::remove_files(&tmp_paths);
-                } else if let Ok(new_segment) = Segment::load(final_segment_path) {
-                    let new_segment = Arc::new(new_segment);
-                    base.rcu(|b| {
-                        let mut next = (**b).clone();
-                        next.add_segment(Arc::clone(&new_segment));
-                        next
-                    });
-                    log::info!("Successfully recovered WAL data to SSD Segment.");
-                }
+            let final_paths = Segment::paths_with_additional_extension(&final_segment_path);
+            if let Err(e) = Segment::rename_files(&tmp_paths, &final_paths) {
+                log::error!("Background recovery failed to rename segment files: {}", e);
+                Segment::remove_files(&tmp_paths);
+                return;
             }
+
+            let new_segment = match Segment::load(final_segment_path) {
+                Ok(segment) => Arc::new(segment),
+                Err(e) => {
+                    log::error!("Background recovery failed to load segment: {}", e);
+                    Segment::remove_files(&final_paths);
+                    return;
+                }
+            };
+            base.rcu(|b| {
+                let mut next = (**b).clone();
+                next.add_segment(Arc::clone(&new_segment));
+                next
+            });
+            log::info!("Successfully recovered WAL data to SSD Segment.");
+        }
+
+        // The WALs can only go once the manifest lists what they held
+        if let Err(e) = Self::store_manifest(
+            &path,
+            &base,
+            &live_tombstones,
+            &live_renames,
+            &next_op_seq,
+            &manifest_lock,
+        ) {
+            log::error!("Background recovery failed to store the manifest: {}", e);
+            return;
         }
 
+        // Unreadable WALs are left for the next open to retry
         for wal_path in frozen_wals {
-            let _ = std::fs::remove_file(wal_path);
+            if !unreadable_wals.contains(&wal_path) {
+                let _ = std::fs::remove_file(wal_path);
+            }
         }
     }
 
+    /// Records the live segments, prefix tombstones, renames and opstamp
+    /// high-water mark in the manifest. Called after every change to the
+    /// segment set, before removing the WALs or segments it replaces.
+    fn store_manifest(
+        path: &Path,
+        base: &ArcSwap<SegmentedIndex>,
+        prefix_tombstones: &RwLock<Arc<Vec<Tombstone>>>,
+        prefix_renames: &RwLock<Arc<Vec<PrefixRename>>>,
+        next_op_seq: &AtomicU64,
+        manifest_lock: &Mutex<()>,
+    ) -> std::io::Result<()> {
+        let _guard = manifest_lock.lock().expect("manifest lock poisoned");
+
+        Manifest {
+            segments: base
+                .load()
+                .segments()
+                .map(|segment| segment.name())
+                .collect(),
+            tombstones: prefix_tombstones
+                .read()
+                .expect("prefix_tombstones lock poisoned")
+                .to_vec(),
+            renames: prefix_renames
+                .read()
+                .expect("prefix_renames lock poisoned")
+                .to_vec(),
+            next_op_seq: next_op_seq.load(Ordering::SeqCst),
+        }
+        .store(path)
+    }
+
     fn next_op_seq(&self) -> u64 {
         self.next_op_seq.fetch_add(1, Ordering::SeqCst)
     }
 
-    /// Insert a filesystem entry into the index.
-    pub fn insert(&self, item: FilesystemEntry) -> Result<(), IndexError> {
-        self.apply_backpressure()?;
-
-        let seq = self.next_op_seq();
-        let path_str = item.path.to_string_lossy().to_string();
-        let volume = item.volume;
+    /// Converts a filesystem entry into the path, volume and entry to index,
+    /// ordered as the next operation
+    fn prepare_entry(
+        &self,
+        item: FilesystemEntry,
+    ) -> Result<(String, String, IndexEntry), IndexError> {
+        let category = self.category_of(&item);
         let entry = IndexEntry {
-            opstamp: Opstamp::insertion(seq),
+            opstamp: Opstamp::insertion(self.next_op_seq()),
             kind: item.kind,
             last_modified: item.last_modified,
             last_accessed: item.last_accessed,
-            category: item.category,
+            category,
             volume_type: item.volume_type,
+            size: item.size,
+            created: item.created,
+            tags: item.tags,
+            content_hash: item.content_hash,
+            symlink_target: item
+                .symlink_target
+                .map(|target| target.to_string_lossy().into_owned()),
+            uid: item.uid,
+            gid: item.gid,
+            mode: item.mode,
+            hidden: item.hidden,
+            file_id: item.file_id,
         };
+        if entry.serialized_len() > u16::MAX as usize {
+            return Err(IndexError::EntryTooLarge(item.path));
+        }
+
+        Ok((item.path.to_string_lossy().into_owned(), item.volume, entry))
+    }
+
+    /// Insert a filesystem entry into the index.
+    pub fn insert(&self, item: FilesystemEntry) -> Result<(), IndexError> {
+        self.apply_backpressure()?;
+
+        let (path_str, volume, entry) = self.prepare_entry(item)?;
 
         {
             let mut wal = self.wal.write().map_err(|_| IndexError::WriteLock)?;
@@ -283,7 +514,7 @@ impl Index {
         }
 
         {
-            let tokens = crate::tokenizer::extract_all_tokens(&path_str, &volume);
+            let tokens = crate::tokenizer::extract_all_tokens(&path_str, &volume, &entry);
             self.mem_idx
                 .write()
                 .map_err(|_| IndexError::WriteLock)?
@@ -313,24 +544,11 @@ impl Index {
                 .by_ref()
                 .take(chunk_size)
                 .map(|item| {
-                    let seq = self.next_op_seq();
-                    let path_str = item.path.to_string_lossy().into_owned();
-                    let tokens = crate::tokenizer::extract_all_tokens(&path_str, &item.volume);
-                    (
-                        path_str,
-                        item.volume,
-                        IndexEntry {
-                            opstamp: Opstamp::insertion(seq),
-                            kind: item.kind,
-                            last_modified: item.last_modified,
-                            last_accessed: item.last_accessed,
-                            category: item.category,
-                            volume_type: item.volume_type,
-                        },
-                        tokens,
-                    )
+                    let (path_str, volume, entry) = self.prepare_entry(item)?;
+                    let tokens = crate::tokenizer::extract_all_tokens(&path_str, &volume, &entry);
+                    Ok((path_str, volume, entry, tokens))
                 })
-                .collect();
+                .collect::<Result<_, IndexError>>()?;
 
             if chunk.is_empty() {
                 break;
@@ -361,38 +579,430 @@ impl Index {
         Ok(())
     }
 
+    /// Deletes the entry at `item`. The deletion only records the path, and
+    /// searches look it up by path to shadow older versions of the entry.
     pub fn delete(&self, item: &Path) -> Result<(), IndexError> {
-        let seq = self.next_op_seq();
-
         let path_str = item.to_string_lossy().to_string();
-        let entry = IndexEntry {
-            opstamp: Opstamp::deletion(seq),
-            kind: Kind::File,
-            last_modified: 0,
-            last_accessed: 0,
-            category: 0,
-            volume_type: common::VolumeType::Local,
-        };
 
         {
             let mut wal = self.wal.write().map_err(|_| IndexError::WriteLock)?;
+            let mut mem = self.mem_idx.write().map_err(|_| IndexError::WriteLock)?;
+
+            let entry = IndexEntry::deletion(self.next_op_seq());
             wal.append(&path_str, "", &entry).map_err(IndexError::Io)?;
+            mem.insert(path_str, "".to_owned(), entry);
+        }
+
+        if self.should_flush() {
+            let _ = self.trigger_flush();
         }
 
+        Ok(())
+    }
+
+    /// Moves the entry at `old` to `new`, as reported by a filesystem watcher,
+    /// keeping its tags, timestamps and other metadata. The move is logged as a
+    /// deletion of `old` followed by an insertion at `new`, so it is ordered
+    /// after any earlier operation on either path.
+    /// Only the entry itself is moved: entries under a renamed directory keep
+    /// their paths. Returns `false` if there is no live entry at `old`.
+    pub fn rename(&self, old: &Path, new: &Path) -> Result<bool, IndexError> {
+        let old_str = old.to_string_lossy().to_string();
+        let new_str = new.to_string_lossy().to_string();
+
+        self.apply_backpressure()?;
+
         {
-            self.mem_idx
-                .write()
-                .map_err(|_| IndexError::WriteLock)?
-                .insert(path_str, "".to_owned(), entry);
+            let LatestEntry {
+                mut wal,
+                mut mem,
+                latest,
+            } = self.lock_latest(&old_str)?;
+
+            let Some((volume, entry)) = latest else {
+                return Ok(false);
+            };
+            if old_str == new_str {
+                return Ok(true);
+            }
+
+            let deletion = IndexEntry::deletion(self.next_op_seq());
+            let moved = IndexEntry {
+                opstamp: Opstamp::insertion(self.next_op_seq()),
+                ..entry
+            };
+
+            wal.append(&old_str, "", &deletion)?;
+            wal.append(&new_str, &volume, &moved)?;
+
+            mem.insert(old_str, "".to_owned(), deletion);
+            mem.insert(new_str, volume, moved);
         }
 
         if self.should_flush() {
             let _ = self.trigger_flush();
         }
 
+        Ok(true)
+    }
+
+    /// Finds the live entries with the given file identity, e.g. to pair a
+    /// watcher's deletion of one path with the creation of another before
+    /// calling `rename`. Hard links share their identity, so there can be
+    /// several, ordered by path.
+    pub fn find_by_file_id(&self, file_id: FileId) -> Result<Vec<SearchResult>, IndexError> {
+        let segments = self.base.load();
+        let mem = self.mem_idx.read().map_err(|_| IndexError::ReadLock)?;
+
+        let active_tombstones = {
+            self.prefix_tombstones
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
+        let active_renames = {
+            self.prefix_renames
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
+
+        let token = crate::tokenizer::file_id_token(file_id);
+        let mut collector = LsmCollector::new(&active_tombstones, &active_renames);
+
+        for id in mem.inverted_index.get(&token).into_iter().flatten() {
+            if let Some((path, volume, entry)) = mem.id_to_data.get(id) {
+                collector.insert(path.as_str(), volume.as_str(), entry.clone());
+            }
+        }
+
+        for segment in segments.segments() {
+            let mut doc_ids = Vec::new();
+            if let Some(post_offset) = segment.as_ref().as_ref().get(&token) {
+                segment.append_posting_list(post_offset, &mut doc_ids);
+            }
+            for doc_id in doc_ids {
+                let Some(metadata) = segment.doc_meta(doc_id) else {
+                    continue;
+                };
+                if let Some((path, volume, entry)) = segment.read_document(metadata.dat_offset) {
+                    collector.insert(path, volume, entry);
+                }
+            }
+        }
+
+        // Only versions carrying the identity were collected, so a newer
+        // version of the same path (e.g. a file replaced by another) is
+        // checked by resolving the path again
+        let mut results = Vec::new();
+        for (path, _, _) in collector.finish() {
+            let Some((volume, entry)) =
+                Self::find_entry(&segments, &mem, &active_tombstones, &active_renames, &path)
+            else {
+                continue;
+            };
+            if entry.file_id != Some(file_id) {
+                continue;
+            }
+
+            results.push(SearchResult {
+                path: PathBuf::from(path.as_ref()),
+                hidden: entry.is_hidden(&path),
+                volume,
+                volume_type: entry.volume_type,
+                kind: entry.kind,
+                last_modified: entry.last_modified,
+                last_accessed: entry.last_accessed,
+                created: entry.created,
+                category: entry.category,
+                size: entry.size,
+                tags: entry.tags,
+                content_hash: entry.content_hash,
+                symlink_target: entry.symlink_target.map(PathBuf::from),
+                uid: entry.uid,
+                gid: entry.gid,
+                mode: entry.mode,
+                file_id: entry.file_id,
+                score: 0.0,
+            });
+        }
+
+        results.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(results)
+    }
+
+    /// Finds the latest live version of the entry at `path` and takes the WAL
+    /// and memtable write locks, so that a write derived from it is ordered
+    /// right after it. Segments are read before taking the locks, and only
+    /// read again under them if the segments or the prefix operations changed
+    /// in the meantime.
+    fn lock_latest(&self, path: &str) -> Result<LatestEntry<'_>, IndexError> {
+        let stamp = self.resolution_stamp()?;
+        let mut versions = Self::segment_versions(&stamp.segments, &stamp.renames, path);
+
+        let wal = self.wal.write().map_err(|_| IndexError::WriteLock)?;
+        let mem = self.mem_idx.write().map_err(|_| IndexError::WriteLock)?;
+
+        let current = self.resolution_stamp()?;
+        if !current.matches(&stamp) {
+            versions = Self::segment_versions(&current.segments, &current.renames, path);
+        }
+        let latest = Self::latest_of(versions, &mem, &current.tombstones, &current.renames, path);
+
+        Ok(LatestEntry { wal, mem, latest })
+    }
+
+    fn resolution_stamp(&self) -> Result<ResolutionStamp, IndexError> {
+        Ok(ResolutionStamp {
+            segments: self.base.load_full(),
+            tombstones: self
+                .prefix_tombstones
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone(),
+            renames: self
+                .prefix_renames
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone(),
+        })
+    }
+
+    /// Finds the latest live version of the entry at `path` in the given
+    /// segments and memtable
+    fn find_entry(
+        segments: &SegmentedIndex,
+        mem: &MemTable,
+        active_tombstones: &[Tombstone],
+        active_renames: &[PrefixRename],
+        path: &str,
+    ) -> Option<(String, IndexEntry)> {
+        let versions = Self::segment_versions(segments, active_renames, path);
+        Self::latest_of(versions, mem, active_tombstones, active_renames, path)
+    }
+
+    /// Finds the latest live version of the entry at `path` among its
+    /// versions in the segments and those in the memtable
+    fn latest_of(
+        versions: Vec<(String, String, IndexEntry)>,
+        mem: &MemTable,
+        active_tombstones: &[Tombstone],
+        active_renames: &[PrefixRename],
+        path: &str,
+    ) -> Option<(String, IndexEntry)> {
+        let mut collector = LsmCollector::new(active_tombstones, active_renames);
+        for (doc_path, volume, entry) in versions {
+            collector.insert(doc_path, volume, entry);
+        }
+
+        for origin in path_origins(active_renames, path) {
+            if let Some((_, volume, entry)) = mem
+                .path_to_id
+                .get(&origin)
+                .and_then(|id| mem.id_to_data.get(id))
+            {
+                collector.insert(origin, volume.clone(), entry.clone());
+            }
+        }
+
+        collector
+            .finish()
+            .find(|(resolved, _, _)| resolved == path)
+            .map(|(_, volume, entry)| (volume.into_owned(), entry))
+    }
+
+    /// Finds the versions of the entry at `path` in the given segments,
+    /// including those stored at a path it was moved from
+    fn segment_versions(
+        segments: &SegmentedIndex,
+        active_renames: &[PrefixRename],
+        path: &str,
+    ) -> Vec<(String, String, IndexEntry)> {
+        let mut versions = Vec::new();
+        for origin in path_origins(active_renames, path) {
+            Self::collect_segment_versions(segments.segments(), &origin, &mut versions);
+        }
+        versions
+    }
+
+    /// Appends the versions of the entry stored at `path` in the given
+    /// segments. Every document carries the tokens of its path, whose posting
+    /// lists are intersected from the shortest one (usually the file name's)
+    /// until a few documents are left to read.
+    fn collect_segment_versions<'s>(
+        segments: impl IntoIterator<Item = &'s Arc<Segment>>,
+        path: &str,
+        out: &mut Vec<(String, String, IndexEntry)>,
+    ) {
+        const FEW_DOCUMENTS: usize = 8;
+
+        let mut tokens = crate::tokenizer::extract_path_tokens(path);
+        tokens.sort_unstable();
+        tokens.dedup();
+
+        let mut token_ids = Vec::new();
+
+        'segments: for segment in segments {
+            let map = segment.as_ref().as_ref();
+
+            let mut offsets = Vec::with_capacity(tokens.len());
+            for token in &tokens {
+                match map.get(token) {
+                    Some(post_offset) => offsets.push(post_offset),
+                    None => continue 'segments,
+                }
+            }
+            offsets.sort_by_cached_key(|&post_offset| segment.posting_len(post_offset));
+
+            let mut doc_ids: Option<Vec<u32>> = None;
+            for post_offset in offsets {
+                if doc_ids
+                    .as_ref()
+                    .is_some_and(|ids| ids.len() <= FEW_DOCUMENTS)
+                {
+                    break;
+                }
+
+                token_ids.clear();
+                segment.append_posting_list(post_offset, &mut token_ids);
+                let narrowed = narrow_candidates(doc_ids, &token_ids);
+                if narrowed.is_empty() {
+                    continue 'segments;
+                }
+                doc_ids = Some(narrowed);
+            }
+
+            for doc_id in doc_ids.into_iter().flatten() {
+                let Some(metadata) = segment.doc_meta(doc_id) else {
+                    continue;
+                };
+                if let Some(document) = segment.read_document(metadata.dat_offset)
+                    && document.0 == path
+                {
+                    out.push(document);
+                }
+            }
+        }
+    }
+
+    /// Collects the deletions of the live entries collected so far, so that
+    /// they shadow older versions found through tokens or metadata their
+    /// deletion doesn't carry (e.g. tags or volumes). Deletions are looked up
+    /// by their path, in the memtable and through their synthetic token.
+    fn collect_deletions<'a, 's>(
+        &self,
+        collector: &mut LsmCollector<'a>,
+        segments: impl IntoIterator<Item = &'s Arc<Segment>>,
+        active_renames: &[PrefixRename],
+    ) -> Result<(), IndexError> {
+        let origins: Vec<String> = collector
+            .live_paths()
+            .flat_map(|path| path_origins(active_renames, path))
+            .collect();
+        if origins.is_empty() {
+            return Ok(());
+        }
+
+        {
+            let mem = self.mem_idx.read().map_err(|_| IndexError::ReadLock)?;
+            for origin in &origins {
+                if let Some((path, volume, entry)) = mem
+                    .path_to_id
+                    .get(origin)
+                    .and_then(|id| mem.id_to_data.get(id))
+                    .filter(|(_, _, entry)| entry.opstamp.is_deletion())
+                {
+                    collector.insert(path.clone(), volume.clone(), entry.clone());
+                }
+            }
+        }
+
+        let mut doc_ids = Vec::new();
+        for segment in segments {
+            let map = segment.as_ref().as_ref();
+            for origin in &origins {
+                let Some(post_offset) = map.get(crate::tokenizer::deletion_token(origin)) else {
+                    continue;
+                };
+
+                doc_ids.clear();
+                segment.append_posting_list(post_offset, &mut doc_ids);
+                for &doc_id in &doc_ids {
+                    let Some(metadata) = segment.doc_meta(doc_id) else {
+                        continue;
+                    };
+                    if let Some((path, volume, entry)) = segment.read_document(metadata.dat_offset)
+                        && path == *origin
+                    {
+                        collector.insert(path, volume, entry);
+                    }
+                }
+            }
+        }
+
         Ok(())
     }
 
+    /// Records that the entry at `path` was accessed at `accessed`, e.g. when
+    /// a user opens the file. Returns `false` if there is no live entry at
+    /// `path`. See `update_fields`.
+    pub fn touch(&self, path: &Path, accessed: u64) -> Result<bool, IndexError> {
+        self.update_fields(
+            path,
+            &EntryUpdate {
+                last_accessed: Some(accessed),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Updates some metadata fields of the entry at `path`, keeping the
+    /// others (tags, ownership, file identity...) unchanged.
+    ///
+    /// Only the updated fields are logged, and the path is only tokenized if
+    /// the entry is not in memory already, which makes updates much cheaper
+    /// than inserting the full entry again. The update is merged into the
+    /// latest version of the entry right away, so that searches filtering or
+    /// sorting on the updated fields (e.g. `recent_files`) see the new values.
+    /// Returns `false` if there is no live entry at `path`.
+    pub fn update_fields(&self, path: &Path, update: &EntryUpdate) -> Result<bool, IndexError> {
+        let path_str = path.to_string_lossy().to_string();
+
+        self.apply_backpressure()?;
+
+        // The entry is resolved before taking the write locks, which order the
+        // update after any concurrent write to the same path
+        let LatestEntry {
+            mut wal,
+            mut mem,
+            latest,
+        } = self.lock_latest(&path_str)?;
+
+        let Some((volume, mut entry)) = latest else {
+            return Ok(false);
+        };
+        if update.is_empty() {
+            return Ok(true);
+        }
+
+        let seq = self.next_op_seq();
+        wal.write_update(&path_str, seq, update)?;
+
+        update.apply(&mut entry);
+        entry.opstamp = Opstamp::insertion(seq);
+        mem.insert(path_str, volume, entry);
+
+        drop(mem);
+        drop(wal);
+
+        if self.should_flush() {
+            let _ = self.trigger_flush();
+        }
+
+        Ok(true)
+    }
+
     /// Deletes all index entries under the given prefix, across all volumes
     pub fn delete_prefix(&self, prefix: &str) -> Result<(), IndexError> {
         self.delete_by_volume_name(None, prefix)
@@ -432,34 +1042,166 @@ impl Index {
         Ok(())
     }
 
-    /// Writes the in-memory index to disk.
-    /// This method can fail if the disk is not writable.
-    pub fn sync(&self) -> Result<(), IndexError> {
-        let mut wal = self.wal.write().map_err(|_| IndexError::WriteLock)?;
-        wal.flush().map_err(IndexError::Io)?;
-
-        Ok(())
-    }
-
-    /// Search the index for the given search term (usually a path or
-    /// file name), bound by limit and offset.
-    pub fn search(
+    /// Moves all index entries under `old_prefix` to `new_prefix`, belonging
+    /// to the given volume (or across all volumes if `None`), e.g. when a
+    /// watcher reports a directory move. Entries keep their tags, timestamps
+    /// and other metadata.
+    ///
+    /// The move is logged as a single operation and applied lazily when
+    /// reading entries, until compaction rewrites them at their new path.
+    /// Until then, searches still match moved entries by the words of their
+    /// previous path (e.g. the old directory name), while `within` scopes and
+    /// depth filters consider their new location.
+    pub fn rename_prefix(
         &self,
-        query: &str,
-        limit: usize,
-        offset: usize,
-        options: SearchOptions<'_>,
-    ) -> Result<Vec<SearchResult>, IndexError> {
-        let mut tokens = crate::tokenizer::tokenize(query);
-
-        if tokens.is_empty() {
-            return Ok(Vec::new());
+        volume: Option<&str>,
+        old_prefix: &str,
+        new_prefix: &str,
+    ) -> Result<(), IndexError> {
+        let normalize = |prefix: &str| {
+            let normalized = prefix.replace(['/', '\\'], std::path::MAIN_SEPARATOR_STR);
+            match normalized.trim_end_matches(std::path::MAIN_SEPARATOR) {
+                "" => normalized,
+                trimmed => trimmed.to_string(),
+            }
+        };
+        let (old_prefix, new_prefix) = (normalize(old_prefix), normalize(new_prefix));
+        if old_prefix == new_prefix {
+            return Ok(());
         }
 
-        let query_lower = query.to_lowercase();
-        let raw_query_tokens: Vec<&str> = query_lower.split_whitespace().collect();
+        self.apply_backpressure()?;
 
-        // Inject synthetic extension tokens so search is mmore constrained
+        // Entries in memory are moved right away, with the sequence following
+        // the rename's
+        let seq = self.next_op_seq.fetch_add(2, Ordering::SeqCst);
+        let moved_seq = seq + 1;
+        let rename = PrefixRename {
+            volume: volume.map(|s| s.to_string()),
+            old_prefix,
+            new_prefix,
+            seq,
+        };
+
+        let mut wal = self.wal.write().map_err(|_| IndexError::WriteLock)?;
+        let mut mem = self.mem_idx.write().map_err(|_| IndexError::WriteLock)?;
+
+        wal.write_prefix_rename(&rename)?;
+
+        let active_tombstones = {
+            self.prefix_tombstones
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
+        let moves: Vec<_> = mem
+            .entries
+            .iter()
+            .filter_map(|(path, (volume, entry))| {
+                match resolve_prefix_ops(
+                    volume,
+                    path,
+                    entry.opstamp.sequence(),
+                    &active_tombstones,
+                    std::slice::from_ref(&rename),
+                )? {
+                    (Cow::Owned(new_path), _) => {
+                        Some((path.clone(), new_path, volume.clone(), entry.clone()))
+                    }
+                    (Cow::Borrowed(_), _) => None,
+                }
+            })
+            .collect();
+
+        // Deleting the old paths keeps flushes from writing them back once
+        // the rename is dropped by a full compaction
+        for (path, new_path, volume, entry) in moves {
+            let moved = IndexEntry {
+                opstamp: entry.opstamp.with_sequence(moved_seq),
+                ..entry
+            };
+            mem.insert(path, "".to_owned(), IndexEntry::deletion(moved_seq));
+            mem.insert(new_path, volume, moved);
+        }
+
+        {
+            let mut renames = self
+                .prefix_renames
+                .write()
+                .map_err(|_| IndexError::WriteLock)?;
+            insert_prefix_rename(Arc::make_mut(&mut renames), rename);
+        }
+
+        drop(mem);
+        drop(wal);
+
+        if self.should_flush() {
+            let _ = self.trigger_flush();
+        }
+
+        Ok(())
+    }
+
+    /// Writes the in-memory index to disk.
+    /// This method can fail if the disk is not writable.
+    pub fn sync(&self) -> Result<(), IndexError> {
+        let mut wal = self.wal.write().map_err(|_| IndexError::WriteLock)?;
+        wal.flush().map_err(IndexError::Io)?;
+
+        Ok(())
+    }
+
+    /// Search the index for the given search term (usually a path or
+    /// file name), bound by limit and offset.
+    ///
+    /// Besides free text, the query understands the following field operators:
+    /// * `ext:pdf` - only entries with the given extension
+    /// * `kind:file`, `kind:dir`, `kind:symlink` - only entries of the given kind
+    /// * `in:/home/me/projects` - only entries under the given directory
+    /// * `vol:C:` - only entries in the given volume
+    /// * `tag:starred` - only entries carrying the given tag
+    /// * `target:/mnt/data` - only symlinks pointing to the given path or under it
+    /// * `cat:image|video` - only entries in any of the given categories
+    /// * `modified:>2024-01-01` - last modified date comparison (`>`, `>=`, `<`, `<=`),
+    ///   a single day/month/year (`2024-01`), or a range (`2024-01-01..2024-02-01`)
+    /// * `created:2024-05` - creation date, with the same syntax as `modified`
+    /// * `depth:<4` - path depth (number of separators) comparison or range
+    ///
+    /// Words and token operators (`ext`, `in`, `vol`, `tag`, `target`) prefixed with `-` or `NOT`
+    /// exclude matching entries, e.g. `report -node_modules NOT backup -ext:tmp`.
+    /// Excluded words match whole tokens, and words made of several tokens
+    /// (such as `node_modules`) only match them next to each other, like phrases.
+    ///
+    /// Quoted phrases such as `"annual report"` only match paths containing
+    /// their words next to each other and in the same order.
+    ///
+    /// Words and token operators in parentheses form an OR group, e.g.
+    /// `(invoice OR receipt) 2024` or `report (ext:pdf | ext:docx)`, where
+    /// `OR`/`|` are optional. Entries must match at least one word of each group.
+    ///
+    /// A query made only of filter operators (`kind`, `cat`, `modified`, `created`, `depth`)
+    /// and exclusions lists every matching entry, most recently used first, like
+    /// `recent_files`. Exclusions alone yield no results.
+    pub fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+        options: SearchOptions<'_>,
+    ) -> Result<Vec<SearchResult>, IndexError> {
+        let query = Query::parse_with_categories(query, self.classifier.as_ref())
+            .map_err(IndexError::Query)?;
+
+        let Some(filter) = CandidateFilter::new(&options).with_query(&query) else {
+            return Ok(Vec::new());
+        };
+
+        let mut tokens = crate::tokenizer::tokenize(&query.text);
+
+        let query_lower = query.text.to_lowercase();
+        let raw_query_tokens: Vec<&str> = query_lower.split_whitespace().collect();
+
+        // Inject synthetic extension tokens so search is mmore constrained
         for &raw in &raw_query_tokens {
             if raw.starts_with('.')
                 && raw.len() > 1
@@ -472,14 +1214,20 @@ impl Index {
             }
         }
 
-        tokens.sort_by_key(|b| std::cmp::Reverse(b.len()));
-
-        let segments = self.base.load().snapshot();
+        // Operator tokens narrow down candidates but don't take part in scoring.
+        // Each term is a list of alternatives, made of the tokens they require.
+        let mut search_terms: Vec<Alternatives> = tokens
+            .iter()
+            .chain(&query.required_tokens)
+            .map(|token| vec![vec![token.clone()]])
+            .collect();
 
-        let required_matches = limit + offset;
-        let default_cap = std::cmp::max(500, required_matches * 3).min(1000);
-        let requested_cap = options.max_scoring_cap.unwrap_or(default_cap);
-        let scoring_cap = std::cmp::max(required_matches + 50, requested_cap);
+        if search_terms.is_empty() && query.groups.is_empty() {
+            if !query.has_filters() {
+                return Ok(Vec::new());
+            }
+            return self.recent_entries(Recency::Used, 0, &query, limit, offset, options);
+        }
 
         let active_tombstones = {
             self.prefix_tombstones
@@ -487,126 +1235,181 @@ impl Index {
                 .map_err(|_| IndexError::ReadLock)?
                 .clone()
         };
+        let active_renames = {
+            self.prefix_renames
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
+
+        // Entries moved by pending prefix renames are scoped and filtered by
+        // depth once their path is resolved
+        let candidate_filter = filter.before_renames(&active_renames);
+        let scope = crate::search::scope_terms(&options);
+
+        // Longer tokens are more selective, while OR groups go last
+        search_terms.sort_by_key(|term| std::cmp::Reverse(term[0][0].len()));
+        search_terms.extend(query.groups.iter().cloned());
+        search_terms.extend(crate::search::stored_scope_terms(&options, &active_renames));
+
+        // Group alternatives take part in scoring as well
+        tokens.extend(
+            query
+                .groups
+                .iter()
+                .flatten()
+                .flatten()
+                .filter(|token| !crate::tokenizer::is_synthetic(token))
+                .cloned(),
+        );
+
+        let segments = self.base.load().snapshot();
 
-        let mut collector = LsmCollector::new(&active_tombstones);
+        let required_matches = limit + offset;
+        let default_cap = std::cmp::max(500, required_matches * 3).min(1000);
+        let requested_cap = options.max_scoring_cap.unwrap_or(default_cap);
+        let scoring_cap = std::cmp::max(required_matches + 50, requested_cap);
 
-        let volume_type_mask = Self::compile_allowed_volume_mask(options.volume_type);
+        let mut collector = LsmCollector::new(&active_tombstones, &active_renames);
 
         let mut mem_materialized = Vec::new();
         {
             let mem = self.mem_idx.read().map_err(|_| IndexError::ReadLock)?;
             let mut mem_candidates: Option<Vec<u32>> = None;
-            let mut mem_intersect_buf = Vec::new();
 
-            // In-memory searches
-            if !tokens.is_empty() {
-                for token in &tokens {
-                    let is_first_token = mem_candidates.is_none();
+            let mem_excluded = crate::search::collect_matching(&query.excluded, |token, out| {
+                if let Some(ids) = mem.inverted_index.get(token) {
+                    out.extend_from_slice(ids);
+                }
+            });
 
-                    let is_exact = token.starts_with(crate::tokenizer::SYNTH_EXT_TOKEN_TAG);
+            // Returns the best (sorted) ids for a token, among the `allowed` ones
+            let token_candidates = |token: &str, is_first_token: bool, allowed: Option<&[u32]>| {
+                let is_exact = crate::tokenizer::is_synthetic(token);
 
-                    let max_expansions = if is_first_token
-                        && token.chars().count() <= options.short_prefix_threshold
-                        && !is_exact
-                    {
-                        options.max_expansions
+                let max_expansions = if is_first_token
+                    && token.chars().count() <= options.short_prefix_threshold
+                    && !is_exact
+                {
+                    options.max_expansions
+                } else {
+                    usize::MAX
+                };
+
+                let max_docs =
+                    if token.chars().count() <= options.short_prefix_threshold && !is_exact {
+                        scoring_cap.saturating_mul(5)
                     } else {
                         usize::MAX
                     };
 
-                    let max_docs =
-                        if token.chars().count() <= options.short_prefix_threshold && !is_exact {
-                            scoring_cap.saturating_mul(5)
-                        } else {
-                            usize::MAX
-                        };
-
-                    let mut term_count = 0;
-                    let mut docs_accumulated = 0;
+                let mut term_count = 0;
+                let mut docs_accumulated = 0;
 
-                    let mut prefiltered_candidates = Vec::new();
+                let mut prefiltered_candidates = Vec::new();
 
-                    let mut process_ids = |ids: &[u32]| {
-                        for &id in ids {
-                            if let Some(existing) = mem_candidates.as_ref()
-                                && existing.binary_search(&id).is_err()
-                            {
-                                continue;
-                            }
+                let mut process_ids = |ids: &[u32]| {
+                    for &id in ids {
+                        if let Some(existing) = allowed
+                            && existing.binary_search(&id).is_err()
+                        {
+                            continue;
+                        }
 
-                            let metadata = mem.metadata[id as usize];
-                            if let Some(sort_key) =
-                                evaluate_candidate(metadata, &options, volume_type_mask)
-                            {
-                                prefiltered_candidates.push((sort_key, id))
-                            }
+                        if mem_excluded.binary_search(&id).is_ok() {
+                            continue;
+                        }
 
-                            if prefiltered_candidates.len() > max_docs.saturating_mul(4) {
-                                crate::search::retain_top_k(&mut prefiltered_candidates, max_docs);
-                            }
+                        let metadata = mem.metadata[id as usize];
+                        if let Some(sort_key) = evaluate_candidate(metadata, &candidate_filter) {
+                            prefiltered_candidates.push((sort_key, id))
                         }
-                    };
 
-                    if is_exact {
-                        if let Some(ids) = mem.inverted_index.get(token.as_str()) {
-                            process_ids(ids);
+                        if prefiltered_candidates.len() > max_docs.saturating_mul(4) {
+                            crate::search::retain_top_k(&mut prefiltered_candidates, max_docs);
                         }
-                    } else {
-                        let mut end_bound = String::with_capacity(token.len() + 4);
-                        end_bound.push_str(token);
-                        end_bound.push('\u{FFFF}');
+                    }
+                };
 
-                        for (_, ids) in mem.inverted_index.range::<str, _>((
-                            Bound::Included(token.as_str()),
-                            Bound::Included(end_bound.as_str()),
-                        )) {
-                            process_ids(ids);
+                let edits = crate::search::max_edits(token, options.fuzziness);
 
-                            term_count += 1;
-                            docs_accumulated += ids.len();
+                if is_exact {
+                    if let Some(ids) = mem.inverted_index.get(token) {
+                        process_ids(ids);
+                    }
+                } else if edits > 0 {
+                    let automaton = FuzzyAutomaton::new(token, edits);
 
-                            if term_count >= max_expansions || docs_accumulated >= max_docs {
-                                break;
-                            }
+                    for (_, ids) in automaton.matching_entries(&mem.inverted_index) {
+                        process_ids(ids);
+
+                        term_count += 1;
+                        docs_accumulated += ids.len();
+
+                        if term_count >= max_expansions || docs_accumulated >= max_docs {
+                            break;
                         }
                     }
+                } else {
+                    let mut end_bound = String::with_capacity(token.len() + 4);
+                    end_bound.push_str(token);
+                    end_bound.push('\u{FFFF}');
 
-                    if prefiltered_candidates.is_empty() {
-                        mem_candidates = Some(Vec::new());
-                        break;
-                    }
+                    for (_, ids) in mem.inverted_index.range::<str, _>((
+                        Bound::Included(token),
+                        Bound::Included(end_bound.as_str()),
+                    )) {
+                        process_ids(ids);
 
-                    crate::search::retain_top_k(&mut prefiltered_candidates, max_docs);
-                    let mut best_ids: Vec<u32> = prefiltered_candidates
-                        .into_iter()
-                        .map(|(_, id)| id)
-                        .collect();
-                    best_ids.sort_unstable();
-                    best_ids.dedup();
-                    let current_token_ids = std::borrow::Cow::Owned(best_ids);
-
-                    match mem_candidates.as_mut() {
-                        // Two point merge in the RAM path
-                        Some(existing) => {
-                            mem_intersect_buf.clear();
-                            crate::simd::intersect_arrays(
-                                existing,
-                                &current_token_ids,
-                                &mut mem_intersect_buf,
-                            );
-                            std::mem::swap(existing, &mut mem_intersect_buf);
+                        term_count += 1;
+                        docs_accumulated += ids.len();
+
+                        if term_count >= max_expansions || docs_accumulated >= max_docs {
+                            break;
                         }
-                        None => mem_candidates = Some(current_token_ids.into_owned()),
                     }
+                }
 
-                    if let Some(c) = &mem_candidates
-                        && c.is_empty()
-                    {
-                        break;
+                crate::search::retain_top_k(&mut prefiltered_candidates, max_docs);
+                let mut best_ids: Vec<u32> = prefiltered_candidates
+                    .into_iter()
+                    .map(|(_, id)| id)
+                    .collect();
+                best_ids.sort_unstable();
+                best_ids.dedup();
+                best_ids
+            };
+
+            // In-memory searches
+            for term in &search_terms {
+                let is_first_token = mem_candidates.is_none();
+
+                // Union of the alternatives, each restricted to the current candidates
+                let mut term_ids = Vec::new();
+                for alternative in term {
+                    let mut alternative_ids: Option<Vec<u32>> = None;
+                    for token in alternative {
+                        let allowed = alternative_ids.as_deref().or(mem_candidates.as_deref());
+                        let ids = token_candidates(token, is_first_token, allowed);
+                        let is_empty = ids.is_empty();
+                        alternative_ids = Some(ids);
+                        if is_empty {
+                            break;
+                        }
                     }
+                    term_ids.extend(alternative_ids.unwrap_or_default());
+                }
+
+                if term.len() > 1 {
+                    term_ids.sort_unstable();
+                    term_ids.dedup();
+                }
+
+                let is_empty = term_ids.is_empty();
+                mem_candidates = Some(term_ids);
+                if is_empty {
+                    break;
                 }
-            } else {
-                mem_candidates = Some(mem.id_to_data.keys().copied().collect());
             }
 
             if let Some(candidates) = mem_candidates {
@@ -615,7 +1418,11 @@ impl Index {
                 for id in candidates {
                     let metadata = mem.metadata[id as usize];
 
-                    if let Some(sort_key) = evaluate_candidate(metadata, &options, volume_type_mask)
+                    if let Some(sort_key) = evaluate_candidate(metadata, &candidate_filter)
+                        && mem
+                            .id_to_data
+                            .get(&id)
+                            .is_some_and(|(path, _, _)| query.matches_phrases(path))
                     {
                         mem_sortable.push((sort_key, id));
                     }
@@ -631,19 +1438,17 @@ impl Index {
                         {
                             continue;
                         }
-                        mem_materialized.push((path.clone(), volume.clone(), *entry));
+                        mem_materialized.push((path.clone(), volume.clone(), entry.clone()));
                     }
                 }
             }
         }
         for (path, volume, entry) in &mem_materialized {
-            collector.insert(path.as_str(), volume.as_str(), *entry);
+            collector.insert(path.as_str(), volume.as_str(), entry.clone());
         }
 
         // Disk searches
-        let mut token_docs = Vec::new();
         let mut current_matches = Vec::new();
-        let mut disk_intersect_buf = Vec::new();
 
         let vol_token = options.volume_name.map(|vol| {
             crate::tokenizer::synthesize_token(crate::tokenizer::SYNTH_VOLUME_TOKEN_TAG, vol)
@@ -664,14 +1469,15 @@ impl Index {
                 }
             }
 
-            for token in &tokens {
-                // Skip on 0 matches
-                if !first_token && current_matches.is_empty() {
-                    valid_matches = false;
-                    break;
+            let disk_excluded = crate::search::collect_matching(&query.excluded, |token, out| {
+                if let Some(post_offset) = segment.as_ref().as_ref().get(token) {
+                    segment.append_posting_list(post_offset, out);
                 }
+            });
 
-                let is_exact = token.starts_with(crate::tokenizer::SYNTH_EXT_TOKEN_TAG);
+            // Returns the best (sorted) doc ids for a token, among the `allowed` ones
+            let token_candidates = |token: &str, first_token: bool, allowed: Option<&[u32]>| {
+                let is_exact = crate::tokenizer::is_synthetic(token);
 
                 let max_expansions = if first_token
                     && token.chars().count() <= options.short_prefix_threshold
@@ -689,7 +1495,6 @@ impl Index {
                         usize::MAX
                     };
 
-                token_docs.clear();
                 let map = segment.as_ref().as_ref();
                 let mut term_count = 0;
 
@@ -697,41 +1502,46 @@ impl Index {
 
                 let mut process_offset = |post_offset: u64| -> usize {
                     segment.for_each_posting_id(post_offset, |doc_id| {
-                        if !first_token && current_matches.binary_search(&doc_id).is_err() {
+                        if let Some(allowed) = allowed
+                            && allowed.binary_search(&doc_id).is_err()
+                        {
                             return;
                         }
-                        let byte_offset = (doc_id as usize) * std::mem::size_of::<u128>();
-                        let meta_mmap = segment.meta_map();
-
-                        if byte_offset + std::mem::size_of::<u128>() <= meta_mmap.len() {
-                            let packed_val = unsafe {
-                                std::ptr::read_unaligned(
-                                    meta_mmap.as_ptr().add(byte_offset) as *const u128
-                                )
-                            }
-                            .to_le();
-
-                            if let Some(sort_key) =
-                                evaluate_candidate(packed_val, &options, volume_type_mask)
-                            {
-                                prefiltered_candidates.push((sort_key, doc_id));
-
-                                if prefiltered_candidates.len() > max_docs.saturating_mul(4) {
-                                    crate::search::retain_top_k(
-                                        &mut prefiltered_candidates,
-                                        max_docs,
-                                    );
-                                }
+                        if disk_excluded.binary_search(&doc_id).is_ok() {
+                            return;
+                        }
+                        if let Some(metadata) = segment.doc_meta(doc_id)
+                            && let Some(sort_key) = evaluate_candidate(metadata, &candidate_filter)
+                        {
+                            prefiltered_candidates.push((sort_key, doc_id));
+
+                            if prefiltered_candidates.len() > max_docs.saturating_mul(4) {
+                                crate::search::retain_top_k(&mut prefiltered_candidates, max_docs);
                             }
                         }
                     });
                     prefiltered_candidates.len()
                 };
 
+                let edits = crate::search::max_edits(token, options.fuzziness);
+
                 if is_exact {
                     if let Some(post_offset) = map.get(token) {
                         process_offset(post_offset);
                     }
+                } else if edits > 0 {
+                    let automaton = FuzzyAutomaton::new(token, edits);
+                    let mut stream = map.search(&automaton).into_stream();
+
+                    while let Some((_, post_offset)) = stream.next() {
+                        let current_len = process_offset(post_offset);
+
+                        term_count += 1;
+
+                        if term_count >= max_expansions || current_len >= max_docs {
+                            break;
+                        }
+                    }
                 } else {
                     let matcher = Str::new(token).starts_with();
                     let mut stream = map.search(&matcher).into_stream();
@@ -748,84 +1558,125 @@ impl Index {
                 }
 
                 crate::search::retain_top_k(&mut prefiltered_candidates, max_docs);
-                token_docs = prefiltered_candidates
+                let mut token_docs: Vec<u32> = prefiltered_candidates
                     .into_iter()
                     .map(|(_, id)| id)
                     .collect();
 
                 token_docs.sort_unstable();
                 token_docs.dedup();
+                token_docs
+            };
 
-                if first_token {
-                    std::mem::swap(&mut current_matches, &mut token_docs);
-                    first_token = false;
-                } else {
-                    disk_intersect_buf.clear();
-                    crate::simd::intersect_arrays(
-                        &current_matches,
-                        &token_docs,
-                        &mut disk_intersect_buf,
-                    );
-                    std::mem::swap(&mut current_matches, &mut disk_intersect_buf);
+            for term in &search_terms {
+                // Skip on 0 matches
+                if !first_token && current_matches.is_empty() {
+                    valid_matches = false;
+                    break;
+                }
+
+                let allowed = (!first_token).then_some(current_matches.as_slice());
+
+                // Union of the alternatives, each restricted to the current matches
+                let mut term_docs = Vec::new();
+                for alternative in term {
+                    let mut alternative_docs: Option<Vec<u32>> = None;
+                    for token in alternative {
+                        let docs = token_candidates(
+                            token,
+                            first_token,
+                            alternative_docs.as_deref().or(allowed),
+                        );
+                        let is_empty = docs.is_empty();
+                        alternative_docs = Some(docs);
+                        if is_empty {
+                            break;
+                        }
+                    }
+                    term_docs.extend(alternative_docs.unwrap_or_default());
+                }
+
+                if term.len() > 1 {
+                    term_docs.sort_unstable();
+                    term_docs.dedup();
                 }
+
+                current_matches = term_docs;
+                first_token = false;
             }
 
             if valid_matches && !current_matches.is_empty() {
                 let valid_docs = &current_matches;
-                let mut sortable_docs: Vec<(u64, u128)> = Vec::with_capacity(valid_docs.len());
-                let meta_mmap = segment.meta_map();
-                let meta_ptr = meta_mmap.as_ptr();
-                let meta_len = meta_mmap.len();
+                let mut sortable_docs: Vec<(u64, u64)> = Vec::with_capacity(valid_docs.len());
 
                 for &doc_id in valid_docs {
-                    let byte_offset = (doc_id as usize) * size_of::<u128>();
-
-                    if byte_offset + size_of::<u128>() > meta_len {
+                    let Some(metadata) = segment.doc_meta(doc_id) else {
                         continue;
-                    }
-
-                    let packed_val = unsafe {
-                        std::ptr::read_unaligned(meta_ptr.add(byte_offset) as *const u128)
-                    }
-                    .to_le();
+                    };
 
-                    if let Some(sort_key) =
-                        evaluate_candidate(packed_val, &options, volume_type_mask)
-                    {
-                        sortable_docs.push((sort_key, packed_val));
+                    if let Some(sort_key) = evaluate_candidate(metadata, &candidate_filter) {
+                        sortable_docs.push((sort_key, metadata.dat_offset));
                     }
                 }
 
-                crate::search::retain_top_k(&mut sortable_docs, scoring_cap);
-
-                // Re-sort by dat_offset ascending to align with in-disk layout
-                sortable_docs
-                    .sort_unstable_by_key(|&(_, packed)| (packed & 0x0000_00FF_FFFF_FFFF) as u64);
+                if query.has_phrases() {
+                    // Phrases can only be checked against full paths, so
+                    // documents are read from the best sort key down, until
+                    // enough of them match
+                    sortable_docs.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+                } else {
+                    crate::search::retain_top_k(&mut sortable_docs, scoring_cap);
 
-                for (_, packed_val) in sortable_docs {
-                    let dat_offset = (packed_val & 0x0000_00FF_FFFF_FFFF) as u64;
+                    // Re-sort by dat_offset ascending to align with in-disk layout
+                    sortable_docs.sort_unstable_by_key(|&(_, dat_offset)| dat_offset);
+                }
 
+                let mut documents = Vec::with_capacity(sortable_docs.len().min(scoring_cap));
+                for (_, dat_offset) in sortable_docs {
+                    if documents.len() >= scoring_cap {
+                        break;
+                    }
                     if let Some((path, volume, entry)) = segment.read_document(dat_offset) {
                         if let Some(filter) = options.volume_name
                             && volume != filter
                         {
                             continue;
                         }
-                        collector.insert(path, volume, entry);
+                        if query.matches_phrases(&path) {
+                            documents.push((path, volume, entry));
+                        }
                     }
                 }
+
+                for (path, volume, entry) in documents {
+                    collector.insert(path, volume, entry);
+                }
             }
         }
 
-        let mut results: Vec<_> = collector.finish().collect();
+        self.collect_deletions(&mut collector, &segments, &active_renames)?;
+
+        let mut results: Vec<_> = collector
+            .finish()
+            .filter(|(path, volume, entry)| {
+                filter.matches_kind(entry.kind)
+                    && (active_renames.is_empty()
+                        || crate::search::matches_resolved(&scope, &filter, path, volume, entry))
+            })
+            .collect();
 
         // Rough top-k
         if results.len() > scoring_cap {
             results.select_nth_unstable_by(scoring_cap, |a, b| {
+                let a_key = filter.sort.size_key(a.2.size);
+                let b_key = filter.sort.size_key(b.2.size);
                 let a_recent = a.2.last_modified.max(a.2.last_accessed);
                 let b_recent = b.2.last_modified.max(b.2.last_accessed);
 
-                b_recent.cmp(&a_recent).then_with(|| a.0.cmp(&b.0))
+                b_key
+                    .cmp(&a_key)
+                    .then_with(|| b_recent.cmp(&a_recent))
+                    .then_with(|| a.0.cmp(&b.0))
             });
             results.truncate(scoring_cap);
         }
@@ -853,24 +1704,37 @@ impl Index {
                     last_accessed: entry.last_accessed,
                     kind: entry.kind,
                     now_micros,
+                    fuzziness: options.fuzziness,
                 };
 
                 let score = (config.scoring_fn)(&weights, &inputs);
 
                 SearchResult {
                     path: PathBuf::from(path.as_ref()),
+                    hidden: entry.is_hidden(&path),
                     volume: volume.into_owned(),
                     volume_type: entry.volume_type,
                     kind: entry.kind,
                     last_modified: entry.last_modified,
                     last_accessed: entry.last_accessed,
+                    created: entry.created,
                     category: entry.category,
+                    size: entry.size,
+                    tags: entry.tags,
+                    content_hash: entry.content_hash,
+                    symlink_target: entry.symlink_target.map(PathBuf::from),
+                    uid: entry.uid,
+                    gid: entry.gid,
+                    mode: entry.mode,
+                    file_id: entry.file_id,
                     score,
                 }
             })
             .collect();
 
         scored.sort();
+        // Stable, so equally sized results remain ordered by score
+        scored.sort_by_key(|result| std::cmp::Reverse(filter.sort.size_key(result.size)));
 
         let paginated_results = scored.into_iter().skip(offset).take(limit).collect();
 
@@ -885,6 +1749,52 @@ impl Index {
         offset: usize,
         options: SearchOptions<'_>,
     ) -> Result<Vec<SearchResult>, IndexError> {
+        self.recent_entries(
+            Recency::Used,
+            since_secs,
+            &Query::default(),
+            limit,
+            offset,
+            options,
+        )
+    }
+
+    /// Retrieve all indexed entries created since the given timestamp (in seconds),
+    /// most recently created first. Entries with an unknown creation time are only
+    /// returned when `since_secs` is `0`.
+    pub fn recently_created(
+        &self,
+        since_secs: u64,
+        limit: usize,
+        offset: usize,
+        options: SearchOptions<'_>,
+    ) -> Result<Vec<SearchResult>, IndexError> {
+        self.recent_entries(
+            Recency::Created,
+            since_secs,
+            &Query::default(),
+            limit,
+            offset,
+            options,
+        )
+    }
+
+    /// Entries at least as recent as `since_secs` matching the filters and
+    /// exclusions of `query`, most recent first (or by size, following
+    /// `SearchOptions::sort`)
+    fn recent_entries(
+        &self,
+        recency: Recency,
+        since_secs: u64,
+        query: &Query,
+        limit: usize,
+        offset: usize,
+        options: SearchOptions<'_>,
+    ) -> Result<Vec<SearchResult>, IndexError> {
+        let Some(filter) = CandidateFilter::new(&options).with_query(query) else {
+            return Ok(Vec::new());
+        };
+
         let segments = self.base.load();
         let mem = self.mem_idx.read().map_err(|_| IndexError::ReadLock)?;
 
@@ -894,175 +1804,643 @@ impl Index {
                 .map_err(|_| IndexError::ReadLock)?
                 .clone()
         };
+        let active_renames = {
+            self.prefix_renames
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
 
-        let mut collector = LsmCollector::new(&active_tombstones);
+        let mut collector = LsmCollector::new(&active_tombstones, &active_renames);
 
-        let volume_type_mask = Self::compile_allowed_volume_mask(options.volume_type);
+        // Entries moved by pending prefix renames are scoped and filtered by
+        // depth once their path is resolved
+        let candidate_filter = filter.before_renames(&active_renames);
+        let sort_key =
+            |recent: u64, metadata: &DocMeta| filter.sort.size_key(metadata.size).unwrap_or(recent);
 
         let required_matches = offset + limit;
         // Buffer to account for items that might be filtered out by volume or tombstones
         let disk_cap = required_matches + 500;
 
-        let mut mem_candidates = Vec::new();
-
-        for (id, &metadata) in mem.metadata.iter().enumerate() {
-            let (_, last_modified, last_accessed, _, is_dir, doc_category, doc_volume_type) =
-                SegmentedIndex::unpack_u128(metadata);
+        let scope = crate::search::stored_scope_terms(&options, &active_renames);
 
-            let recent = if last_modified < last_accessed {
-                last_accessed
-            } else {
-                last_modified
-            };
+        let mut mem_candidates = Vec::new();
 
-            if recent >= since_secs {
-                if let Some(kind) = options.kind {
-                    let is_target_dir = kind == Kind::Directory;
-                    let is_entry_dir = is_dir;
-                    if is_entry_dir != is_target_dir {
-                        continue;
-                    };
-                }
+        let mem_scope = crate::search::collect_scoped(&scope, |token, out| {
+            if let Some(ids) = mem.inverted_index.get(token) {
+                out.extend_from_slice(ids);
+            }
+        });
 
-                if let Some(category) = options.category
-                    && doc_category & category == 0
-                {
-                    continue;
-                }
+        let mem_excluded = crate::search::collect_matching(&query.excluded, |token, out| {
+            if let Some(ids) = mem.inverted_index.get(token) {
+                out.extend_from_slice(ids);
+            }
+        });
 
-                if (volume_type_mask & (1 << doc_volume_type)) == 0 {
-                    continue;
-                }
+        for (id, metadata) in mem.metadata.iter().enumerate() {
+            if let Some(allowed) = &mem_scope
+                && allowed.binary_search(&(id as u32)).is_err()
+            {
+                continue;
+            }
+            if mem_excluded.binary_search(&(id as u32)).is_ok() {
+                continue;
+            }
 
-                mem_candidates.push((metadata, id as u32));
+            let recent = recency.of_meta(metadata);
+            if recent >= since_secs && evaluate_candidate(*metadata, &candidate_filter).is_some() {
+                mem_candidates.push((sort_key(recent, metadata), id as u32));
             }
         }
 
-        // Top-K truncation here
-        if mem_candidates.len() > disk_cap {
+        // Top-K truncation here. Phrases can only be checked against full
+        // paths, so candidates are then kept from the most recent down, until
+        // enough of them match.
+        if query.has_phrases() {
+            mem_candidates.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        } else if mem_candidates.len() > disk_cap {
             mem_candidates.select_nth_unstable_by(disk_cap, |a, b| {
-                // Inline bitwise extraction to avoid incurring type conversion penalties
-                let last_modified_a = ((a.0 >> 40) & 0x3_FFFF_FFFF) as u64;
-                let last_accessed_a = ((a.0 >> 74) & 0x3_FFFF_FFFF) as u64;
-                let recent_a = if last_modified_a < last_accessed_a {
-                    last_accessed_a
-                } else {
-                    last_modified_a
-                };
-                let last_modified_b = ((b.0 >> 40) & 0x3_FFFF_FFFF) as u64;
-                let last_accessed_b = ((b.0 >> 74) & 0x3_FFFF_FFFF) as u64;
-                let recent_b = if last_modified_b < last_accessed_b {
-                    last_accessed_b
-                } else {
-                    last_modified_b
-                };
-
-                recent_b
-                    .cmp(&recent_a) // Sort descending by last access time
+                b.0.cmp(&a.0) // Sort descending by recency
                     .then_with(|| a.1.cmp(&b.1))
             });
             mem_candidates.truncate(disk_cap);
         }
 
         // Late materialization
+        let mut matched = 0;
         for (_, id) in mem_candidates {
+            if matched >= disk_cap {
+                break;
+            }
             if let Some((path, volume, entry)) = mem.id_to_data.get(&{ id }) {
                 if let Some(filter) = options.volume_name
                     && volume != filter
                 {
                     continue;
                 }
-                collector.insert(path.as_str(), volume.as_str(), *entry);
+                if query.matches_phrases(path) {
+                    collector.insert(path.clone(), volume.clone(), entry.clone());
+                    matched += 1;
+                }
             }
         }
+        drop(mem);
 
-        let mut disk_candidates: Vec<(&Arc<Segment>, u128)> = Vec::new();
+        let mut disk_candidates: Vec<(u64, &Arc<Segment>, u64)> = Vec::new();
 
         for segment in segments.segments() {
-            let meta_mmap = segment.meta_map();
+            let disk_scope = crate::search::collect_scoped(&scope, |token, out| {
+                if let Some(post_offset) = segment.as_ref().as_ref().get(token) {
+                    segment.append_posting_list(post_offset, out);
+                }
+            });
 
-            for chunk in meta_mmap.chunks_exact(16) {
-                let packed = u128::from_le_bytes(chunk.try_into().unwrap());
-                let (_, last_modified, last_accessed, _, is_dir, doc_category, doc_vol_type) =
-                    SegmentedIndex::unpack_u128(packed);
+            let disk_excluded = crate::search::collect_matching(&query.excluded, |token, out| {
+                if let Some(post_offset) = segment.as_ref().as_ref().get(token) {
+                    segment.append_posting_list(post_offset, out);
+                }
+            });
 
-                let recent = if last_modified < last_accessed {
-                    last_accessed
-                } else {
-                    last_modified
+            for doc_id in 0..segment.doc_count() as u32 {
+                if let Some(allowed) = &disk_scope
+                    && allowed.binary_search(&doc_id).is_err()
+                {
+                    continue;
+                }
+                if disk_excluded.binary_search(&doc_id).is_ok() {
+                    continue;
+                }
+
+                let Some(metadata) = segment.doc_meta(doc_id) else {
+                    continue;
                 };
+                let recent = recency.of_meta(&metadata);
+                if recent >= since_secs && evaluate_candidate(metadata, &candidate_filter).is_some()
+                {
+                    disk_candidates.push((
+                        sort_key(recent, &metadata),
+                        segment,
+                        metadata.dat_offset,
+                    ));
+                }
+            }
+        }
 
-                if recent >= since_secs {
-                    if let Some(target_kind) = options.kind {
-                        let is_target_dir = target_kind == Kind::Directory;
-                        if is_dir != is_target_dir {
-                            continue;
-                        }
-                    }
+        if query.has_phrases() {
+            disk_candidates.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.cmp(&b.2)));
+        } else if disk_candidates.len() > disk_cap {
+            disk_candidates.select_nth_unstable_by(disk_cap, |a, b| {
+                b.0.cmp(&a.0) // Sort descending by recency
+                    .then_with(|| a.2.cmp(&b.2))
+            });
+            disk_candidates.truncate(disk_cap);
+        }
 
-                    if let Some(category) = options.category
-                        && doc_category & category == 0
-                    {
-                        continue;
-                    }
+        let mut matched = 0;
+        for (_, segment, dat_offset) in disk_candidates {
+            if matched >= disk_cap {
+                break;
+            }
+            if let Some((path, volume, entry)) = segment.read_document(dat_offset) {
+                if let Some(filter) = options.volume_name
+                    && volume != filter
+                {
+                    continue;
+                }
+                if query.matches_phrases(&path) {
+                    collector.insert(path, volume, entry);
+                    matched += 1;
+                }
+            }
+        }
+
+        self.collect_deletions(&mut collector, segments.segments(), &active_renames)?;
+
+        let resolved_scope = crate::search::scope_terms(&options);
+        let mut results: Vec<_> = collector
+            .finish()
+            .filter(|(path, volume, entry)| {
+                filter.matches_kind(entry.kind)
+                    && (active_renames.is_empty()
+                        || crate::search::matches_resolved(
+                            &resolved_scope,
+                            &filter,
+                            path,
+                            volume,
+                            entry,
+                        ))
+            })
+            .collect();
+
+        results.sort_by(|a, b| {
+            let key_a = filter.sort.size_key(a.2.size);
+            let key_b = filter.sort.size_key(b.2.size);
+            let recent_a = recency.of_entry(&a.2);
+            let recent_b = recency.of_entry(&b.2);
+
+            key_b
+                .cmp(&key_a)
+                .then_with(|| recent_b.cmp(&recent_a))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+
+        let paginated_results = results
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(path, volume, entry)| SearchResult {
+                path: PathBuf::from(path.as_ref()),
+                hidden: entry.is_hidden(&path),
+                volume: volume.into_owned(),
+                volume_type: entry.volume_type,
+                kind: entry.kind,
+                last_modified: entry.last_modified,
+                last_accessed: entry.last_accessed,
+                created: entry.created,
+                category: entry.category,
+                size: entry.size,
+                tags: entry.tags,
+                content_hash: entry.content_hash,
+                symlink_target: entry.symlink_target.map(PathBuf::from),
+                uid: entry.uid,
+                gid: entry.gid,
+                mode: entry.mode,
+                file_id: entry.file_id,
+                score: 0.0,
+            })
+            .collect();
+
+        Ok(paginated_results)
+    }
+
+    /// Find groups of live entries sharing the same content hash, across the
+    /// on-disk segments and the in-memory index. Entries without a content
+    /// hash are ignored, and `options` restrict which entries are considered.
+    /// Groups are ordered by the space taken by their redundant copies
+    /// (largest first), and bound by limit and offset.
+    pub fn find_duplicates(
+        &self,
+        limit: usize,
+        offset: usize,
+        options: SearchOptions<'_>,
+    ) -> Result<Vec<DuplicateGroup>, IndexError> {
+        let segments = self.base.load_full();
+
+        let active_tombstones = {
+            self.prefix_tombstones
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
+        let active_renames = {
+            self.prefix_renames
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
+
+        let filter = CandidateFilter::new(&options);
+        let scope = crate::search::scope_terms(&options);
+
+        // Every version of an entry with a content hash is listed under its
+        // hash token, so only hashes listed more than once may be duplicated
+        let hash_tokens = crate::tokenizer::content_hash_token_range();
+        let mut listings: HashMap<String, usize> = HashMap::new();
+
+        let mut mem_versions = Vec::new();
+        {
+            let mem = self.mem_idx.read().map_err(|_| IndexError::ReadLock)?;
+            for (token, ids) in mem.inverted_index.range(hash_tokens.clone()) {
+                *listings.entry(token.clone()).or_default() += ids.len();
+                mem_versions.extend(ids.iter().filter_map(|id| mem.id_to_data.get(id).cloned()));
+            }
+        }
 
-                    if (volume_type_mask & (1 << doc_vol_type)) == 0 {
+        for segment in segments.segments() {
+            let mut stream = segment
+                .as_ref()
+                .as_ref()
+                .range()
+                .ge(&hash_tokens.start)
+                .lt(&hash_tokens.end)
+                .into_stream();
+            while let Some((token, post_offset)) = stream.next() {
+                let count = segment.posting_len(post_offset);
+                *listings
+                    .entry(String::from_utf8_lossy(token).into_owned())
+                    .or_default() += count;
+            }
+        }
+
+        let mut collector = LsmCollector::new(&active_tombstones, &active_renames);
+        for (path, volume, entry) in mem_versions {
+            if entry
+                .content_hash
+                .and_then(|hash| listings.get(&crate::tokenizer::content_hash_token(hash)))
+                .is_some_and(|&count| count > 1)
+            {
+                collector.insert(path, volume, entry);
+            }
+        }
+
+        let duplicated: Vec<_> = listings
+            .iter()
+            .filter(|&(_, &count)| count > 1)
+            .map(|(token, _)| token)
+            .collect();
+
+        let mut doc_ids = Vec::new();
+        for segment in segments.segments() {
+            let map = segment.as_ref().as_ref();
+            for token in &duplicated {
+                let Some(post_offset) = map.get(token) else {
+                    continue;
+                };
+
+                doc_ids.clear();
+                segment.append_posting_list(post_offset, &mut doc_ids);
+                for &doc_id in &doc_ids {
+                    let Some(metadata) = segment.doc_meta(doc_id) else {
                         continue;
+                    };
+                    if let Some((path, volume, entry)) = segment.read_document(metadata.dat_offset)
+                    {
+                        collector.insert(path, volume, entry);
                     }
+                }
+            }
+        }
+
+        // Only versions carrying a hash were collected, so the latest version
+        // of each candidate is resolved again. Scopes and metadata filters are
+        // then applied to it, so that older versions never resurface.
+        let candidates: Vec<_> = collector
+            .finish()
+            .map(|(path, _, _)| {
+                let versions = Self::segment_versions(&segments, &active_renames, &path);
+                (path.into_owned(), versions)
+            })
+            .collect();
 
-                    disk_candidates.push((segment, packed));
+        let mut latest = Vec::with_capacity(candidates.len());
+        {
+            let mem = self.mem_idx.read().map_err(|_| IndexError::ReadLock)?;
+            for (path, versions) in candidates {
+                if let Some((volume, entry)) =
+                    Self::latest_of(versions, &mem, &active_tombstones, &active_renames, &path)
+                {
+                    latest.push((path, volume, entry));
                 }
             }
         }
 
-        if disk_candidates.len() > disk_cap {
-            disk_candidates.select_nth_unstable_by(disk_cap, |a, b| {
-                // Inline bitwise extraction to avoid incurring type conversion penalties
-                let last_modified_a = ((a.1 >> 40) & 0x3_FFFF_FFFF) as u64;
-                let last_accessed_a = ((a.1 >> 74) & 0x3_FFFF_FFFF) as u64;
-                let recent_a = if last_modified_a < last_accessed_a {
-                    last_accessed_a
-                } else {
-                    last_modified_a
-                };
-                let last_modified_b = ((b.1 >> 40) & 0x3_FFFF_FFFF) as u64;
-                let last_accessed_b = ((b.1 >> 74) & 0x3_FFFF_FFFF) as u64;
-                let recent_b = if last_modified_b < last_accessed_b {
-                    last_accessed_b
-                } else {
-                    last_modified_b
-                };
+        let mut by_hash: HashMap<u128, Vec<SearchResult>> = HashMap::new();
 
-                let offset_a = a.1 & 0x0000_00FF_FFFF_FFFF;
-                let offset_b = b.1 & 0x0000_00FF_FFFF_FFFF;
+        for (path, volume, entry) in latest {
+            let Some(content_hash) = entry.content_hash else {
+                continue;
+            };
+            if let Some(filter) = options.volume_name
+                && volume != filter
+            {
+                continue;
+            }
+            if !crate::search::matches_resolved(&scope, &filter, &path, &volume, &entry) {
+                continue;
+            }
 
-                recent_b
-                    .cmp(&recent_a) // Sort descending by last access time
-                    .then_with(|| offset_a.cmp(&offset_b))
+            by_hash.entry(content_hash).or_default().push(SearchResult {
+                path: PathBuf::from(&path),
+                hidden: entry.is_hidden(&path),
+                volume,
+                volume_type: entry.volume_type,
+                kind: entry.kind,
+                last_modified: entry.last_modified,
+                last_accessed: entry.last_accessed,
+                created: entry.created,
+                category: entry.category,
+                size: entry.size,
+                tags: entry.tags,
+                content_hash: entry.content_hash,
+                symlink_target: entry.symlink_target.map(PathBuf::from),
+                uid: entry.uid,
+                gid: entry.gid,
+                mode: entry.mode,
+                file_id: entry.file_id,
+                score: 0.0,
             });
-            disk_candidates.truncate(disk_cap);
         }
 
-        for (segment, packed) in disk_candidates {
-            let (dat_offset, _, _, _, _, _, _) = SegmentedIndex::unpack_u128(packed);
+        let mut groups: Vec<_> = by_hash
+            .into_iter()
+            .filter(|(_, entries)| entries.len() > 1)
+            .map(|(content_hash, mut entries)| {
+                entries.sort_by(|a, b| a.path.cmp(&b.path));
+                DuplicateGroup {
+                    content_hash,
+                    entries,
+                }
+            })
+            .collect();
 
-            if let Some((path, volume, entry)) = segment.read_document(dat_offset) {
-                if let Some(filter) = options.volume_name
-                    && volume != filter
-                {
-                    continue;
+        groups.sort_by(|a, b| {
+            b.redundant_size()
+                .cmp(&a.redundant_size())
+                .then_with(|| a.content_hash.cmp(&b.content_hash))
+        });
+
+        Ok(groups.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// Search the index for paths matching a regular expression, bound by
+    /// limit and offset. Matching is case-insensitive and unanchored, so
+    /// `report_\d{4}\.xlsx` matches anywhere in a path. Results are ordered
+    /// by recency.
+    ///
+    /// Candidates are pruned through the token dictionaries when the pattern
+    /// contains literal text. Patterns without any (e.g. `\d+`) need to
+    /// confirm every indexed path and are considerably slower.
+    pub fn search_regex(
+        &self,
+        pattern: &str,
+        limit: usize,
+        offset: usize,
+        options: SearchOptions<'_>,
+    ) -> Result<Vec<SearchResult>, IndexError> {
+        let pattern = PathPattern::from_regex(pattern).map_err(IndexError::Regex)?;
+
+        self.search_pattern(&pattern, limit, offset, options)
+    }
+
+    /// Search the index for paths matching a glob, bound by limit and offset.
+    /// Supports `*`, `**`, `?`, `[...]` classes and `{a,b}` alternations, and
+    /// matches case-insensitively. Results are ordered by recency.
+    ///
+    /// A glob without separators (e.g. `*.rs`) matches file names, an absolute
+    /// glob matches the whole path, and any other glob (e.g. `src/**/*.toml`)
+    /// matches the trailing components of a path.
+    pub fn search_glob(
+        &self,
+        glob: &str,
+        limit: usize,
+        offset: usize,
+        options: SearchOptions<'_>,
+    ) -> Result<Vec<SearchResult>, IndexError> {
+        let pattern = PathPattern::from_glob(glob).map_err(IndexError::Glob)?;
+
+        self.search_pattern(&pattern, limit, offset, options)
+    }
+
+    fn search_pattern(
+        &self,
+        pattern: &PathPattern,
+        limit: usize,
+        offset: usize,
+        options: SearchOptions<'_>,
+    ) -> Result<Vec<SearchResult>, IndexError> {
+        let filter = CandidateFilter::new(&options);
+        let segments = self.base.load().snapshot();
+
+        let active_tombstones = {
+            self.prefix_tombstones
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
+        let active_renames = {
+            self.prefix_renames
+                .read()
+                .map_err(|_| IndexError::ReadLock)?
+                .clone()
+        };
+
+        let mut collector = LsmCollector::new(&active_tombstones, &active_renames);
+
+        // Entries moved by pending prefix renames are scoped and filtered by
+        // depth once their path is resolved
+        let candidate_filter = filter.before_renames(&active_renames);
+
+        // Buffer to account for matches shadowed by newer versions or tombstones
+        let match_cap = offset + limit + 500;
+
+        let token_automaton = pattern.token_automaton();
+        let scope = crate::search::stored_scope_terms(&options, &active_renames);
+
+        let mut mem_materialized = Vec::new();
+        {
+            let mem = self.mem_idx.read().map_err(|_| IndexError::ReadLock)?;
+
+            let mut ids: Option<Vec<u32>> = None;
+            for token in pattern.required_tokens() {
+                let token_ids = mem.inverted_index.get(token).map_or(&[][..], Vec::as_slice);
+                ids = Some(narrow_candidates(ids, token_ids));
+            }
+            if let Some(automaton) = &token_automaton {
+                let mut matching = Vec::new();
+                for (token, token_ids) in &mem.inverted_index {
+                    if automaton.matches(token) {
+                        matching.extend_from_slice(token_ids);
+                    }
+                }
+                matching.sort_unstable();
+                matching.dedup();
+                ids = Some(narrow_candidates(ids, &matching));
+            }
+            if let Some(scoped_ids) = crate::search::collect_scoped(&scope, |token, out| {
+                if let Some(ids) = mem.inverted_index.get(token) {
+                    out.extend_from_slice(ids);
+                }
+            }) {
+                ids = Some(narrow_candidates(ids, &scoped_ids));
+            }
+            let ids = ids.unwrap_or_else(|| (0..mem.metadata.len() as u32).collect());
+
+            let mut mem_candidates: Vec<(u64, u32)> = ids
+                .into_iter()
+                .filter_map(|id| {
+                    let metadata = mem.metadata[id as usize];
+                    evaluate_candidate(metadata, &candidate_filter).map(|_| {
+                        let sort_key = filter.sort.size_key(metadata.size);
+                        (sort_key.unwrap_or(Recency::Used.of_meta(&metadata)), id)
+                    })
+                })
+                .collect();
+            mem_candidates.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+            let mut matched = 0;
+            for (_, id) in mem_candidates {
+                if matched >= match_cap {
+                    break;
+                }
+
+                if let Some((path, volume, entry)) = mem.id_to_data.get(&id) {
+                    if let Some(filter) = options.volume_name
+                        && volume != filter
+                    {
+                        continue;
+                    }
+
+                    if !entry.opstamp.is_deletion() && pattern.is_match(path) {
+                        mem_materialized.push((path.clone(), volume.clone(), entry.clone()));
+                        matched += 1;
+                    }
+                }
+            }
+        }
+        for (path, volume, entry) in &mem_materialized {
+            collector.insert(path.as_str(), volume.as_str(), entry.clone());
+        }
+
+        let vol_token = options.volume_name.map(|vol| {
+            crate::tokenizer::synthesize_token(crate::tokenizer::SYNTH_VOLUME_TOKEN_TAG, vol)
+        });
+
+        let mut token_ids = Vec::new();
+
+        for segment in &segments {
+            let map = segment.as_ref().as_ref();
+
+            if let Some(ref vol_token) = vol_token
+                && map.get(vol_token).is_none()
+            {
+                continue;
+            }
+
+            let mut ids: Option<Vec<u32>> = None;
+            for token in pattern.required_tokens() {
+                token_ids.clear();
+                if let Some(post_offset) = map.get(token) {
+                    segment.append_posting_list(post_offset, &mut token_ids);
+                }
+                ids = Some(narrow_candidates(ids, &token_ids));
+            }
+            if let Some(automaton) = &token_automaton {
+                token_ids.clear();
+                let mut stream = map.search(automaton).into_stream();
+                while let Some((_, post_offset)) = stream.next() {
+                    segment.append_posting_list(post_offset, &mut token_ids);
+                }
+                token_ids.sort_unstable();
+                token_ids.dedup();
+                ids = Some(narrow_candidates(ids, &token_ids));
+            }
+            if let Some(scoped_ids) = crate::search::collect_scoped(&scope, |token, out| {
+                if let Some(post_offset) = map.get(token) {
+                    segment.append_posting_list(post_offset, out);
+                }
+            }) {
+                ids = Some(narrow_candidates(ids, &scoped_ids));
+            }
+            let doc_ids = ids.unwrap_or_else(|| (0..segment.doc_count() as u32).collect());
+
+            let mut disk_candidates: Vec<(u64, u64)> = doc_ids
+                .iter()
+                .filter_map(|&doc_id| {
+                    let metadata = segment.doc_meta(doc_id)?;
+
+                    evaluate_candidate(metadata, &candidate_filter).map(|_| {
+                        let sort_key = filter.sort.size_key(metadata.size);
+                        (
+                            sort_key.unwrap_or(Recency::Used.of_meta(&metadata)),
+                            metadata.dat_offset,
+                        )
+                    })
+                })
+                .collect();
+
+            disk_candidates.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+            let mut matched = 0;
+            for (_, dat_offset) in disk_candidates {
+                if matched >= match_cap {
+                    break;
+                }
+
+                if let Some((path, volume, entry)) = segment.read_document(dat_offset) {
+                    if let Some(filter) = options.volume_name
+                        && volume != filter
+                    {
+                        continue;
+                    }
+
+                    if pattern.is_match(&path) {
+                        collector.insert(path, volume, entry);
+                        matched += 1;
+                    }
                 }
-                collector.insert(path, volume, entry);
             }
         }
 
-        let mut results: Vec<_> = collector.finish().collect();
+        self.collect_deletions(&mut collector, &segments, &active_renames)?;
+
+        let resolved_scope = crate::search::scope_terms(&options);
+        let mut results: Vec<_> = collector
+            .finish()
+            .filter(|(path, volume, entry)| {
+                filter.matches_kind(entry.kind)
+                    && (active_renames.is_empty()
+                        || crate::search::matches_resolved(
+                            &resolved_scope,
+                            &filter,
+                            path,
+                            volume,
+                            entry,
+                        ))
+            })
+            .collect();
 
         results.sort_by(|a, b| {
+            let key_a = filter.sort.size_key(a.2.size);
+            let key_b = filter.sort.size_key(b.2.size);
             let recent_a = a.2.last_accessed.max(a.2.last_modified);
             let recent_b = b.2.last_accessed.max(b.2.last_modified);
 
-            recent_b.cmp(&recent_a).then_with(|| a.0.cmp(&b.0))
+            key_b
+                .cmp(&key_a)
+                .then_with(|| recent_b.cmp(&recent_a))
+                .then_with(|| a.0.cmp(&b.0))
         });
 
         let paginated_results = results
@@ -1071,12 +2449,22 @@ impl Index {
             .take(limit)
             .map(|(path, volume, entry)| SearchResult {
                 path: PathBuf::from(path.as_ref()),
+                hidden: entry.is_hidden(&path),
                 volume: volume.into_owned(),
                 volume_type: entry.volume_type,
                 kind: entry.kind,
                 last_modified: entry.last_modified,
                 last_accessed: entry.last_accessed,
+                created: entry.created,
                 category: entry.category,
+                size: entry.size,
+                tags: entry.tags,
+                content_hash: entry.content_hash,
+                symlink_target: entry.symlink_target.map(PathBuf::from),
+                uid: entry.uid,
+                gid: entry.gid,
+                mode: entry.mode,
+                file_id: entry.file_id,
                 score: 0.0,
             })
             .collect();
@@ -1142,9 +2530,18 @@ impl Index {
             let guard = self.prefix_tombstones.read().expect("lock poisoned");
             guard.clone()
         };
+        let snapshot_renames = {
+            let guard = self.prefix_renames.read().expect("lock poisoned");
+            guard.clone()
+        };
 
-        if let Err(e) = compactor::merge_segments(&snapshot, snapshot_tombstones, tmp_path.clone())
-            .map_err(|e| IndexError::Io(std::io::Error::other(e)))
+        if let Err(e) = compactor::merge_segments(
+            &snapshot,
+            snapshot_tombstones,
+            Arc::clone(&snapshot_renames),
+            tmp_path.clone(),
+        )
+        .map_err(|e| IndexError::Io(std::io::Error::other(e)))
         {
             let tmp_paths = Segment::paths_with_additional_extension(&tmp_path);
             Segment::remove_files(&tmp_paths);
@@ -1176,6 +2573,29 @@ impl Index {
                 .write()
                 .map_err(|_| IndexError::WriteLock)?;
             Arc::make_mut(&mut tombstones).retain(|(_, _, seq)| *seq >= compactor_seq);
+
+            // Renames logged during the merge weren't materialized
+            let mut renames = self
+                .prefix_renames
+                .write()
+                .map_err(|_| IndexError::WriteLock)?;
+            Arc::make_mut(&mut renames)
+                .retain(|rename| rename.seq >= compactor_seq || !snapshot_renames.contains(rename));
+        }
+
+        // The replaced segments are removed once the last reader drops them,
+        // after the manifest stops listing them
+        Self::store_manifest(
+            &self.path,
+            &self.base,
+            &self.prefix_tombstones,
+            &self.prefix_renames,
+            &self.next_op_seq,
+            &self.manifest_lock,
+        )
+        .map_err(IndexError::Io)?;
+        for segment in &snapshot {
+            segment.mark_deleted();
         }
 
         log::debug!("Full compaction complete");
@@ -1190,6 +2610,11 @@ impl Index {
                 .read()
                 .expect("prefix_tombstones lock poisoned")
                 .len()
+                + self
+                    .prefix_renames
+                    .read()
+                    .expect("prefix_renames lock poisoned")
+                    .len()
                 > self.compactor_config.tombstone_threshold
     }
 
@@ -1207,7 +2632,7 @@ impl Index {
         let snapshot = {
             let mut wal = self.wal.write().map_err(|_| IndexError::WriteLock)?;
 
-            let (snapshot, tombstones_cow) = {
+            let (snapshot, tombstones_cow, renames_cow) = {
                 let mut mem = self.mem_idx.write().map_err(|_| IndexError::WriteLock)?;
 
                 if mem.is_empty() {
@@ -1220,16 +2645,24 @@ impl Index {
                     .read()
                     .map_err(|_| IndexError::ReadLock)?
                     .clone();
-                (snapshot, tombstones_cow)
+                let renames_cow: Arc<Vec<PrefixRename>> = self
+                    .prefix_renames
+                    .read()
+                    .map_err(|_| IndexError::ReadLock)?
+                    .clone();
+                (snapshot, tombstones_cow, renames_cow)
             };
 
             wal.rotate(&flushing_path).map_err(IndexError::Io)?;
 
-            // Re-write tombstones to the WAL until a full compaction runs.
+            // Re-write tombstones and renames to the WAL until a full compaction runs.
 
             for (volume, prefix, seq) in tombstones_cow.iter() {
                 wal.write_prefix_tombstone(volume.as_deref(), prefix, *seq)?;
             }
+            for rename in renames_cow.iter() {
+                wal.write_prefix_rename(rename)?;
+            }
 
             snapshot
         };
@@ -1240,6 +2673,8 @@ impl Index {
         let compactor_lock = Arc::clone(&self.compactor);
         let op_seq = Arc::clone(&self.next_op_seq);
         let prefix_tombstones = Arc::clone(&self.prefix_tombstones);
+        let prefix_renames = Arc::clone(&self.prefix_renames);
+        let manifest_lock = Arc::clone(&self.manifest_lock);
 
         let flusher = crate::sync::thread::Builder::new()
             .name("minidex-flush".to_owned())
@@ -1286,6 +2721,20 @@ impl Index {
                     next
                 });
 
+                // The rotated WAL is only redundant once the manifest lists
+                // the new segment
+                if let Err(e) = Self::store_manifest(
+                    &path,
+                    &base,
+                    &prefix_tombstones,
+                    &prefix_renames,
+                    &op_seq,
+                    &manifest_lock,
+                ) {
+                    log::error!("flush failed to store the manifest: {}", e);
+                    return;
+                }
+
                 if let Err(e) = std::fs::remove_file(&flushing_path) {
                     log::error!("failed to delete rotated WAL: {}", e);
                 }
@@ -1294,17 +2743,37 @@ impl Index {
                     .read()
                     .expect("failed to acquire prefix tombstone lock")
                     .len()
+                    + prefix_renames
+                        .read()
+                        .expect("failed to acquire prefix rename lock")
+                        .len()
                     > tombstone_threshold;
 
                 let candidates = {
                     let segments = base.load().snapshot();
 
-                    // Clear tombstones if we have nothing in disk
+                    // Clear tombstones and renames if we have nothing in disk
                     if segments.is_empty() && force_full {
                         let mut tombstones = prefix_tombstones
                             .write()
                             .expect("failed to acquire prefix tombstones write-lock");
                         Arc::make_mut(&mut tombstones).clear();
+                        let mut renames = prefix_renames
+                            .write()
+                            .expect("failed to acquire prefix renames write-lock");
+                        Arc::make_mut(&mut renames).clear();
+                        drop((tombstones, renames));
+
+                        if let Err(e) = Self::store_manifest(
+                            &path,
+                            &base,
+                            &prefix_tombstones,
+                            &prefix_renames,
+                            &op_seq,
+                            &manifest_lock,
+                        ) {
+                            log::error!("failed to store the manifest: {}", e);
+                        }
                         return;
                     }
 
@@ -1327,7 +2796,15 @@ impl Index {
                     return;
                 }
 
-                *compactor_guard = Self::compact(base, path, candidates, prefix_tombstones, op_seq);
+                *compactor_guard = Self::compact(
+                    base,
+                    path,
+                    candidates,
+                    prefix_tombstones,
+                    prefix_renames,
+                    op_seq,
+                    manifest_lock,
+                );
             })
             .map_err(IndexError::Io)?;
 
@@ -1340,7 +2817,9 @@ impl Index {
         path: PathBuf,
         snapshot: Vec<Arc<Segment>>,
         prefix_tombstones: Arc<RwLock<Arc<Vec<Tombstone>>>>,
+        prefix_renames: Arc<RwLock<Arc<Vec<PrefixRename>>>>,
         next_op_seq: Arc<AtomicU64>,
+        manifest_lock: Arc<Mutex<()>>,
     ) -> Option<JoinHandle<()>> {
         if snapshot.is_empty() {
             return None;
@@ -1358,9 +2837,18 @@ impl Index {
                     .read()
                     .expect("prefix_tombstones lock poisoned")
                     .clone();
-                match compactor::merge_segments(&snapshot, snapshot_tombstones, tmp_path.clone()) {
-                    Ok(compactor_seq) => {
-                        let tmp_paths = Segment::paths_with_additional_extension(&tmp_path);
+                let snapshot_renames = prefix_renames
+                    .read()
+                    .expect("prefix_renames lock poisoned")
+                    .clone();
+                match compactor::merge_segments(
+                    &snapshot,
+                    snapshot_tombstones,
+                    Arc::clone(&snapshot_renames),
+                    tmp_path.clone(),
+                ) {
+                    Ok(compactor_seq) => {
+                        let tmp_paths = Segment::paths_with_additional_extension(&tmp_path);
                         let final_path = path.join(format!("{}", next_seq));
                         let final_paths = Segment::to_paths(&final_path);
 
@@ -1394,6 +2882,32 @@ impl Index {
                                 .expect("failed to acquire prefix tombstones write lock");
                             Arc::make_mut(&mut tombstones)
                                 .retain(|(_, _, seq)| *seq >= compactor_seq);
+
+                            // Renames logged during the merge weren't materialized
+                            let mut renames = prefix_renames
+                                .write()
+                                .expect("failed to acquire prefix renames write lock");
+                            Arc::make_mut(&mut renames).retain(|rename| {
+                                rename.seq >= compactor_seq || !snapshot_renames.contains(rename)
+                            });
+                        }
+
+                        // The replaced segments are removed once the last
+                        // reader drops them, after the manifest stops
+                        // listing them
+                        if let Err(e) = Self::store_manifest(
+                            &path,
+                            &base,
+                            &prefix_tombstones,
+                            &prefix_renames,
+                            &next_op_seq,
+                            &manifest_lock,
+                        ) {
+                            log::error!("Failed to store the manifest: {}", e);
+                            return;
+                        }
+                        for segment in &snapshot {
+                            segment.mark_deleted();
                         }
 
                         log::debug!("Compaction finished");
@@ -1423,7 +2937,7 @@ impl Index {
         let mut buckets: BTreeMap<u32, Vec<Arc<Segment>>> = BTreeMap::new();
 
         for seg in segments {
-            let size = seg.meta_map().len().max(1) as u64; // Meta length perfectly correlates to doc count
+            let size = seg.doc_count().max(1) as u64;
             let bucket_idx = size.ilog2() / 2; // Base-4 grouping
             buckets.entry(bucket_idx).or_default().push(seg.clone());
         }
@@ -1440,7 +2954,7 @@ impl Index {
         // a complete bucket, force a merge of the smallest ones to prevent file descriptor exhaustion.
         if segments.len() > min_merge_count.saturating_mul(3) {
             let mut sorted = segments.to_vec();
-            sorted.sort_by_key(|s| s.meta_map().len());
+            sorted.sort_by_key(|s| s.doc_count());
             sorted.truncate(min_merge_count);
             return sorted;
         }
@@ -1448,13 +2962,6 @@ impl Index {
         Vec::new()
     }
 
-    fn compile_allowed_volume_mask(allowed_volume_types: Option<&[VolumeType]>) -> u8 {
-        match allowed_volume_types {
-            Some(allowed) => allowed.iter().fold(0, |acc, &vt| acc | (1 << (vt as u8))),
-            None => 0b0000_1111,
-        }
-    }
-
     #[inline(always)]
     fn apply_backpressure(&self) -> Result<(), IndexError> {
         let threshold = self.compactor_config.flush_threshold.saturating_mul(3);
@@ -1480,6 +2987,45 @@ impl Index {
     }
 }
 
+/// The latest live version of an entry (if any), with the locks ordering
+/// writes to it. See `Index::lock_latest`.
+struct LatestEntry<'a> {
+    wal: RwLockWriteGuard<'a, Wal>,
+    mem: RwLockWriteGuard<'a, MemTable>,
+    latest: Option<(String, IndexEntry)>,
+}
+
+/// What resolving an entry depends on, besides the memtable
+struct ResolutionStamp {
+    segments: Arc<SegmentedIndex>,
+    tombstones: Arc<Vec<Tombstone>>,
+    renames: Arc<Vec<PrefixRename>>,
+}
+
+impl ResolutionStamp {
+    /// Segments and prefix operations are copied on write, so unchanged
+    /// pointers mean nothing was flushed, compacted, added or removed
+    fn matches(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.segments, &other.segments)
+            && Arc::ptr_eq(&self.tombstones, &other.tombstones)
+            && Arc::ptr_eq(&self.renames, &other.renames)
+    }
+}
+
+/// The paths the entry at `path` may be stored at, as entries moved by a
+/// prefix rename are still stored at a previous path
+fn path_origins(active_renames: &[PrefixRename], path: &str) -> Vec<String> {
+    let mut origins = vec![path.to_string()];
+    for rename in active_renames.iter().rev() {
+        let previous: Vec<_> = origins
+            .iter()
+            .filter_map(|origin| rename.revert(origin))
+            .collect();
+        origins.extend(previous);
+    }
+    origins
+}
+
 impl Drop for Index {
     fn drop(&mut self) {
         let _ = self.sync();
@@ -1516,8 +3062,14 @@ pub enum IndexError {
     SegmentedIndex(SegmentedIndexError),
     #[error("failed to compile matching regex: {0}")]
     Regex(String),
+    #[error("invalid glob pattern: {0}")]
+    Glob(String),
+    #[error("invalid search query: {0}")]
+    Query(QueryError),
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("entry metadata is too large to be indexed: {0}")]
+    EntryTooLarge(PathBuf),
 }
 
 #[cfg(all(test, feature = "shuttle"))]
@@ -1526,7 +3078,7 @@ mod concurrency_tests;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::common::{VolumeType, category};
+    use crate::category;
 
     #[test]
     fn test_index_basic_lifecycle() -> Result<(), IndexError> {
@@ -1541,11 +3093,10 @@ mod tests {
             index.insert(FilesystemEntry {
                 path: PathBuf::from(&path1),
                 volume: "vol1".to_string(),
-                kind: Kind::File,
                 last_modified: 100,
                 last_accessed: 100,
                 category: category::TEXT,
-                volume_type: VolumeType::Local,
+                ..Default::default()
             })?;
 
             let results = index.search("bar", 10, 0, SearchOptions::default())?;
@@ -1580,11 +3131,10 @@ mod tests {
             let entries = (0..100).map(|i| FilesystemEntry {
                 path: PathBuf::from(format!("{}foo{}file_{}.txt", sep, sep, i)),
                 volume: "vol1".to_string(),
-                kind: Kind::File,
                 last_modified: 100,
                 last_accessed: 100,
                 category: category::TEXT,
-                volume_type: VolumeType::Local,
+                ..Default::default()
             });
 
             index.insert_batch(entries, 20)?;
@@ -1618,11 +3168,10 @@ mod tests {
         index.insert(FilesystemEntry {
             path: PathBuf::from(unicode_path),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
             category: category::TEXT,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
 
         // Search for a fragment
@@ -1650,22 +3199,20 @@ mod tests {
         index.insert(FilesystemEntry {
             path: PathBuf::from(format!("{}foo{}a.txt", sep, sep)),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
             category: category::TEXT,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
 
         // This insert should trigger a flush in the background
         index.insert(FilesystemEntry {
             path: PathBuf::from(format!("{}foo{}b.txt", sep, sep)),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
             category: category::TEXT,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
 
         // Wait a bit for background flush
@@ -1689,21 +3236,17 @@ mod tests {
         index.insert(FilesystemEntry {
             path: PathBuf::from(format!("{}foo{}bar{}a.txt", sep, sep, sep)),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
         let other_path = format!("{}other{}b.txt", sep, sep);
         index.insert(FilesystemEntry {
             path: PathBuf::from(&other_path),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
 
         // Delete everything under /foo
@@ -1728,20 +3271,16 @@ mod tests {
         index.insert(FilesystemEntry {
             path: PathBuf::from(format!("{}foo{}bar{}a.txt", sep, sep, sep)),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
         index.insert(FilesystemEntry {
             path: PathBuf::from(format!("{}foo{}bar{}b.txt", sep, sep, sep)),
             volume: "vol2".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
-            category: 0,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
 
         // Delete /foo on vol1 only
@@ -1774,11 +3313,9 @@ mod tests {
             index.insert(FilesystemEntry {
                 path: PathBuf::from(format!("{}foo{}{}.txt", sep, sep, i)),
                 volume: "vol1".to_string(),
-                kind: Kind::File,
                 last_modified: 100,
                 last_accessed: 100,
-                category: 0,
-                volume_type: VolumeType::Local,
+                ..Default::default()
             })?;
             // Force wait for each flush
             crate::sync::thread::sleep(crate::sync::time::Duration::from_millis(200));
@@ -1825,21 +3362,17 @@ mod tests {
         index.insert(FilesystemEntry {
             path: PathBuf::from(format!("{}foo{}old.txt", sep, sep)),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 100_000_000, // 100 seconds in micros
             last_accessed: 100_000_000, // Very old
-            category: 0,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
         let new_path = format!("{}foo{}new.txt", sep, sep);
         index.insert(FilesystemEntry {
             path: PathBuf::from(&new_path),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 1_000_000_000, // 1000 seconds in micros
             last_accessed: 1_000_000_000, // Newer
-            category: 0,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
 
         // since is in seconds; only the entry at 1000s should pass
@@ -1862,20 +3395,18 @@ mod tests {
         index.insert(FilesystemEntry {
             path: PathBuf::from(format!("{}vol1{}a.txt", sep, sep)),
             volume: "vol1".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
             category: category::TEXT,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
         index.insert(FilesystemEntry {
             path: PathBuf::from(format!("{}vol2{}b.txt", sep, sep)),
             volume: "vol2".to_string(),
-            kind: Kind::File,
             last_modified: 100,
             last_accessed: 100,
             category: category::IMAGE,
-            volume_type: VolumeType::Local,
+            ..Default::default()
         })?;
 
         // Filter by volume
@@ -1908,6 +3439,2203 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_index_search_operators() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_ops_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        // 2024-06-01T00:00:00Z
+        let june_2024 = 1_717_200_000_000_000;
+
+        let entries = [
+            (
+                "work",
+                "report.pdf",
+                Kind::File,
+                category::DOCUMENT,
+                june_2024,
+            ),
+            ("work", "report.txt", Kind::File, category::TEXT, june_2024),
+            (
+                "work",
+                "reports",
+                Kind::Directory,
+                category::OTHER,
+                june_2024,
+            ),
+            ("home", "report.pdf", Kind::File, category::DOCUMENT, 100),
+        ];
+        let nested = FilesystemEntry {
+            path: PathBuf::from(format!("{}work{}nested{}report.pdf", sep, sep, sep)),
+            volume: "vol2".to_string(),
+            last_modified: june_2024,
+            last_accessed: june_2024,
+            category: category::DOCUMENT,
+            ..Default::default()
+        };
+        let index = index_with(
+            &temp_dir,
+            entries
+                .into_iter()
+                .map(
+                    |(dir, name, kind, category, last_modified)| FilesystemEntry {
+                        path: PathBuf::from(format!("{}{}{}{}", sep, dir, sep, name)),
+                        volume: "vol1".to_string(),
+                        kind,
+                        last_modified,
+                        last_accessed: last_modified,
+                        category,
+                        ..Default::default()
+                    },
+                )
+                .chain([nested]),
+            4,
+        )?;
+
+        let search = |query: &str| -> Result<Vec<String>, IndexError> {
+            let mut paths: Vec<_> = index
+                .search(query, 10, 0, SearchOptions::default())?
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect();
+            paths.sort();
+            Ok(paths)
+        };
+
+        assert_eq!(search("report ext:pdf")?.len(), 3);
+        assert_eq!(search("report ext:pdf in:/work depth:2")?.len(), 1);
+        assert_eq!(search("report in:/work")?.len(), 4);
+        assert_eq!(search("report vol:VOL2")?.len(), 1);
+        assert_eq!(
+            search("report kind:dir")?,
+            vec![format!("{}work{}reports", sep, sep)]
+        );
+        assert_eq!(search("report cat:text|image")?.len(), 1);
+        assert_eq!(search("report ext:pdf modified:>=2024-01-01")?.len(), 2);
+        assert_eq!(search("report ext:pdf modified:<2024")?.len(), 1);
+
+        // Filter-only queries list every matching entry
+        assert_eq!(
+            search("kind:dir")?,
+            vec![format!("{}work{}reports", sep, sep)]
+        );
+        assert_eq!(search("kind:file")?.len(), 4);
+        assert_eq!(
+            search("cat:document modified:>=2024-01-01")?,
+            vec![
+                format!("{}work{}nested{}report.pdf", sep, sep, sep),
+                format!("{}work{}report.pdf", sep, sep),
+            ]
+        );
+        assert_eq!(
+            search("kind:file -work")?,
+            vec![format!("{}home{}report.pdf", sep, sep)]
+        );
+        assert_eq!(search("kind:file -report_txt")?.len(), 3);
+        assert!(search("-report")?.is_empty());
+
+        assert!(matches!(
+            search("report depth:<abc"),
+            Err(IndexError::Query(_))
+        ));
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    /// Opens an index in `dir` holding `entries`, flushing the first
+    /// `flush_after` of them to exercise both the disk and in-memory paths
+    fn index_with(
+        dir: &Path,
+        entries: impl IntoIterator<Item = FilesystemEntry>,
+        flush_after: usize,
+    ) -> Result<Index, IndexError> {
+        let index = Index::open(dir)?;
+        for (i, entry) in entries.into_iter().enumerate() {
+            if i == flush_after {
+                index.flush()?;
+            }
+            index.insert(entry)?;
+        }
+        Ok(index)
+    }
+
+    #[test]
+    fn test_index_search_regex() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_regex_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let names = [
+            "Report_2024.xlsx",
+            "report_24.xlsx",
+            "report_2023.xlsx",
+            "notes_1999.txt",
+        ];
+        let index = index_with(
+            &temp_dir,
+            names.iter().enumerate().map(|(i, name)| FilesystemEntry {
+                path: PathBuf::from(format!("{}work{}{}", sep, sep, name)),
+                volume: "vol1".to_string(),
+                last_modified: 1000 + i as u64,
+                last_accessed: 1000 + i as u64,
+                ..Default::default()
+            }),
+            2,
+        )?;
+
+        let search =
+            |pattern: &str, limit: usize, offset: usize| -> Result<Vec<String>, IndexError> {
+                Ok(index
+                    .search_regex(pattern, limit, offset, SearchOptions::default())?
+                    .into_iter()
+                    .map(|r| r.path.file_name().unwrap().to_string_lossy().into_owned())
+                    .collect())
+            };
+
+        // Ordered by recency
+        assert_eq!(
+            search(r"report_\d{4}\.xlsx", 10, 0)?,
+            vec!["report_2023.xlsx", "Report_2024.xlsx"]
+        );
+        assert_eq!(
+            search(r"report_\d{4}\.xlsx", 1, 1)?,
+            vec!["Report_2024.xlsx"]
+        );
+        // No literal to prune with: falls back to confirming every path
+        assert_eq!(search(r"_\d{4}\.", 10, 0)?.len(), 3);
+        assert!(search(r"^report", 10, 0)?.is_empty());
+
+        // Deletions in memory shadow flushed entries
+        index.delete(&PathBuf::from(format!(
+            "{}work{}Report_2024.xlsx",
+            sep, sep
+        )))?;
+        assert_eq!(
+            search(r"report_\d{4}\.xlsx", 10, 0)?,
+            vec!["report_2023.xlsx"]
+        );
+
+        assert!(matches!(
+            search(r"report(", 10, 0),
+            Err(IndexError::Regex(_))
+        ));
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_glob() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_glob_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let paths = [
+            "/proj/Cargo.toml",
+            "/proj/src/lib.rs",
+            "/proj/src/nested/config.toml",
+            "/proj/target/debug/build.rs",
+            "/other/src/main.rs",
+        ];
+        let index = index_with(
+            &temp_dir,
+            paths.iter().enumerate().map(|(i, path)| FilesystemEntry {
+                path: PathBuf::from(path.replace('/', sep)),
+                volume: "vol1".to_string(),
+                last_modified: 1000 + i as u64,
+                last_accessed: 1000 + i as u64,
+                ..Default::default()
+            }),
+            3,
+        )?;
+
+        let search = |glob: &str| -> Result<Vec<String>, IndexError> {
+            let mut paths: Vec<_> = index
+                .search_glob(glob, 10, 0, SearchOptions::default())?
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().replace(sep, "/"))
+                .collect();
+            paths.sort();
+            Ok(paths)
+        };
+
+        assert_eq!(
+            search("*.rs")?,
+            vec![
+                "/other/src/main.rs",
+                "/proj/src/lib.rs",
+                "/proj/target/debug/build.rs"
+            ]
+        );
+        assert_eq!(
+            search("**/src/**/*.toml")?,
+            vec!["/proj/src/nested/config.toml"]
+        );
+        assert_eq!(
+            search("/proj/**/*.{rs,toml}")?,
+            vec![
+                "/proj/Cargo.toml",
+                "/proj/src/lib.rs",
+                "/proj/src/nested/config.toml",
+                "/proj/target/debug/build.rs"
+            ]
+        );
+        assert_eq!(search("/proj/src/*")?, vec!["/proj/src/lib.rs"]);
+        assert!(search("/nowhere/*.rs")?.is_empty());
+
+        // Deletions in memory shadow flushed entries
+        index.delete(&PathBuf::from("/proj/src/lib.rs".replace('/', sep)))?;
+        assert_eq!(search("src/*.rs")?, vec!["/other/src/main.rs"]);
+
+        assert!(matches!(search("[abc"), Err(IndexError::Glob(_))));
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_fuzzy() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_fuzzy_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let names = ["receive_notes.txt", "recieve_typo.txt", "receipt.pdf"];
+        let index = index_with(
+            &temp_dir,
+            names.iter().map(|name| FilesystemEntry {
+                path: PathBuf::from(format!("{}docs{}{}", sep, sep, name)),
+                volume: "vol1".to_string(),
+                last_modified: 1000,
+                last_accessed: 1000,
+                ..Default::default()
+            }),
+            1,
+        )?;
+
+        let fuzzy = SearchOptions {
+            fuzziness: 1,
+            ..Default::default()
+        };
+
+        // Exact matching is unaffected by default
+        let results = index.search("recieve", 10, 0, SearchOptions::default())?;
+        assert_eq!(results.len(), 1);
+
+        let results = index.search("recieve", 10, 0, fuzzy)?;
+        let names: Vec<_> = results
+            .iter()
+            .map(|r| r.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        // Exact matches rank above fuzzy ones
+        assert_eq!(names, vec!["recieve_typo.txt", "receive_notes.txt"]);
+        assert!(results[0].score > results[1].score);
+
+        // Short terms stay strict
+        let results = index.search(
+            "rx",
+            10,
+            0,
+            SearchOptions {
+                fuzziness: 2,
+                ..Default::default()
+            },
+        )?;
+        assert!(results.is_empty());
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_negation() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_neg_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let paths = [
+            "/app/node_modules/index.js",
+            "/app/node/modules_index.js",
+            "/app/src/index.js",
+            "/app/backup/index.js",
+            "/app/src/index.tmp",
+        ];
+        let index = index_with(
+            &temp_dir,
+            paths.iter().map(|path| FilesystemEntry {
+                path: PathBuf::from(path.replace('/', sep)),
+                volume: "vol1".to_string(),
+                last_modified: 1000,
+                last_accessed: 1000,
+                ..Default::default()
+            }),
+            2,
+        )?;
+
+        let search = |query: &str, limit: usize| -> Result<Vec<String>, IndexError> {
+            let mut paths: Vec<_> = index
+                .search(query, limit, 0, SearchOptions::default())?
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().replace(sep, "/"))
+                .collect();
+            paths.sort();
+            Ok(paths)
+        };
+
+        assert_eq!(search("index", 10)?.len(), 5);
+        // Words made of several tokens are only excluded where they're adjacent
+        assert_eq!(
+            search("index -node_modules NOT backup -ext:tmp", 10)?,
+            vec!["/app/node/modules_index.js", "/app/src/index.js"]
+        );
+        // Pages are filled despite the exclusions
+        assert_eq!(search("index -in:/app/src", 3)?.len(), 3);
+        assert!(search("-node_modules", 10)?.is_empty());
+        assert!(matches!(
+            search("index -cat:image", 10),
+            Err(IndexError::Query(QueryError::Negation(_)))
+        ));
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_or_groups() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_or_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let paths = [
+            "/finance/invoice_2024.pdf",
+            "/finance/receipt_2024.docx",
+            "/finance/receipt_2023.pdf",
+            "/finance/report_2024.pdf",
+            "/finance/old_records/invoice_2024.txt",
+        ];
+        let index = index_with(
+            &temp_dir,
+            paths.iter().map(|path| FilesystemEntry {
+                path: PathBuf::from(path.replace('/', sep)),
+                volume: "vol1".to_string(),
+                last_modified: 1000,
+                last_accessed: 1000,
+                ..Default::default()
+            }),
+            2,
+        )?;
+
+        let search = |query: &str| -> Result<Vec<String>, IndexError> {
+            let mut paths: Vec<_> = index
+                .search(query, 10, 0, SearchOptions::default())?
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().replace(sep, "/"))
+                .collect();
+            paths.sort();
+            Ok(paths)
+        };
+
+        assert_eq!(
+            search("(invoice OR receipt) 2024")?,
+            vec![
+                "/finance/invoice_2024.pdf",
+                "/finance/old_records/invoice_2024.txt",
+                "/finance/receipt_2024.docx",
+            ]
+        );
+        assert_eq!(
+            search("(invoice | receipt) (ext:pdf ext:docx) -old")?,
+            vec![
+                "/finance/invoice_2024.pdf",
+                "/finance/receipt_2023.pdf",
+                "/finance/receipt_2024.docx",
+            ]
+        );
+        // Alternatives made of several tokens require all of them
+        assert_eq!(
+            search("(old_records OR report)")?,
+            vec![
+                "/finance/old_records/invoice_2024.txt",
+                "/finance/report_2024.pdf",
+            ]
+        );
+        assert!(search("(missing OR absent)")?.is_empty());
+        assert!(matches!(
+            search("(kind:dir OR report)"),
+            Err(IndexError::Query(QueryError::Group(_)))
+        ));
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_phrases() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_phrase_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let paths = [
+            "/docs/annual/report.pdf",
+            "/docs/AnnualReport_2024.pdf",
+            "/docs/report_annual.pdf",
+            "/docs/annual report 2023.pdf",
+            "/docs/annual/reports/annual_summary.pdf",
+        ];
+        let index = index_with(
+            &temp_dir,
+            paths.iter().enumerate().map(|(i, path)| FilesystemEntry {
+                path: PathBuf::from(path.replace('/', sep)),
+                volume: "vol1".to_string(),
+                last_modified: 1000 + i as u64,
+                last_accessed: 1000 + i as u64,
+                ..Default::default()
+            }),
+            3,
+        )?;
+
+        let search = |query: &str, limit: usize| -> Result<Vec<String>, IndexError> {
+            let mut paths: Vec<_> = index
+                .search(query, limit, 0, SearchOptions::default())?
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().replace(sep, "/"))
+                .collect();
+            paths.sort();
+            Ok(paths)
+        };
+
+        assert_eq!(search("annual report", 10)?.len(), 5);
+        assert_eq!(
+            search("\"annual report\"", 10)?,
+            vec![
+                "/docs/AnnualReport_2024.pdf",
+                "/docs/annual report 2023.pdf"
+            ]
+        );
+        // Pages are filled with phrase matches only
+        assert_eq!(search("\"annual report\"", 1)?.len(), 1);
+        assert_eq!(
+            search("\"report annual\"", 10)?,
+            vec!["/docs/report_annual.pdf"]
+        );
+        assert!(search("\"pdf annual\"", 10)?.is_empty());
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_within() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_within_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let paths = [
+            "/home/me/work/notes.txt",
+            "/home/me/work/src/notes.rs",
+            "/home/me/play/notes.txt",
+            "/home/me/workshop/notes.txt",
+            "/srv/notes.txt",
+        ];
+        let index = index_with(
+            &temp_dir,
+            paths.iter().map(|path| FilesystemEntry {
+                path: PathBuf::from(path.replace('/', sep)),
+                volume: "vol1".to_string(),
+                last_modified: 1_000_000_000,
+                last_accessed: 1_000_000_000,
+                ..Default::default()
+            }),
+            2,
+        )?;
+
+        let sorted = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().replace(sep, "/"))
+                .collect();
+            paths.sort();
+            paths
+        };
+        let within = |dirs: &'static [&'static str]| SearchOptions {
+            within: dirs,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            sorted(index.search("notes", 10, 0, within(&["/home/me/work"]))?),
+            vec!["/home/me/work/notes.txt", "/home/me/work/src/notes.rs"]
+        );
+        assert_eq!(
+            sorted(index.search("notes", 10, 0, within(&["/home/me/work/src/", "/srv"]))?),
+            vec!["/home/me/work/src/notes.rs", "/srv/notes.txt"]
+        );
+        // The root doesn't scope results
+        assert_eq!(index.search("notes", 10, 0, within(&["/"]))?.len(), 5);
+        assert!(
+            index
+                .search("notes", 10, 0, within(&["/nowhere"]))?
+                .is_empty()
+        );
+
+        assert_eq!(
+            sorted(index.search_glob("*.txt", 10, 0, within(&["/home/me/work"]))?),
+            vec!["/home/me/work/notes.txt"]
+        );
+        assert_eq!(
+            sorted(index.recent_files(0, 10, 0, within(&["/home/me/play", "/srv"]))?),
+            vec!["/home/me/play/notes.txt", "/srv/notes.txt"]
+        );
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_date_ranges() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_dates_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entries = [
+            ("/docs/report_old.txt", 1_000, 5_000),
+            ("/docs/report_new.txt", 4_000, 4_000),
+            ("/docs/report_mid.txt", 2_000, 9_000),
+        ];
+        let index = index_with(
+            &temp_dir,
+            entries
+                .into_iter()
+                .map(|(path, modified, accessed)| FilesystemEntry {
+                    path: PathBuf::from(path),
+                    volume: "vol1".to_string(),
+                    last_modified: modified * 1_000_000,
+                    last_accessed: accessed * 1_000_000,
+                    ..Default::default()
+                }),
+            1,
+        )?;
+
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        let options = SearchOptions {
+            modified_range: Some((1_500, 4_000)),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search("report", 10, 0, options)?),
+            vec!["/docs/report_mid.txt", "/docs/report_new.txt"]
+        );
+
+        let options = SearchOptions {
+            modified_range: Some((0, 3_000)),
+            accessed_range: Some((6_000, u64::MAX)),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search("report", 10, 0, options)?),
+            vec!["/docs/report_mid.txt"]
+        );
+
+        // Combined with the query operator, both ranges apply
+        let options = SearchOptions {
+            modified_range: Some((0, 3_000)),
+            ..Default::default()
+        };
+        assert!(
+            index
+                .search("report modified:>1970-01-01", 10, 0, options)?
+                .is_empty()
+        );
+
+        let options = SearchOptions {
+            accessed_range: Some((0, 5_000)),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.recent_files(0, 10, 0, options)?),
+            vec!["/docs/report_new.txt", "/docs/report_old.txt"]
+        );
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_depth() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_depth_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let paths = [
+            ("/projects", Kind::Directory),
+            ("/projects/alpha", Kind::Directory),
+            ("/projects/alpha/beta", Kind::Directory),
+            ("/projects/alpha/beta/gamma", Kind::Directory),
+            ("/archive/projects", Kind::Directory),
+        ];
+        let index = index_with(
+            &temp_dir,
+            paths.into_iter().map(|(path, kind)| FilesystemEntry {
+                path: PathBuf::from(path.replace('/', sep)),
+                volume: "vol1".to_string(),
+                kind,
+                last_modified: 1_000_000_000,
+                last_accessed: 1_000_000_000,
+                ..Default::default()
+            }),
+            2,
+        )?;
+
+        let sorted = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().replace(sep, "/"))
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        let options = SearchOptions {
+            kind: Some(Kind::Directory),
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(index.recent_files(0, 10, 0, options)?),
+            vec!["/archive/projects", "/projects", "/projects/alpha"]
+        );
+
+        let options = SearchOptions {
+            min_depth: Some(2),
+            max_depth: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(index.search("projects", 10, 0, options)?),
+            vec![
+                "/archive/projects",
+                "/projects/alpha",
+                "/projects/alpha/beta"
+            ]
+        );
+
+        // Top-level folders under a subtree root
+        let options = SearchOptions {
+            max_depth: Some(1),
+            depth_root: Some("/projects/"),
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(index.recent_files(0, 10, 0, options)?),
+            vec!["/projects/alpha"]
+        );
+
+        let options = SearchOptions {
+            min_depth: Some(2),
+            depth_root: Some("/projects"),
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(index.search("beta", 10, 0, options)?),
+            vec!["/projects/alpha/beta", "/projects/alpha/beta/gamma"]
+        );
+        let options = SearchOptions {
+            min_depth: Some(2),
+            depth_root: Some("/projects"),
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(index.search_glob("*a", 10, 0, options)?),
+            vec!["/projects/alpha/beta", "/projects/alpha/beta/gamma"]
+        );
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_search_sizes() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_sizes_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entries = [
+            ("/videos/holiday.mp4", 3_000_000_000),
+            ("/videos/clip.mp4", 20_000_000),
+            ("/videos/trailer.mp4", 800_000_000),
+            ("/videos/notes.txt", 2_000),
+        ];
+        let index = index_with(
+            &temp_dir,
+            entries.into_iter().map(|(path, size)| FilesystemEntry {
+                path: PathBuf::from(path),
+                volume: "vol1".to_string(),
+                last_modified: 1_000_000_000,
+                last_accessed: 1_000_000_000,
+                category: category::VIDEO,
+                size,
+                ..Default::default()
+            }),
+            2,
+        )?;
+
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let options = SearchOptions {
+            min_size: Some(1_000_000_000),
+            ..Default::default()
+        };
+        let results = index.search("videos", 10, 0, options)?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].size, 3_000_000_000);
+
+        let options = SearchOptions {
+            min_size: Some(10_000),
+            sort: SortOrder::LargestFirst,
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search("videos", 10, 0, options)?),
+            vec![
+                "/videos/holiday.mp4",
+                "/videos/trailer.mp4",
+                "/videos/clip.mp4"
+            ]
+        );
+
+        let options = SearchOptions {
+            max_size: Some(1_000_000_000),
+            sort: SortOrder::SmallestFirst,
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search_glob("*.mp4", 10, 0, options)?),
+            vec!["/videos/clip.mp4", "/videos/trailer.mp4"]
+        );
+
+        let options = SearchOptions {
+            max_size: Some(10_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.recent_files(0, 10, 0, options)?),
+            vec!["/videos/notes.txt"]
+        );
+
+        // Sizes survive compaction
+        index.force_compact_all()?;
+        let options = SearchOptions {
+            sort: SortOrder::LargestFirst,
+            ..Default::default()
+        };
+        let results = index.search("mp4", 1, 0, options)?;
+        assert_eq!(paths(results), vec!["/videos/holiday.mp4"]);
+
+        // Deleting an entry hides older versions of any size
+        index.insert(FilesystemEntry {
+            path: PathBuf::from("/videos/holiday.mp4"),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000_000,
+            last_accessed: 1_000_000_000,
+            category: category::VIDEO,
+            size: 5_000,
+            ..Default::default()
+        })?;
+        index.delete(Path::new("/videos/holiday.mp4"))?;
+        let large = || SearchOptions {
+            min_size: Some(1_000_000_000),
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            assert!(index.search("videos", 10, 0, large())?.is_empty());
+            assert!(index.search_glob("*.mp4", 10, 0, large())?.is_empty());
+            assert!(index.recent_files(0, 10, 0, large())?.is_empty());
+            index.flush()?;
+        }
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_recently_created() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_created_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entries = [
+            ("/docs/old_report.pdf", 1_685_577_600),   // 2023-06-01
+            ("/docs/report_draft.pdf", 1_705_276_800), // 2024-01-15
+            ("/docs/report_final.pdf", 1_710_028_800), // 2024-03-10
+            ("/docs/unknown_report.pdf", 0),
+        ];
+        let index = index_with(
+            &temp_dir,
+            entries
+                .into_iter()
+                .enumerate()
+                .map(|(i, (path, created_secs))| FilesystemEntry {
+                    path: PathBuf::from(path),
+                    volume: "vol1".to_string(),
+                    // Last use is unrelated to creation order
+                    last_modified: (1_720_000_000 - i as u64) * 1_000_000,
+                    last_accessed: (1_720_000_000 - i as u64) * 1_000_000,
+                    category: category::DOCUMENT,
+                    created: created_secs * 1_000_000,
+                    ..Default::default()
+                }),
+            2,
+        )?;
+
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        assert_eq!(
+            paths(index.recently_created(1_700_000_000, 10, 0, SearchOptions::default())?),
+            vec!["/docs/report_final.pdf", "/docs/report_draft.pdf"]
+        );
+        assert_eq!(
+            paths(index.recently_created(0, 1, 1, SearchOptions::default())?),
+            vec!["/docs/report_draft.pdf"]
+        );
+
+        let options = SearchOptions {
+            created_range: Some((1_600_000_000, 1_706_000_000)),
+            ..Default::default()
+        };
+        let mut results = paths(index.search("report", 10, 0, options)?);
+        results.sort();
+        assert_eq!(
+            results,
+            vec!["/docs/old_report.pdf", "/docs/report_draft.pdf"]
+        );
+
+        let results = index.search("report created:2024-03", 10, 0, SearchOptions::default())?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].created, 1_710_028_800 * 1_000_000);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_tags() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_tags_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entries: [(&str, &[&str]); 4] = [
+            ("/work/alpha/plan.txt", &["project:alpha", "Starred"]),
+            ("/work/alpha/notes.txt", &["project:alpha"]),
+            ("/work/beta/plan.txt", &["project:beta", "starred"]),
+            ("/work/beta/notes.txt", &[]),
+        ];
+
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        {
+            let index = index_with(
+                &temp_dir,
+                entries.into_iter().map(|(path, tags)| FilesystemEntry {
+                    path: PathBuf::from(path),
+                    volume: "vol1".to_string(),
+                    last_modified: 1_000_000,
+                    last_accessed: 1_000_000,
+                    category: category::TEXT,
+                    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                    ..Default::default()
+                }),
+                2,
+            )?;
+
+            assert_eq!(
+                paths(index.search("plan tag:starred", 10, 0, SearchOptions::default())?),
+                vec!["/work/alpha/plan.txt", "/work/beta/plan.txt"]
+            );
+            assert_eq!(
+                paths(index.search("tag:project:alpha", 10, 0, SearchOptions::default())?),
+                vec!["/work/alpha/notes.txt", "/work/alpha/plan.txt"]
+            );
+            assert_eq!(
+                paths(index.search("work -tag:starred", 10, 0, SearchOptions::default())?),
+                vec!["/work/alpha/notes.txt", "/work/beta/notes.txt"]
+            );
+
+            let options = SearchOptions {
+                tags: &["STARRED", "project:beta"],
+                ..Default::default()
+            };
+            let results = index.search("work", 10, 0, options)?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].tags, vec!["project:beta", "starred"]);
+
+            let options = SearchOptions {
+                tags: &["starred"],
+                ..Default::default()
+            };
+            assert_eq!(
+                paths(index.recent_files(0, 10, 0, options)?),
+                vec!["/work/alpha/plan.txt", "/work/beta/plan.txt"]
+            );
+            let options = SearchOptions {
+                tags: &["project:alpha"],
+                ..Default::default()
+            };
+            assert_eq!(
+                paths(index.search_glob("*plan*", 10, 0, options)?),
+                vec!["/work/alpha/plan.txt"]
+            );
+
+            // Tags are not searchable as regular text
+            assert!(
+                index
+                    .search("starred", 10, 0, SearchOptions::default())?
+                    .is_empty()
+            );
+
+            index.sync()?;
+        }
+
+        // Tags survive WAL replay and compaction
+        let index = Index::open(&temp_dir)?;
+        index.wait_for_completed_recovery();
+        index.force_compact_all()?;
+        let results = index.search("plan tag:starred", 10, 0, SearchOptions::default())?;
+        assert_eq!(results.len(), 2);
+        let alpha = results
+            .iter()
+            .find(|r| r.path == Path::new("/work/alpha/plan.txt"))
+            .unwrap();
+        assert_eq!(alpha.tags, vec!["project:alpha", "Starred"]);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_delete_tagged() -> Result<(), IndexError> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("minidex_test_lib_delete_tagged_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entry = |path: &str| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            tags: vec!["starred".to_string()],
+            ..Default::default()
+        };
+        let index = index_with(
+            &temp_dir,
+            [
+                entry("/work/report.txt"),
+                entry("/work/report_draft.txt"),
+                entry("/work/report_final.txt"),
+            ],
+            2,
+        )?;
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        // Deleting entries on disk and in memory
+        index.delete(Path::new("/work/report.txt"))?;
+        index.delete(Path::new("/work/report_final.txt"))?;
+
+        let starred = || SearchOptions {
+            tags: &["starred"],
+            ..Default::default()
+        };
+        let on_volume = || SearchOptions {
+            volume_name: Some("vol1"),
+            ..Default::default()
+        };
+        let expected = vec!["/work/report_draft.txt"];
+        let check = || -> Result<(), IndexError> {
+            assert_eq!(
+                paths(index.search("tag:starred report", 10, 0, SearchOptions::default())?),
+                expected
+            );
+            assert_eq!(paths(index.search("report", 10, 0, on_volume())?), expected);
+            assert_eq!(paths(index.recent_files(0, 10, 0, starred())?), expected);
+            // Deletions carry no timestamps of their own
+            assert_eq!(paths(index.recent_files(1, 10, 0, on_volume())?), expected);
+            assert_eq!(
+                paths(index.search_glob("*report*", 10, 0, on_volume())?),
+                expected
+            );
+            Ok(())
+        };
+        check()?;
+
+        // Deletions keep shadowing once flushed
+        index.flush()?;
+        check()?;
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_reinsert_in_memory() -> Result<(), IndexError> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("minidex_test_lib_reinsert_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entry = |volume: &str, tag: &str, target: &str| FilesystemEntry {
+            path: PathBuf::from("/home/me/link"),
+            volume: volume.to_string(),
+            kind: Kind::Symlink,
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            tags: vec![tag.to_string()],
+            symlink_target: Some(PathBuf::from(target)),
+            ..Default::default()
+        };
+        let index = Index::open(&temp_dir)?;
+        index.insert(entry("vol1", "old", "/mnt/old"))?;
+        index.insert(entry("vol2", "new", "/mnt/new"))?;
+
+        let count = |query: &str| -> Result<usize, IndexError> {
+            Ok(index.search(query, 10, 0, SearchOptions::default())?.len())
+        };
+        for query in ["link tag:old", "link target:/mnt/old", "link vol:vol1"] {
+            assert_eq!(count(query)?, 0, "{query}");
+        }
+        for query in ["link tag:new", "link target:/mnt/new", "link vol:vol2"] {
+            assert_eq!(count(query)?, 1, "{query}");
+        }
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_large_entries() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_large_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        // Well past any fixed decompression buffer, but within the entry limit
+        let tags: Vec<String> = (0..8)
+            .map(|i| format!("{}{}", i, "x".repeat(4000)))
+            .collect();
+        let entries = ["/docs/large.txt", "/docs/after_1.txt", "/docs/after_2.txt"];
+        let index = index_with(
+            &temp_dir,
+            entries.into_iter().map(|path| FilesystemEntry {
+                path: PathBuf::from(path),
+                volume: "vol1".to_string(),
+                last_modified: 1_000_000,
+                last_accessed: 1_000_000,
+                tags: if path.contains("large") {
+                    tags.clone()
+                } else {
+                    Vec::new()
+                },
+                ..Default::default()
+            }),
+            2,
+        )?;
+        index.flush()?;
+        index.force_compact_all()?;
+
+        let results = index.search("large", 10, 0, SearchOptions::default())?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tags, tags);
+        assert_eq!(
+            index
+                .search("after", 10, 0, SearchOptions::default())?
+                .len(),
+            2
+        );
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_find_duplicates() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_dups_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entry = |path: &str, size: u64, content_hash: Option<u128>| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            size,
+            content_hash,
+            ..Default::default()
+        };
+        let index = index_with(
+            &temp_dir,
+            [
+                entry("/dup/bin/a.bin", 100, Some(1)),
+                entry("/dup/bin/b.bin", 100, Some(1)),
+                entry("/dup/iso/x.iso", 1000, Some(2)),
+                entry("/dup/iso/y.iso", 1000, Some(2)),
+                entry("/dup/bin/c.bin", 100, Some(1)),
+                entry("/dup/unique.txt", 100, Some(3)),
+                entry("/dup/unhashed.txt", 100, None),
+            ],
+            4,
+        )?;
+
+        let groups = |index: &Index, options| -> Result<Vec<(u128, Vec<String>)>, IndexError> {
+            Ok(index
+                .find_duplicates(10, 0, options)?
+                .into_iter()
+                .map(|group| {
+                    let paths = group
+                        .entries
+                        .iter()
+                        .map(|r| r.path.to_string_lossy().into_owned())
+                        .collect();
+                    (group.content_hash, paths)
+                })
+                .collect())
+        };
+
+        // Redundant isos take more space than redundant bins
+        assert_eq!(
+            groups(&index, SearchOptions::default())?,
+            vec![
+                (2, vec!["/dup/iso/x.iso".into(), "/dup/iso/y.iso".into()]),
+                (
+                    1,
+                    vec![
+                        "/dup/bin/a.bin".into(),
+                        "/dup/bin/b.bin".into(),
+                        "/dup/bin/c.bin".into()
+                    ]
+                ),
+            ]
+        );
+
+        let options = SearchOptions {
+            within: &["/dup/bin"],
+            ..Default::default()
+        };
+        assert_eq!(groups(&index, options)?.len(), 1);
+        let options = SearchOptions {
+            min_size: Some(500),
+            ..Default::default()
+        };
+        assert_eq!(groups(&index, options)?[0].0, 2);
+
+        // Only the latest version of an entry counts
+        index.insert(entry("/dup/bin/b.bin", 100, Some(4)))?;
+        index.delete(Path::new("/dup/iso/y.iso"))?;
+
+        let expected = vec![(1, vec!["/dup/bin/a.bin".into(), "/dup/bin/c.bin".into()])];
+        assert_eq!(groups(&index, SearchOptions::default())?, expected);
+
+        index.force_compact_all()?;
+        assert_eq!(groups(&index, SearchOptions::default())?, expected);
+
+        // Scopes apply to the latest version of each entry
+        let tagged = |path: &str, tags: Vec<String>| FilesystemEntry {
+            tags,
+            ..entry(path, 100, Some(1))
+        };
+        index.insert(tagged("/dup/bin/a.bin", vec!["keep".to_string()]))?;
+        index.insert(tagged("/dup/bin/c.bin", vec!["keep".to_string()]))?;
+        index.flush()?;
+        let keep = || SearchOptions {
+            tags: &["keep"],
+            ..Default::default()
+        };
+        assert_eq!(groups(&index, keep())?, expected);
+        index.insert(tagged("/dup/bin/c.bin", Vec::new()))?;
+        assert!(groups(&index, keep())?.is_empty());
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_index_symlink_targets() -> Result<(), IndexError> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("minidex_test_lib_symlinks_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entries = [
+            ("/home/me/data_link", Kind::Symlink, Some("/mnt/data")),
+            (
+                "/home/me/report_link",
+                Kind::Symlink,
+                Some("../../mnt/data/report.pdf"),
+            ),
+            ("/home/me/other_link", Kind::Symlink, Some("/mnt/database")),
+            ("/mnt/data/report.pdf", Kind::File, None),
+        ];
+        let index = index_with(
+            &temp_dir,
+            entries
+                .into_iter()
+                .map(|(path, kind, target)| FilesystemEntry {
+                    path: PathBuf::from(path),
+                    volume: "vol1".to_string(),
+                    kind,
+                    last_modified: 1_000_000,
+                    last_accessed: 1_000_000,
+                    symlink_target: target.map(PathBuf::from),
+                    ..Default::default()
+                }),
+            2,
+        )?;
+
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        let results = index.search("report_link", 10, 0, SearchOptions::default())?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].symlink_target,
+            Some(PathBuf::from("../../mnt/data/report.pdf"))
+        );
+        assert_eq!(
+            results[0].resolved_symlink_target(),
+            Some(PathBuf::from("/mnt/data/report.pdf"))
+        );
+
+        // Links to the directory itself and to entries under it
+        assert_eq!(
+            paths(index.search("target:/mnt/data", 10, 0, SearchOptions::default())?),
+            vec!["/home/me/data_link", "/home/me/report_link"]
+        );
+        assert_eq!(
+            paths(index.search("link -target:/mnt/data", 10, 0, SearchOptions::default())?),
+            vec!["/home/me/other_link"]
+        );
+
+        let options = SearchOptions {
+            symlink_target_within: &["/mnt/data/report.pdf", "/mnt/database/"],
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.recent_files(0, 10, 0, options)?),
+            vec!["/home/me/other_link", "/home/me/report_link"]
+        );
+
+        // Targets survive compaction
+        index.force_compact_all()?;
+        let options = SearchOptions {
+            symlink_target_within: &["/mnt"],
+            ..Default::default()
+        };
+        assert_eq!(index.search_glob("*link", 10, 0, options)?.len(), 3);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_ownership_and_hidden() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_owner_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entries = [
+            ("/srv/app/run.sh", Kind::File, 1000, 0o100755, false),
+            ("/srv/app/config.toml", Kind::File, 1000, 0o100644, false),
+            ("/srv/app/.env", Kind::File, 0, 0o100600, false),
+            ("/srv/app/.git/HEAD", Kind::File, 1000, 0o100644, false),
+            ("/srv/app/bin", Kind::Directory, 0, 0o40755, false),
+            ("/srv/app/secret.key", Kind::File, 0, 0o100600, true),
+        ];
+        let index = index_with(
+            &temp_dir,
+            entries
+                .into_iter()
+                .map(|(path, kind, uid, mode, hidden)| FilesystemEntry {
+                    path: PathBuf::from(path),
+                    volume: "vol1".to_string(),
+                    kind,
+                    last_modified: 1_000_000,
+                    last_accessed: 1_000_000,
+                    uid: Some(uid),
+                    gid: Some(uid),
+                    mode: Some(mode),
+                    hidden,
+                    ..Default::default()
+                }),
+            3,
+        )?;
+
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        // Hidden entries are left out by default
+        assert_eq!(
+            paths(index.search("app", 10, 0, SearchOptions::default())?),
+            vec!["/srv/app/bin", "/srv/app/config.toml", "/srv/app/run.sh"]
+        );
+        let options = SearchOptions {
+            include_hidden: true,
+            ..Default::default()
+        };
+        let results = index.search("env", 10, 0, options)?;
+        assert_eq!(results.len(), 1);
+        assert!(results[0].hidden);
+        assert_eq!((results[0].uid, results[0].mode), (Some(0), Some(0o100600)));
+
+        let options = SearchOptions {
+            owner: Some(1000),
+            include_hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search("app", 10, 0, options)?),
+            vec![
+                "/srv/app/.git/HEAD",
+                "/srv/app/config.toml",
+                "/srv/app/run.sh"
+            ]
+        );
+
+        let options = SearchOptions {
+            executable_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search_glob("/srv/app/*", 10, 0, options)?),
+            vec!["/srv/app/run.sh"]
+        );
+
+        // Ownership and hidden flags survive compaction
+        index.force_compact_all()?;
+        let options = SearchOptions {
+            group: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.recent_files(0, 10, 0, options)?),
+            vec!["/srv/app/bin"]
+        );
+        let options = SearchOptions {
+            group: Some(0),
+            include_hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(index.recent_files(0, 10, 0, options)?.len(), 3);
+
+        // Deleting an entry hides older versions with another owner or mode
+        index.insert(FilesystemEntry {
+            path: PathBuf::from("/srv/app/run.sh"),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            uid: Some(5),
+            gid: Some(5),
+            mode: Some(0o100644),
+            ..Default::default()
+        })?;
+        index.delete(Path::new("/srv/app/run.sh"))?;
+        let owned = || SearchOptions {
+            owner: Some(1000),
+            ..Default::default()
+        };
+        let executable = || SearchOptions {
+            executable_only: true,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            assert_eq!(
+                paths(index.search("app", 10, 0, owned())?),
+                vec!["/srv/app/config.toml"]
+            );
+            assert!(
+                index
+                    .search_glob("/srv/app/*", 10, 0, executable())?
+                    .is_empty()
+            );
+            index.flush()?;
+        }
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_rename() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_rename_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let file_id = FileId { dev: 1, inode: 42 };
+        let entry = |path: &str, tags: Vec<String>, file_id| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 5_000_000,
+            size: 10,
+            tags,
+            file_id,
+            ..Default::default()
+        };
+
+        {
+            let index = Index::open(&temp_dir)?;
+            index.insert(entry(
+                "/home/me/notes.txt",
+                vec!["starred".to_string()],
+                Some(file_id),
+            ))?;
+            index.insert(entry("/home/me/other.txt", Vec::new(), None))?;
+            // The renamed entry is on disk
+            index.flush()?;
+
+            assert!(index.rename(
+                Path::new("/home/me/notes.txt"),
+                Path::new("/home/me/archive/journal.txt")
+            )?);
+            assert!(!index.rename(
+                Path::new("/home/me/notes.txt"),
+                Path::new("/home/me/elsewhere.txt")
+            )?);
+            assert!(!index.rename(Path::new("/home/me/missing.txt"), Path::new("/tmp/x"))?);
+
+            assert!(
+                index
+                    .search("notes", 10, 0, SearchOptions::default())?
+                    .is_empty()
+            );
+            let results = index.search("journal", 10, 0, SearchOptions::default())?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(
+                results[0].path,
+                PathBuf::from("/home/me/archive/journal.txt")
+            );
+            assert_eq!(results[0].volume, "vol1");
+            assert_eq!(results[0].tags, vec!["starred"]);
+            assert_eq!(results[0].last_accessed, 5_000_000);
+            assert_eq!(results[0].file_id, Some(file_id));
+
+            let results = index.find_by_file_id(file_id)?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(
+                results[0].path,
+                PathBuf::from("/home/me/archive/journal.txt")
+            );
+
+            // The renamed entry is in memory
+            assert!(index.rename(
+                Path::new("/home/me/archive/journal.txt"),
+                Path::new("/home/me/final.txt")
+            )?);
+
+            index.sync()?;
+        }
+
+        // Reopen index and verify the WAL replays the renames in order
+        {
+            let index = Index::open(&temp_dir)?;
+            index.wait_for_completed_recovery();
+
+            let options = SearchOptions {
+                tags: &["starred"],
+                ..Default::default()
+            };
+            let results = index.search("home", 10, 0, options)?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].path, PathBuf::from("/home/me/final.txt"));
+            assert_eq!(results[0].file_id, Some(file_id));
+            assert_eq!(
+                index.search("txt", 10, 0, SearchOptions::default())?.len(),
+                2
+            );
+
+            let paths = |file_id| -> Result<Vec<PathBuf>, IndexError> {
+                Ok(index
+                    .find_by_file_id(file_id)?
+                    .into_iter()
+                    .map(|result| result.path)
+                    .collect())
+            };
+            assert_eq!(paths(file_id)?, vec![PathBuf::from("/home/me/final.txt")]);
+
+            // Hard links share the identity
+            index.insert(entry("/home/me/link.txt", Vec::new(), Some(file_id)))?;
+            index.flush()?;
+            assert_eq!(
+                paths(file_id)?,
+                vec![
+                    PathBuf::from("/home/me/final.txt"),
+                    PathBuf::from("/home/me/link.txt")
+                ]
+            );
+
+            // A file replaced by another no longer has the identity
+            let other_id = FileId { dev: 1, inode: 43 };
+            index.insert(entry("/home/me/final.txt", Vec::new(), Some(other_id)))?;
+            assert_eq!(paths(file_id)?, vec![PathBuf::from("/home/me/link.txt")]);
+            assert_eq!(paths(other_id)?, vec![PathBuf::from("/home/me/final.txt")]);
+
+            index.delete(Path::new("/home/me/link.txt"))?;
+            assert!(paths(file_id)?.is_empty());
+        }
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_update_fields() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_update_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entry = |path: &str, tags: Vec<String>| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 5_000_000,
+            size: 10,
+            tags,
+            uid: Some(1000),
+            ..Default::default()
+        };
+
+        {
+            let index = Index::open(&temp_dir)?;
+            index.insert(entry("/home/me/report.pdf", vec!["starred".to_string()]))?;
+            index.insert(entry("/home/me/old.txt", Vec::new()))?;
+            // The touched entry is on disk
+            index.flush()?;
+
+            assert!(index.touch(Path::new("/home/me/report.pdf"), 2_000_000_000)?);
+            assert!(!index.touch(Path::new("/home/me/missing.txt"), 2_000_000_000)?);
+
+            let results = index.recent_files(1000, 10, 0, SearchOptions::default())?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].path, PathBuf::from("/home/me/report.pdf"));
+            assert_eq!(results[0].last_accessed, 2_000_000_000);
+            assert_eq!(results[0].tags, vec!["starred"]);
+            assert_eq!(results[0].size, 10);
+            assert_eq!(results[0].uid, Some(1000));
+
+            // The updated entry is in memory
+            index.insert(entry("/home/me/draft.txt", Vec::new()))?;
+            let update = EntryUpdate {
+                last_modified: Some(3_000_000_000),
+                size: Some(4096),
+                ..Default::default()
+            };
+            assert!(index.update_fields(Path::new("/home/me/draft.txt"), &update)?);
+
+            let results = index.search("draft", 10, 0, SearchOptions::default())?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].last_modified, 3_000_000_000);
+            assert_eq!(results[0].last_accessed, 5_000_000);
+            assert_eq!(results[0].size, 4096);
+
+            index.sync()?;
+        }
+
+        // Reopen index and verify the WAL replays the updates
+        {
+            let index = Index::open(&temp_dir)?;
+            index.wait_for_completed_recovery();
+
+            // Both entries were used after 1000s, `draft.txt` by its modification
+            let results = index.recent_files(1000, 10, 0, SearchOptions::default())?;
+            assert_eq!(results.len(), 2);
+            let report = results
+                .iter()
+                .find(|result| result.path == Path::new("/home/me/report.pdf"))
+                .unwrap();
+            assert_eq!(report.last_accessed, 2_000_000_000);
+            assert_eq!(report.tags, vec!["starred"]);
+
+            let options = SearchOptions {
+                min_size: Some(1024),
+                ..Default::default()
+            };
+            let results = index.search("home", 10, 0, options)?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].path, PathBuf::from("/home/me/draft.txt"));
+            assert_eq!(results[0].last_modified, 3_000_000_000);
+            assert_eq!(
+                index.search("txt", 10, 0, SearchOptions::default())?.len(),
+                2
+            );
+        }
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_category_classifier() -> Result<(), IndexError> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("minidex_test_lib_classifier_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let mut classifier = CategoryClassifier::default();
+        let model = classifier.add_category("model").unwrap();
+        classifier.set_extension("gguf", model);
+
+        let entry = |path: &str, kind: Kind, category: u8| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            kind,
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            category,
+            ..Default::default()
+        };
+
+        let index = Index::open(&temp_dir)?.with_classifier(classifier);
+        index.insert(entry("/data/photo.JPG", Kind::File, category::OTHER))?;
+        index.insert(entry("/data/llama.gguf", Kind::File, category::OTHER))?;
+        index.flush()?;
+        index.insert_batch(
+            [
+                // Explicit categories are kept
+                entry("/data/notes.txt", Kind::File, category::DOCUMENT),
+                entry("/data/backup.zip", Kind::Directory, category::OTHER),
+                entry("/data/unknown.xyz", Kind::File, category::OTHER),
+            ],
+            10,
+        )?;
+
+        let results = index.search("data", 10, 0, SearchOptions::default())?;
+        let category_of = |name: &str| {
+            results
+                .iter()
+                .find(|result| result.path.ends_with(name))
+                .map(|result| result.category)
+        };
+        assert_eq!(category_of("photo.JPG"), Some(category::IMAGE));
+        assert_eq!(category_of("llama.gguf"), Some(model));
+        assert_eq!(category_of("notes.txt"), Some(category::DOCUMENT));
+        assert_eq!(category_of("backup.zip"), Some(category::OTHER));
+        assert_eq!(category_of("unknown.xyz"), Some(category::OTHER));
+
+        // Custom categories are understood by queries
+        let results = index.search("data cat:model|image", 10, 0, SearchOptions::default())?;
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            index.search("data cat:font", 10, 0, SearchOptions::default()),
+            Err(IndexError::Query(QueryError::InvalidValue(..)))
+        ));
+
+        index.sync()?;
+        drop(index);
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_rename_prefix() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_renpre_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let p = |path: &str| path.replace('/', std::path::MAIN_SEPARATOR_STR);
+        let entry = |path: &str, tags: Vec<String>| FilesystemEntry {
+            path: PathBuf::from(p(path)),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            size: 10,
+            tags,
+            ..Default::default()
+        };
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        {
+            let index = index_with(
+                &temp_dir,
+                [
+                    // Prefixes are matched exactly, leaving a sibling
+                    // differing in case in place
+                    entry("/home/me/Photos/f.jpg", Vec::new()),
+                    entry("/home/me/photos/a.jpg", vec!["starred".to_string()]),
+                    entry("/home/me/photos/2024/b.jpg", Vec::new()),
+                    entry("/home/me/photoshop/c.jpg", Vec::new()),
+                    entry("/home/me/photos/d.jpg", Vec::new()),
+                ],
+                4,
+            )?;
+
+            index.rename_prefix(None, &p("/home/me/photos"), &p("/home/me/pictures/"))?;
+
+            let expected = vec![
+                p("/home/me/Photos/f.jpg"),
+                p("/home/me/photoshop/c.jpg"),
+                p("/home/me/pictures/2024/b.jpg"),
+                p("/home/me/pictures/a.jpg"),
+                p("/home/me/pictures/d.jpg"),
+            ];
+            assert_eq!(
+                paths(index.search("jpg", 10, 0, SearchOptions::default())?),
+                expected
+            );
+
+            // Moved entries keep their tags, and can be moved again
+            let options = SearchOptions {
+                tags: &["starred"],
+                ..Default::default()
+            };
+            let results = index.search("a", 10, 0, options)?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].path, PathBuf::from(p("/home/me/pictures/a.jpg")));
+            assert!(index.rename(
+                Path::new(&p("/home/me/pictures/a.jpg")),
+                Path::new(&p("/home/me/a.jpg"))
+            )?);
+
+            // Files added under the old name after the move stay there
+            index.insert(entry("/home/me/photos/e.jpg", Vec::new()))?;
+
+            index.sync()?;
+        }
+
+        let expected = vec![
+            p("/home/me/Photos/f.jpg"),
+            p("/home/me/a.jpg"),
+            p("/home/me/photos/e.jpg"),
+            p("/home/me/photoshop/c.jpg"),
+            p("/home/me/pictures/2024/b.jpg"),
+            p("/home/me/pictures/d.jpg"),
+        ];
+
+        // Reopen index and verify the WAL replays the move
+        {
+            let index = Index::open(&temp_dir)?;
+            index.wait_for_completed_recovery();
+            assert_eq!(
+                paths(index.search("jpg", 10, 0, SearchOptions::default())?),
+                expected
+            );
+
+            // Compaction materializes the move, so the new name is searchable
+            index.force_compact_all()?;
+            assert_eq!(
+                paths(index.search("jpg", 10, 0, SearchOptions::default())?),
+                expected
+            );
+            assert_eq!(
+                paths(index.search("pictures", 10, 0, SearchOptions::default())?),
+                vec![
+                    p("/home/me/pictures/2024/b.jpg"),
+                    p("/home/me/pictures/d.jpg")
+                ]
+            );
+            assert!(index.prefix_renames.read().unwrap().is_empty());
+        }
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_rename_prefix_scopes() -> Result<(), IndexError> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("minidex_test_lib_renscope_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let p = |path: &str| path.replace('/', std::path::MAIN_SEPARATOR_STR);
+        let entry = |path: &str| FilesystemEntry {
+            path: PathBuf::from(p(path)),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            ..Default::default()
+        };
+        let paths = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut paths: Vec<_> = results
+                .into_iter()
+                .map(|r| r.path.to_string_lossy().into_owned())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        // Moved entries stay on disk at their previous path until compaction,
+        // while those in memory are moved right away
+        let index = index_with(
+            &temp_dir,
+            [
+                entry("/home/me/photos/a.jpg"),
+                entry("/home/me/photos/2024/b.jpg"),
+                entry("/tmp/import/c.jpg"),
+                entry("/tmp/import/e.jpg"),
+            ],
+            3,
+        )?;
+        index.rename_prefix(None, &p("/home/me/photos"), &p("/home/me/pictures"))?;
+        index.rename_prefix(None, &p("/tmp/import"), &p("/home/me/pictures/import"))?;
+        index.insert(entry("/home/me/photos/d.jpg"))?;
+
+        let (pictures, photos) = (p("/home/me/pictures"), p("/home/me/photos"));
+        let within = |dir: &str| -> Result<Vec<String>, IndexError> {
+            let options = SearchOptions {
+                within: &[dir],
+                ..Default::default()
+            };
+            Ok(paths(index.search("jpg", 10, 0, options)?))
+        };
+
+        // Moved entries are found by their new location, and only by it
+        let moved = vec![
+            p("/home/me/pictures/2024/b.jpg"),
+            p("/home/me/pictures/a.jpg"),
+            p("/home/me/pictures/import/c.jpg"),
+            p("/home/me/pictures/import/e.jpg"),
+        ];
+        assert_eq!(within(&pictures)?, moved);
+        assert_eq!(within(&photos)?, vec![p("/home/me/photos/d.jpg")]);
+        assert!(within(&p("/tmp"))?.is_empty());
+
+        let options = SearchOptions {
+            within: &[pictures.as_str()],
+            ..Default::default()
+        };
+        assert_eq!(paths(index.recent_files(0, 10, 0, options)?), moved);
+        let options = SearchOptions {
+            within: &[pictures.as_str()],
+            ..Default::default()
+        };
+        assert_eq!(paths(index.search_glob("*.jpg", 10, 0, options)?), moved);
+
+        // Depths are counted from the new location
+        let options = SearchOptions {
+            depth_root: Some(&pictures),
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search("jpg", 10, 0, options)?),
+            vec![p("/home/me/pictures/a.jpg")]
+        );
+        let options = SearchOptions {
+            min_depth: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search("jpg", 10, 0, options)?),
+            vec![
+                p("/home/me/pictures/2024/b.jpg"),
+                p("/home/me/pictures/import/c.jpg"),
+                p("/home/me/pictures/import/e.jpg")
+            ]
+        );
+
+        drop(index);
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_kept_frozen_wals() -> Result<(), IndexError> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_lib_frozen_{}", rand_id()));
+        let other_dir = temp_dir.join("other");
+        std::fs::create_dir_all(&other_dir)?;
+
+        let entry = |path: &str| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            ..Default::default()
+        };
+
+        // A WAL left frozen by a recovery that failed, next to a new journal
+        Index::open(&temp_dir)?.insert(entry("/data/kept.txt"))?;
+        std::fs::rename(
+            temp_dir.join("journal.wal"),
+            temp_dir.join("journal.wal.frozen"),
+        )?;
+        Index::open(&other_dir)?.insert(entry("/data/journal.txt"))?;
+        std::fs::rename(other_dir.join("journal.wal"), temp_dir.join("journal.wal"))?;
+
+        {
+            let index = Index::open(&temp_dir)?;
+            index.wait_for_completed_recovery();
+
+            let mut paths: Vec<_> = index
+                .search("data", 10, 0, SearchOptions::default())?
+                .into_iter()
+                .map(|r| r.path)
+                .collect();
+            paths.sort();
+            assert_eq!(
+                paths,
+                vec![
+                    PathBuf::from("/data/journal.txt"),
+                    PathBuf::from("/data/kept.txt")
+                ]
+            );
+        }
+
+        let frozen = std::fs::read_dir(&temp_dir)?
+            .flatten()
+            .any(|e| e.file_name().to_string_lossy().ends_with(".frozen"));
+        assert!(!frozen);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_manifest() -> Result<(), IndexError> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("minidex_test_lib_manifest_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let entry = |path: String| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            last_modified: 100,
+            last_accessed: 100,
+            ..Default::default()
+        };
+
+        let stale = temp_dir.join("1");
+        {
+            let index = Index::open(&temp_dir)?;
+            index.insert(entry(format!("{sep}old{sep}a.txt")))?;
+            index.flush()?;
+
+            // Keep a copy of the segment, as a crash before compaction
+            // removed it would
+            let segment = index.base.load().snapshot()[0].name();
+            let live = Segment::to_paths(&temp_dir.join(segment));
+            Segment::rename_files(&live, &Segment::to_paths(&stale))?;
+            std::fs::copy(stale.with_extension("seg"), &live.0)?;
+            std::fs::copy(stale.with_extension("dat"), &live.1)?;
+            std::fs::copy(stale.with_extension("post"), &live.2)?;
+            std::fs::copy(stale.with_extension("meta"), &live.3)?;
+
+            index.delete(&PathBuf::from(format!("{sep}old{sep}a.txt")))?;
+            index.insert(entry(format!("{sep}new{sep}b.txt")))?;
+            index.force_compact_all()?;
+
+            let manifest = Manifest::load(&temp_dir)
+                .map_err(IndexError::SegmentedIndex)?
+                .expect("manifest should be written");
+            assert_eq!(manifest.segments.len(), 1);
+
+            // Tombstones not yet applied by a compaction are recorded
+            index.delete_prefix(&format!("{sep}gone"))?;
+            index.insert(entry(format!("{sep}new{sep}c.txt")))?;
+            index.flush()?;
+
+            let manifest = Manifest::load(&temp_dir)
+                .map_err(IndexError::SegmentedIndex)?
+                .expect("manifest should be written");
+            assert_eq!(manifest.segments.len(), 2);
+            assert_eq!(manifest.tombstones.len(), 1);
+            assert!(manifest.next_op_seq > manifest.tombstones[0].2);
+            index.sync()?;
+        }
+
+        // Files left over from interrupted flushes and compactions are
+        // ignored and removed
+        assert!(stale.with_extension("seg").exists());
+        std::fs::write(temp_dir.join("2.tmp.dat"), b"partial")?;
+        {
+            let index = Index::open(&temp_dir)?;
+            index.wait_for_completed_recovery();
+
+            assert!(!stale.with_extension("seg").exists());
+            assert!(!temp_dir.join("2.tmp.dat").exists());
+            let results = index.search("txt", 10, 0, SearchOptions::default())?;
+            assert_eq!(results.len(), 2);
+            assert!(
+                results
+                    .iter()
+                    .all(|result| result.path.starts_with(format!("{sep}new")))
+            );
+            // Tombstones both in the manifest and the WAL are kept once
+            assert_eq!(index.prefix_tombstones.read().unwrap().len(), 1);
+
+            // Opstamps never go below the recorded high-water mark
+            let manifest = Manifest {
+                next_op_seq: u64::MAX / 2,
+                ..Manifest::load(&temp_dir)
+                    .map_err(IndexError::SegmentedIndex)?
+                    .expect("manifest should be written")
+            };
+            manifest.store(&temp_dir)?;
+            index.sync()?;
+        }
+
+        let index = Index::open(&temp_dir)?;
+        index.wait_for_completed_recovery();
+        assert!(index.next_op_seq.load(Ordering::SeqCst) >= u64::MAX / 2);
+        drop(index);
+
+        // Indexes written before manifests existed get one listing their
+        // segments
+        std::fs::remove_file(temp_dir.join(segmented_index::manifest::MANIFEST_FILE))?;
+        for _ in 0..2 {
+            let index = Index::open(&temp_dir)?;
+            index.wait_for_completed_recovery();
+            let results = index.search("txt", 10, 0, SearchOptions::default())?;
+            assert_eq!(results.len(), 2);
+        }
+        let manifest = Manifest::load(&temp_dir)
+            .map_err(IndexError::SegmentedIndex)?
+            .expect("manifest should be written");
+        assert_eq!(manifest.segments.len(), 2);
+        assert!(manifest.segments.iter().all(|name| !name.contains('.')));
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_compaction_manifest_failure() -> Result<(), IndexError> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("minidex_test_lib_compfail_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let entry = |path: &str| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            last_modified: 1_000_000,
+            last_accessed: 1_000_000,
+            ..Default::default()
+        };
+
+        {
+            let index = index_with(
+                &temp_dir,
+                [entry("/data/first.txt"), entry("/data/second.txt")],
+                1,
+            )?;
+            index.flush()?;
+
+            // The manifest can't be stored while its temporary path is taken
+            std::fs::create_dir(Manifest::tmp_path(&temp_dir))?;
+            assert!(index.force_compact_all().is_err());
+        }
+        std::fs::remove_dir(Manifest::tmp_path(&temp_dir))?;
+
+        // The manifest still lists the replaced segments, which were kept
+        let index = Index::open(&temp_dir)?;
+        assert!(index.quarantined_segments().is_empty());
+        assert_eq!(
+            index.search("data", 10, 0, SearchOptions::default())?.len(),
+            2
+        );
+
+        drop(index);
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_quarantine() -> Result<(), IndexError> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("minidex_test_lib_quarantine_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let entry = |path: String| FilesystemEntry {
+            path: PathBuf::from(path),
+            volume: "vol1".to_string(),
+            last_modified: 100,
+            last_accessed: 100,
+            ..Default::default()
+        };
+
+        let corrupt = {
+            let index = Index::open(&temp_dir)?;
+            index.insert(entry(format!("{sep}bad{sep}a.txt")))?;
+            index.flush()?;
+            let corrupt = index.base.load().snapshot()[0].name();
+            index.insert(entry(format!("{sep}good{sep}b.txt")))?;
+            index.flush()?;
+            index.sync()?;
+            assert!(index.quarantined_segments().is_empty());
+            corrupt
+        };
+
+        let post_path = temp_dir.join(&corrupt).with_extension("post");
+        let mut post = std::fs::read(&post_path)?;
+        post[8] ^= 1;
+        std::fs::write(&post_path, &post)?;
+
+        {
+            let index = Index::open(&temp_dir)?;
+            index.wait_for_completed_recovery();
+
+            // The corrupt segment is moved aside instead of being served
+            let quarantined = temp_dir.join("quarantine").join(&corrupt);
+            assert_eq!(index.quarantined_segments(), vec![quarantined.clone()]);
+            assert!(quarantined.with_extension("post").exists());
+            assert!(quarantined.with_extension("seg").exists());
+            assert!(!post_path.exists());
+
+            let results = index.search("txt", 10, 0, SearchOptions::default())?;
+            assert_eq!(results.len(), 1);
+            assert!(results[0].path.starts_with(format!("{sep}good")));
+
+            let manifest = Manifest::load(&temp_dir)
+                .map_err(IndexError::SegmentedIndex)?
+                .expect("manifest should be written");
+            assert!(!manifest.segments.contains(&corrupt));
+        }
+
+        // Later opens no longer look for it
+        let index = Index::open(&temp_dir)?;
+        index.wait_for_completed_recovery();
+        assert!(index.quarantined_segments().is_empty());
+        drop(index);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
     fn rand_id() -> u64 {
         crate::sync::time::SystemTime::now()
             .duration_since(crate::sync::time::UNIX_EPOCH)
@@ -1,6 +1,6 @@
 use std::collections::{BTreeMap, HashMap};
 
-use crate::{Kind, entry::IndexEntry, segmented_index::SegmentedIndex};
+use crate::{entry::IndexEntry, segmented_index::DocMeta};
 
 /// The in-memory data structures, containing
 /// an inverted index for fast lookups
@@ -17,13 +17,18 @@ pub(crate) struct MemTable {
     // FST staging area (sorted for flusher to work)
     pub entries: BTreeMap<String, (String, IndexEntry)>,
 
-    // Mapping to u128 metadata for pre-filtering
-    pub metadata: Vec<u128>,
+    // Mapping to document metadata for pre-filtering
+    pub metadata: Vec<DocMeta>,
 }
 
 impl MemTable {
     pub fn insert(&mut self, path: String, volume: String, entry: IndexEntry) {
-        let tokens = crate::tokenizer::tokenize(&path);
+        // Paths already in memory keep their path tokens
+        let tokens = if self.path_to_id.contains_key(&path) {
+            Vec::new()
+        } else {
+            crate::tokenizer::extract_all_tokens(&path, &volume, &entry)
+        };
         self.insert_with_tokens(path, volume, entry, tokens);
     }
 
@@ -34,22 +39,22 @@ impl MemTable {
         entry: IndexEntry,
         tokens: Vec<String>,
     ) {
-        if let Some(id) = self.path_to_id.get(&path) {
-            self.id_to_data
-                .insert(*id, (path.clone(), volume.clone(), entry));
-            let depth = path
-                .bytes()
-                .filter(|&b| b == std::path::MAIN_SEPARATOR as u8)
-                .count() as u16;
-            self.metadata[*id as usize] = SegmentedIndex::pack_u128(
-                *id as u64,
-                entry.last_modified / 1_000_000,
-                entry.last_accessed / 1_000_000,
-                depth,
-                entry.kind == Kind::Directory,
-                entry.category,
-                entry.volume_type as u8,
-            );
+        if let Some(&id) = self.path_to_id.get(&path) {
+            self.metadata[id as usize] = DocMeta::new(id as u64, &path, &entry);
+            if let Some((_, previous_volume, previous_entry)) = self
+                .id_to_data
+                .insert(id, (path.clone(), volume.clone(), entry.clone()))
+            {
+                self.update_entry_tokens(
+                    id,
+                    crate::tokenizer::extract_entry_tokens(
+                        &path,
+                        &previous_volume,
+                        &previous_entry,
+                    ),
+                    crate::tokenizer::extract_entry_tokens(&path, &volume, &entry),
+                );
+            }
             self.entries.insert(path, (volume, entry));
             return;
         }
@@ -58,23 +63,10 @@ impl MemTable {
         self.next_id += 1;
 
         self.path_to_id.insert(path.clone(), id);
+        // The id is okay as dat offset, because it's only used for tie-breaking
+        self.metadata.push(DocMeta::new(id as u64, &path, &entry));
         self.id_to_data
-            .insert(id, (path.clone(), volume.clone(), entry));
-        let depth = path
-            .bytes()
-            .filter(|&b| b == std::path::MAIN_SEPARATOR as u8)
-            .count() as u16;
-        let meta = SegmentedIndex::pack_u128(
-            id as u64, // This is okay, because it's only used for tie-breaking
-            entry.last_modified / 1_000_000,
-            entry.last_accessed / 1_000_000,
-            depth,
-            entry.kind == Kind::Directory,
-            entry.category,
-            entry.volume_type as u8,
-        );
-
-        self.metadata.push(meta);
+            .insert(id, (path.clone(), volume.clone(), entry.clone()));
 
         for token in tokens {
             self.inverted_index.entry(token).or_default().push(id);
@@ -83,6 +75,39 @@ impl MemTable {
         self.entries.insert(path, (volume, entry));
     }
 
+    /// Moves a document from the postings of its previous entry tokens to
+    /// those of its new ones. Path tokens never change for a given path.
+    fn update_entry_tokens(&mut self, id: u32, mut previous: Vec<String>, mut tokens: Vec<String>) {
+        previous.sort_unstable();
+        previous.dedup();
+        tokens.sort_unstable();
+        tokens.dedup();
+        if previous == tokens {
+            return;
+        }
+
+        for token in previous.iter().filter(|t| tokens.binary_search(t).is_err()) {
+            if let Some(ids) = self.inverted_index.get_mut(token) {
+                if let Ok(pos) = ids.binary_search(&id) {
+                    ids.remove(pos);
+                }
+                if ids.is_empty() {
+                    self.inverted_index.remove(token);
+                }
+            }
+        }
+
+        // Posting lists stay sorted, as searches intersect them
+        for token in tokens {
+            if previous.binary_search(&token).is_err() {
+                let ids = self.inverted_index.entry(token).or_default();
+                if let Err(pos) = ids.binary_search(&id) {
+                    ids.insert(pos, id);
+                }
+            }
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.entries.is_empty()
     }
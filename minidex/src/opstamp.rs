@@ -28,6 +28,12 @@ impl Opstamp {
         self.0 & Self::SEQ_MASK
     }
 
+    /// The same kind of operation, at another sequence
+    #[inline]
+    pub fn with_sequence(&self, seq: u64) -> Self {
+        Self((self.0 & Self::TOMBSTONE_BIT) | (seq & Self::SEQ_MASK))
+    }
+
     #[inline]
     pub fn as_bytes(&self) -> [u8; 8] {
         self.0.to_le_bytes()
@@ -75,6 +81,14 @@ mod tests {
         assert!(*op > Opstamp::TOMBSTONE_BIT);
     }
 
+    #[test]
+    fn test_opstamp_with_sequence() {
+        let op = Opstamp::deletion(42).with_sequence(7);
+        assert!(op.is_deletion());
+        assert_eq!(op.sequence(), 7);
+        assert!(!Opstamp::insertion(42).with_sequence(7).is_deletion());
+    }
+
     #[test]
     fn test_opstamp_serialization() {
         let op = Opstamp::deletion(12345);
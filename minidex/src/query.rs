@@ -0,0 +1,727 @@
+use crate::{
+    Kind,
+    category::{self, CategoryClassifier},
+    tokenizer::{SYNTH_EXT_TOKEN_TAG, SYNTH_TAG_TOKEN_TAG, SYNTH_VOLUME_TOKEN_TAG},
+};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+/// Years accepted in dates, so that day and second counts can't overflow
+const YEARS: std::ops::RangeInclusive<i64> = 1970..=9999;
+
+/// Alternatives of an OR group, each made of the tokens it requires
+pub(crate) type Alternatives = Vec<Vec<String>>;
+
+/// A parsed search query.
+/// Free text is kept apart from field operators (`ext:pdf`, `kind:dir`,
+/// `in:/home/me`, `vol:C:`, `tag:starred`, `target:/mnt/data`, `cat:image|video`,
+/// `modified:>2024-01-01`, `created:2024-05`, `depth:<4`), which compile into
+/// synthetic tokens and metadata filters.
+/// Terms and token operators can be negated with `-term` or `NOT term`, and
+/// grouped into alternatives with parentheses: `(invoice OR receipt)`.
+/// Quoted phrases (`"annual report"`) must appear as-is in matching paths.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Query {
+    /// Free text portion of the query, with all operators stripped
+    pub text: String,
+    /// Synthetic tokens every candidate must contain
+    pub required_tokens: Vec<String>,
+    /// Groups of tokens to exclude: candidates containing every token of any
+    /// group are dropped
+    pub excluded: Vec<Vec<String>>,
+    /// Negated words made of several tokens (normalized with
+    /// `normalize_phrase`). Their tokens must appear next to each other, so
+    /// they are excluded by phrase rather than by `excluded`.
+    pub excluded_phrases: Vec<String>,
+    /// OR groups: candidates must contain every token of at least one
+    /// alternative in each group
+    pub groups: Vec<Alternatives>,
+    /// Quoted phrases (normalized with `normalize_phrase`) every matching
+    /// path must contain
+    pub phrases: Vec<String>,
+    pub kind: Option<Kind>,
+    pub category: Option<u8>,
+    /// Inclusive last modified range, in seconds
+    pub modified: Option<(u64, u64)>,
+    /// Inclusive creation time range, in seconds
+    pub created: Option<(u64, u64)>,
+    /// Inclusive path depth range
+    pub depth: Option<(u16, u16)>,
+}
+
+impl Query {
+    /// Parse a query string, with the built-in categories only
+    #[cfg(test)]
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        Self::parse_with_categories(input, None)
+    }
+
+    /// Parse a query string. Unknown fields (and words such as `C:\foo`) are
+    /// treated as free text, while known fields with invalid values are
+    /// rejected. `cat:` operators also accept the custom categories of the
+    /// given classifier.
+    pub fn parse_with_categories(
+        input: &str,
+        categories: Option<&CategoryClassifier>,
+    ) -> Result<Self, QueryError> {
+        let mut query = Self::default();
+        let mut text = Vec::new();
+        let mut words = input.split_whitespace();
+        let mut group: Option<Alternatives> = None;
+
+        while let Some(mut word) = words.next() {
+            if group.is_none()
+                && let Some(rest) = word.strip_prefix('(')
+            {
+                group = Some(Vec::new());
+                word = rest;
+            }
+
+            if let Some(alternatives) = group.as_mut() {
+                let (word, closes_group) = match word.strip_suffix(')') {
+                    Some(rest) => (rest, true),
+                    None => (word, false),
+                };
+
+                // `OR` and `|` are optional, as alternatives are always OR'ed
+                if !matches!(word, "" | "OR" | "|") {
+                    let tokens = query.parse_alternative(word)?;
+                    if !tokens.is_empty() {
+                        alternatives.push(tokens);
+                    }
+                }
+
+                if closes_group
+                    && let Some(alternatives) = group.take()
+                    && !alternatives.is_empty()
+                {
+                    query.groups.push(alternatives);
+                }
+                continue;
+            }
+
+            if let Some(mut rest) = word.strip_prefix('"') {
+                // Phrase words are still searched (and scored) as regular text
+                let mut phrase_words = Vec::new();
+                loop {
+                    if let Some(last) = rest.strip_suffix('"') {
+                        phrase_words.push(last);
+                        break;
+                    }
+                    phrase_words.push(rest);
+                    // Phrases still being typed are closed at the end of the query
+                    match words.next() {
+                        Some(next) => rest = next,
+                        None => break,
+                    }
+                }
+
+                let phrase = phrase_words.join(" ");
+                let normalized = crate::tokenizer::normalize_phrase(&phrase);
+                if !normalized.is_empty() {
+                    query.phrases.push(normalized);
+                    text.extend(phrase_words.into_iter().filter(|w| !w.is_empty()));
+                }
+                continue;
+            }
+
+            let (word, negated) = match word.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (rest, true),
+                _ if word == "NOT" => match words.next() {
+                    Some(next) => (next, true),
+                    // Negations still being typed are ignored
+                    None => continue,
+                },
+                _ => (word, false),
+            };
+
+            let Some((field, value)) = word
+                .split_once(':')
+                .filter(|(field, _)| Self::is_field(&field.to_ascii_lowercase()))
+            else {
+                if negated {
+                    let tokens = crate::tokenizer::tokenize(word);
+                    if tokens.len() > 1 {
+                        // e.g. `-node_modules` spares `node/modules_index.js`
+                        let normalized = crate::tokenizer::normalize_phrase(word);
+                        if !normalized.is_empty() {
+                            query.excluded_phrases.push(normalized);
+                        }
+                    } else if !tokens.is_empty() {
+                        query.excluded.push(tokens);
+                    }
+                } else {
+                    text.push(word);
+                }
+                continue;
+            };
+
+            let field = field.to_ascii_lowercase();
+
+            // Operators still being typed are ignored rather than rejected
+            if value.is_empty() {
+                continue;
+            }
+
+            if negated {
+                query.apply_negated_operator(&field, value)?;
+            } else if field == "cat" {
+                query.apply_categories(value, categories)?;
+            } else {
+                query.apply_operator(&field, value)?;
+            }
+        }
+
+        // Groups still being typed are closed at the end of the query
+        if let Some(alternatives) = group
+            && !alternatives.is_empty()
+        {
+            query.groups.push(alternatives);
+        }
+
+        query.text = text.join(" ");
+
+        Ok(query)
+    }
+
+    /// Whether the query has metadata filter operators (`kind`, `cat`,
+    /// `modified`, `created`, `depth`)
+    pub fn has_filters(&self) -> bool {
+        self.kind.is_some()
+            || self.category.is_some()
+            || self.modified.is_some()
+            || self.created.is_some()
+            || self.depth.is_some()
+    }
+
+    /// Whether the query has phrases, which can only be checked against full paths
+    pub fn has_phrases(&self) -> bool {
+        !self.phrases.is_empty() || !self.excluded_phrases.is_empty()
+    }
+
+    /// Whether a path contains every quoted phrase of the query, and none of
+    /// its excluded phrases
+    pub fn matches_phrases(&self, path: &str) -> bool {
+        if !self.has_phrases() {
+            return true;
+        }
+
+        let normalized = crate::tokenizer::normalize_phrase(path);
+        self.phrases
+            .iter()
+            .all(|phrase| contains_words(&normalized, phrase))
+            && !self
+                .excluded_phrases
+                .iter()
+                .any(|phrase| contains_words(&normalized, phrase))
+    }
+
+    /// Parse a word inside an OR group into the tokens it requires.
+    /// Only free text and token operators can be used as alternatives.
+    fn parse_alternative(&mut self, word: &str) -> Result<Vec<String>, QueryError> {
+        if word.len() > 1 && word.starts_with('-') {
+            return Err(QueryError::Group(word.to_owned()));
+        }
+
+        let Some((field, value)) = word
+            .split_once(':')
+            .filter(|(field, _)| Self::is_field(&field.to_ascii_lowercase()))
+        else {
+            return Ok(crate::tokenizer::tokenize(word));
+        };
+
+        let field = field.to_ascii_lowercase();
+        if !matches!(field.as_str(), "ext" | "in" | "vol" | "tag" | "target") {
+            return Err(QueryError::Group(word.to_owned()));
+        }
+        if value.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let required = self.required_tokens.len();
+        self.apply_operator(&field, value)?;
+        Ok(self.required_tokens.drain(required..).collect())
+    }
+
+    fn is_field(field: &str) -> bool {
+        matches!(
+            field,
+            "ext"
+                | "kind"
+                | "in"
+                | "vol"
+                | "tag"
+                | "target"
+                | "cat"
+                | "modified"
+                | "created"
+                | "depth"
+        )
+    }
+
+    /// Only token operators can be negated, as they map to posting lists
+    fn apply_negated_operator(&mut self, field: &str, value: &str) -> Result<(), QueryError> {
+        if !matches!(field, "ext" | "in" | "vol" | "tag" | "target") {
+            return Err(QueryError::Negation(field.to_owned()));
+        }
+
+        let required = self.required_tokens.len();
+        self.apply_operator(field, value)?;
+        self.excluded.extend(
+            self.required_tokens
+                .drain(required..)
+                .map(|token| vec![token]),
+        );
+
+        Ok(())
+    }
+
+    fn apply_operator(&mut self, field: &str, value: &str) -> Result<(), QueryError> {
+        match field {
+            "ext" => {
+                let ext = value.trim_start_matches('.');
+                if ext.is_empty() {
+                    return Err(QueryError::InvalidValue(field.to_owned(), value.to_owned()));
+                }
+                self.required_tokens
+                    .push(crate::tokenizer::synthesize_token(SYNTH_EXT_TOKEN_TAG, ext));
+            }
+            "kind" => {
+                let kind = match value.to_ascii_lowercase().as_str() {
+                    "file" | "f" => Kind::File,
+                    "dir" | "directory" | "folder" | "d" => Kind::Directory,
+                    "symlink" | "link" | "l" => Kind::Symlink,
+                    _ => {
+                        return Err(QueryError::InvalidValue(field.to_owned(), value.to_owned()));
+                    }
+                };
+                if self.kind.is_some_and(|k| k != kind) {
+                    return Err(QueryError::Conflict(field.to_owned()));
+                }
+                self.kind = Some(kind);
+            }
+            "in" => {
+                // Everything lives under the root
+                if let Some(token) = crate::tokenizer::path_prefix_token(value) {
+                    self.required_tokens.push(token);
+                }
+            }
+            "vol" => {
+                self.required_tokens
+                    .push(crate::tokenizer::synthesize_token(
+                        SYNTH_VOLUME_TOKEN_TAG,
+                        value,
+                    ));
+            }
+            "tag" => {
+                self.required_tokens
+                    .push(crate::tokenizer::synthesize_token(
+                        SYNTH_TAG_TOKEN_TAG,
+                        value,
+                    ));
+            }
+            "target" => {
+                self.required_tokens
+                    .push(crate::tokenizer::target_prefix_token(value));
+            }
+            "modified" => {
+                let range = parse_range(value, parse_date_range)
+                    .ok_or_else(|| QueryError::InvalidValue(field.to_owned(), value.to_owned()))?;
+                self.modified = Some(intersect(self.modified, range));
+            }
+            "created" => {
+                let range = parse_range(value, parse_date_range)
+                    .ok_or_else(|| QueryError::InvalidValue(field.to_owned(), value.to_owned()))?;
+                self.created = Some(intersect(self.created, range));
+            }
+            "depth" => {
+                let range = parse_range(value, |v| v.parse::<u16>().ok().map(|d| (d, d)))
+                    .ok_or_else(|| QueryError::InvalidValue(field.to_owned(), value.to_owned()))?;
+                self.depth = Some(intersect(self.depth, range));
+            }
+            _ => unreachable!("unknown query field {field}"),
+        }
+
+        Ok(())
+    }
+
+    /// Applies a `cat:` operator, matching any of the `|` separated categories
+    fn apply_categories(
+        &mut self,
+        value: &str,
+        categories: Option<&CategoryClassifier>,
+    ) -> Result<(), QueryError> {
+        let mut mask = 0;
+        for name in value.split('|').filter(|n| !n.is_empty()) {
+            mask |= match categories {
+                Some(categories) => categories.category_named(name),
+                None => category::from_name(name),
+            }
+            .ok_or_else(|| QueryError::InvalidValue("cat".to_owned(), value.to_owned()))?;
+        }
+        self.category = Some(self.category.map_or(mask, |c| c & mask));
+
+        Ok(())
+    }
+}
+
+/// Whether `phrase` appears in `text` starting at a word boundary. As with
+/// regular prefix matching, the last word of the phrase may be incomplete.
+fn contains_words(text: &str, phrase: &str) -> bool {
+    text.match_indices(phrase)
+        .any(|(idx, _)| idx == 0 || matches!(text.as_bytes()[idx - 1], b' ' | b'/'))
+}
+
+/// Parses comparison (`>v`, `>=v`, `<v`, `<=v`, `=v`, `v`) and range (`a..b`)
+/// expressions into an inclusive range. `parse_one` yields the inclusive
+/// bounds covered by a single value (e.g. all seconds of a given day).
+fn parse_range<T>(value: &str, parse_one: impl Fn(&str) -> Option<(T, T)>) -> Option<(T, T)>
+where
+    T: Bounded + Copy,
+{
+    if let Some((start, end)) = value.split_once("..") {
+        let lo = if start.is_empty() {
+            T::MIN
+        } else {
+            parse_one(start)?.0
+        };
+        let hi = if end.is_empty() {
+            T::MAX
+        } else {
+            parse_one(end)?.1
+        };
+        return Some((lo, hi));
+    }
+
+    if let Some(v) = value.strip_prefix(">=") {
+        Some((parse_one(v)?.0, T::MAX))
+    } else if let Some(v) = value.strip_prefix("<=") {
+        Some((T::MIN, parse_one(v)?.1))
+    } else if let Some(v) = value.strip_prefix('>') {
+        Some((parse_one(v)?.1.next()?, T::MAX))
+    } else if let Some(v) = value.strip_prefix('<') {
+        Some((T::MIN, parse_one(v)?.0.prev()?))
+    } else {
+        parse_one(value.strip_prefix('=').unwrap_or(value))
+    }
+}
+
+fn intersect<T: Ord + Copy>(current: Option<(T, T)>, range: (T, T)) -> (T, T) {
+    match current {
+        Some((lo, hi)) => (lo.max(range.0), hi.min(range.1)),
+        None => range,
+    }
+}
+
+trait Bounded: Sized {
+    const MIN: Self;
+    const MAX: Self;
+    fn next(self) -> Option<Self>;
+    fn prev(self) -> Option<Self>;
+}
+
+macro_rules! impl_bounded {
+    ($($t:ty),*) => {
+        $(impl Bounded for $t {
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+            fn next(self) -> Option<Self> {
+                self.checked_add(1)
+            }
+            fn prev(self) -> Option<Self> {
+                self.checked_sub(1)
+            }
+        })*
+    };
+}
+
+impl_bounded!(u16, u64);
+
+/// Parses a `YYYY-MM-DD` (or `YYYY-MM`, `YYYY`) date into the inclusive range
+/// of UTC seconds it covers.
+fn parse_date_range(value: &str) -> Option<(u64, u64)> {
+    let mut parts = value.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok().filter(|y| YEARS.contains(y))?;
+    let month: Option<u32> = match parts.next() {
+        Some(m) => Some(m.parse().ok().filter(|m| (1..=12).contains(m))?),
+        None => None,
+    };
+    let day: Option<u32> = match parts.next() {
+        Some(d) => Some(d.parse().ok().filter(|&d| d >= 1)?),
+        None => None,
+    };
+
+    let (start, end) = match (month, day) {
+        (Some(m), Some(d)) => {
+            if d > days_in_month(year, m) {
+                return None;
+            }
+            let start = days_from_civil(year, m, d);
+            (start, start + 1)
+        }
+        (Some(m), None) => {
+            let start = days_from_civil(year, m, 1);
+            (start, start + days_in_month(year, m) as i64)
+        }
+        _ => (days_from_civil(year, 1, 1), days_from_civil(year + 1, 1, 1)),
+    };
+
+    Some((
+        start as u64 * SECONDS_PER_DAY,
+        end as u64 * SECONDS_PER_DAY - 1,
+    ))
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since the UNIX epoch for a proleptic Gregorian calendar date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum QueryError {
+    #[error("invalid value `{1}` for `{0}:`")]
+    InvalidValue(String, String),
+    #[error("conflicting values for `{0}:`")]
+    Conflict(String),
+    #[error("`{0}:` can't be negated")]
+    Negation(String),
+    #[error("`{0}` can't be used inside a group")]
+    Group(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plain_text() {
+        let query = Query::parse("annual  report").unwrap();
+        assert_eq!(query.text, "annual report");
+        assert!(query.required_tokens.is_empty());
+        assert_eq!(query.kind, None);
+    }
+
+    #[test]
+    fn test_parse_token_operators() {
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let query = Query::parse("report ext:PDF in:/home/Me vol:C: tag:Project:Alpha").unwrap();
+        assert_eq!(query.text, "report");
+        assert_eq!(
+            query.required_tokens,
+            vec![
+                "\x02pdf".to_string(),
+                format!("\x00{}home{}me{}", sep, sep, sep),
+                "\x01c:".to_string(),
+                "\x03project:alpha".to_string(),
+            ]
+        );
+
+        let query = Query::parse("target:/ target:/mnt/Data").unwrap();
+        assert_eq!(
+            query.required_tokens,
+            vec![
+                format!("\x06{}", sep),
+                format!("\x06{}mnt{}data{}", sep, sep, sep),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_filter_operators() {
+        let query = Query::parse("kind:dir cat:image|video depth:<4").unwrap();
+        assert_eq!(query.text, "");
+        assert_eq!(query.kind, Some(Kind::Directory));
+        assert_eq!(query.category, Some(category::IMAGE | category::VIDEO));
+        assert_eq!(query.depth, Some((0, 3)));
+    }
+
+    #[test]
+    fn test_parse_custom_categories() {
+        let mut categories = CategoryClassifier::default();
+        let model = categories.add_category("model").unwrap();
+
+        let query = Query::parse_with_categories("cat:Model|img", Some(&categories)).unwrap();
+        assert_eq!(query.category, Some(model | category::IMAGE));
+        assert!(matches!(
+            Query::parse("cat:model"),
+            Err(QueryError::InvalidValue(..))
+        ));
+    }
+
+    #[test]
+    fn test_parse_dates() {
+        // 2024-01-01T00:00:00Z
+        let jan_1 = 1_704_067_200;
+
+        let query = Query::parse("modified:>2024-01-01").unwrap();
+        assert_eq!(query.modified, Some((jan_1 + SECONDS_PER_DAY, u64::MAX)));
+
+        let query = Query::parse("modified:>=2024-01-01").unwrap();
+        assert_eq!(query.modified, Some((jan_1, u64::MAX)));
+
+        let query = Query::parse("modified:<2024-01-01").unwrap();
+        assert_eq!(query.modified, Some((0, jan_1 - 1)));
+
+        let query = Query::parse("modified:2024-01-01").unwrap();
+        assert_eq!(query.modified, Some((jan_1, jan_1 + SECONDS_PER_DAY - 1)));
+
+        let query = Query::parse("modified:2024-01").unwrap();
+        assert_eq!(
+            query.modified,
+            Some((jan_1, jan_1 + 31 * SECONDS_PER_DAY - 1))
+        );
+
+        let query = Query::parse("modified:2023-12-31..2024-01-01").unwrap();
+        assert_eq!(
+            query.modified,
+            Some((jan_1 - SECONDS_PER_DAY, jan_1 + SECONDS_PER_DAY - 1))
+        );
+
+        let query = Query::parse("created:>=2024-01-01 created:<2024-01-02").unwrap();
+        assert_eq!(query.created, Some((jan_1, jan_1 + SECONDS_PER_DAY - 1)));
+        assert_eq!(query.modified, None);
+    }
+
+    #[test]
+    fn test_parse_unknown_fields_are_text() {
+        let query = Query::parse("C:\\Users foo:bar").unwrap();
+        assert_eq!(query.text, "C:\\Users foo:bar");
+    }
+
+    #[test]
+    fn test_parse_incomplete_operator_is_ignored() {
+        let query = Query::parse("report ext:").unwrap();
+        assert_eq!(query.text, "report");
+        assert!(query.required_tokens.is_empty());
+    }
+
+    #[test]
+    fn test_parse_negations() {
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let query = Query::parse("report -node_modules NOT Backup -ext:tmp -in:/tmp -").unwrap();
+        assert_eq!(query.text, "report -");
+        assert!(query.required_tokens.is_empty());
+        assert_eq!(
+            query.excluded,
+            vec![
+                vec!["backup".to_string()],
+                vec!["\x02tmp".to_string()],
+                vec![format!("\x00{}tmp{}", sep, sep)],
+            ]
+        );
+        assert_eq!(query.excluded_phrases, vec!["node modules"]);
+        assert!(!query.matches_phrases("/app/node_modules/index.js"));
+        assert!(query.matches_phrases("/app/node/modules_index.js"));
+
+        // Unknown fields are negated as text
+        let query = Query::parse("-foo:bar NOT").unwrap();
+        assert!(query.excluded.is_empty());
+        assert_eq!(query.excluded_phrases, vec!["foo bar"]);
+
+        assert_eq!(
+            Query::parse("report -kind:dir"),
+            Err(QueryError::Negation("kind".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_groups() {
+        let query =
+            Query::parse("(invoice OR receipt) 2024 ( ext:pdf | .DOCX ) (node_modules").unwrap();
+        assert_eq!(query.text, "2024");
+        assert_eq!(
+            query.groups,
+            vec![
+                vec![vec!["invoice".to_string()], vec!["receipt".to_string()]],
+                vec![vec!["\x02pdf".to_string()], vec!["docx".to_string()]],
+                vec![vec!["modules".to_string(), "node".to_string()]],
+            ]
+        );
+        assert!(query.required_tokens.is_empty());
+
+        // Empty groups are ignored
+        let query = Query::parse("report () (OR)").unwrap();
+        assert!(query.groups.is_empty());
+
+        assert_eq!(
+            Query::parse("(kind:dir OR report)"),
+            Err(QueryError::Group("kind:dir".to_string()))
+        );
+        assert_eq!(
+            Query::parse("(report -draft)"),
+            Err(QueryError::Group("-draft".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_phrases() {
+        let query = Query::parse("\"Annual  Report\" 2024 \"q3\" \"draft").unwrap();
+        assert_eq!(query.text, "Annual Report 2024 q3 draft");
+        assert_eq!(query.phrases, vec!["annual report", "q 3", "draft"]);
+
+        let query = Query::parse("\"\" \" report").unwrap();
+        assert_eq!(query.text, "report");
+        assert_eq!(query.phrases, vec!["report"]);
+    }
+
+    #[test]
+    fn test_matches_phrases() {
+        let query = Query::parse("\"annual report\"").unwrap();
+        assert!(query.matches_phrases("/docs/Annual_Report_2024.pdf"));
+        assert!(query.matches_phrases("/docs/AnnualReports.pdf"));
+        assert!(!query.matches_phrases("/docs/annual/report.pdf"));
+        assert!(!query.matches_phrases("/docs/report_annual.pdf"));
+        assert!(!query.matches_phrases("/docs/semiannual_report.pdf"));
+    }
+
+    #[test]
+    fn test_parse_invalid_values() {
+        assert!(matches!(
+            Query::parse("modified:>2024-13-01"),
+            Err(QueryError::InvalidValue(..))
+        ));
+        // Years out of range are rejected rather than overflowing
+        for value in [
+            "modified:1000000000000",
+            "modified:>1000000000000",
+            "modified:99999999999999999",
+            "created:<=1000000000000-01-01",
+            "created:10000..1000000000000",
+        ] {
+            assert!(
+                matches!(Query::parse(value), Err(QueryError::InvalidValue(..))),
+                "{value}"
+            );
+        }
+        assert!(matches!(
+            Query::parse("depth:deep"),
+            Err(QueryError::InvalidValue(..))
+        ));
+        assert!(matches!(
+            Query::parse("cat:spreadsheet"),
+            Err(QueryError::InvalidValue(..))
+        ));
+        assert!(matches!(
+            Query::parse("-cat:image"),
+            Err(QueryError::Negation(..))
+        ));
+        assert!(matches!(
+            Query::parse("kind:file kind:dir"),
+            Err(QueryError::Conflict(..))
+        ));
+    }
+}
@@ -1,7 +1,18 @@
 use std::path::PathBuf;
 
-use crate::{Kind, common::VolumeType};
+use crate::{
+    Kind,
+    common::{PrefixRename, VolumeType},
+    entry::{FileId, IndexEntry},
+    query::Query,
+    segmented_index::DocMeta,
+};
 
+mod fuzzy;
+pub use fuzzy::MAX_FUZZINESS;
+pub(crate) use fuzzy::{FuzzyAutomaton, max_edits};
+mod pattern;
+pub(crate) use pattern::PathPattern;
 mod scoring;
 pub use scoring::*;
 
@@ -16,6 +27,46 @@ pub struct SearchOptions<'a> {
     pub max_expansions: usize,
     pub max_scoring_cap: Option<usize>,
     pub short_prefix_threshold: usize,
+    /// Maximum number of edits (up to `MAX_FUZZINESS`) tolerated when matching
+    /// query terms, so typos like "recieve" still match. `0` disables fuzzy matching.
+    pub fuzziness: u8,
+    /// Only return entries under any of these directories
+    pub within: &'a [&'a str],
+    /// Inclusive range (in seconds) the last modified time of results must fall within
+    pub modified_range: Option<(u64, u64)>,
+    /// Inclusive range (in seconds) the last accessed time of results must fall within
+    pub accessed_range: Option<(u64, u64)>,
+    /// Inclusive range (in seconds) the creation time of results must fall within.
+    /// Entries with an unknown creation time have a creation time of `0`.
+    pub created_range: Option<(u64, u64)>,
+    /// Minimum path depth of results (number of separators in the path)
+    pub min_depth: Option<u16>,
+    /// Maximum path depth of results (number of separators in the path)
+    pub max_depth: Option<u16>,
+    /// Only return entries under this directory, counting `min_depth` and
+    /// `max_depth` from it (its direct children being at depth 1)
+    pub depth_root: Option<&'a str>,
+    /// Minimum size of results, in bytes
+    pub min_size: Option<u64>,
+    /// Maximum size of results, in bytes
+    pub max_size: Option<u64>,
+    /// Order of results. Ignored by `Index::recent_files`, which always
+    /// returns the most recent entries first.
+    pub sort: SortOrder,
+    /// Only return entries carrying all of these tags (matched case insensitively)
+    pub tags: &'a [&'a str],
+    /// Only return symlinks whose target (resolved against the directory
+    /// containing the link) is any of these paths, or lies under them
+    pub symlink_target_within: &'a [&'a str],
+    /// Only return entries owned by this user id
+    pub owner: Option<u32>,
+    /// Only return entries owned by this group id
+    pub group: Option<u32>,
+    /// Only return files with an execute permission bit set
+    pub executable_only: bool,
+    /// Also return hidden entries, i.e. entries flagged as hidden and
+    /// entries under a dotfile
+    pub include_hidden: bool,
 }
 
 impl Default for SearchOptions<'_> {
@@ -29,6 +80,49 @@ impl Default for SearchOptions<'_> {
             max_expansions: 50,
             max_scoring_cap: None,
             short_prefix_threshold: 1,
+            fuzziness: 0,
+            within: &[],
+            modified_range: None,
+            accessed_range: None,
+            created_range: None,
+            min_depth: None,
+            max_depth: None,
+            depth_root: None,
+            min_size: None,
+            max_size: None,
+            sort: SortOrder::Relevance,
+            tags: &[],
+            symlink_target_within: &[],
+            owner: None,
+            group: None,
+            executable_only: false,
+            include_hidden: false,
+        }
+    }
+}
+
+/// Order of search results
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Best scoring entries first (most recent first for pattern searches,
+    /// filter-only queries and recent files)
+    #[default]
+    Relevance,
+    /// Largest entries first
+    LargestFirst,
+    /// Smallest entries first
+    SmallestFirst,
+}
+
+impl SortOrder {
+    /// Candidate sort key for a size, where higher keys come first,
+    /// or `None` if results aren't ordered by size.
+    #[inline(always)]
+    pub(crate) fn size_key(self, size: u64) -> Option<u64> {
+        match self {
+            Self::Relevance => None,
+            Self::LargestFirst => Some(size),
+            Self::SmallestFirst => Some(!size),
         }
     }
 }
@@ -43,10 +137,33 @@ pub struct SearchResult {
     pub kind: Kind,
     pub last_modified: u64,
     pub last_accessed: u64,
+    pub created: u64,
     pub category: u8,
+    pub size: u64,
+    pub tags: Vec<String>,
+    pub content_hash: Option<u128>,
+    /// Target of a symlink, as read from the link
+    pub symlink_target: Option<PathBuf>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub mode: Option<u32>,
+    /// Whether the entry is hidden (flagged as hidden, or under a dotfile)
+    pub hidden: bool,
+    /// Stable identity of the file (e.g. device and inode), if known
+    pub file_id: Option<FileId>,
     pub score: f64,
 }
 
+impl SearchResult {
+    /// Target of a symlink resolved against the directory containing it.
+    /// Resolution is lexical and doesn't follow links inside the target.
+    pub fn resolved_symlink_target(&self) -> Option<PathBuf> {
+        self.symlink_target
+            .as_deref()
+            .map(|target| crate::entry::resolve_symlink_target(&self.path, target))
+    }
+}
+
 impl Eq for SearchResult {}
 
 impl Ord for SearchResult {
@@ -66,41 +183,169 @@ impl PartialOrd for SearchResult {
     }
 }
 
-#[inline(always)]
-pub(crate) fn evaluate_candidate(
-    packed: u128,
-    options: &SearchOptions,
-    volume_type_mask: u8,
-) -> Option<u64> {
-    // Inline bitwise extraction to avoid incurring type conversion penalties
-    let last_modified = ((packed >> 40) & 0x3_FFFF_FFFF) as u64;
-    let last_accessed = ((packed >> 74) & 0x3_FFFF_FFFF) as u64;
-    let depth = ((packed >> 108) & 0xFF) as u64;
-    let is_dir = ((packed >> 116) & 1) as u64; // Yields exactly 1 or 0
-    let category = ((packed >> 117) & 0xFF) as u8;
-    let volume_type = ((packed >> 125) & 0b11) as u8;
+/// Entries sharing the same content, as found by `Index::find_duplicates`
+#[derive(Debug, PartialEq)]
+pub struct DuplicateGroup {
+    pub content_hash: u128,
+    /// Entries with this content hash, ordered by path
+    pub entries: Vec<SearchResult>,
+}
 
-    // Apply fast bitwise filters first
-    if let Some(target_kind) = options.kind {
-        let is_target_dir = if target_kind == crate::Kind::Directory {
-            1
-        } else {
-            0
-        };
-        if is_dir != is_target_dir {
+impl DuplicateGroup {
+    /// Bytes taken by all copies but one
+    pub fn redundant_size(&self) -> u64 {
+        let size = self.entries.first().map_or(0, |entry| entry.size);
+        size.saturating_mul(self.entries.len().saturating_sub(1) as u64)
+    }
+}
+
+/// Metadata filters compiled from `SearchOptions` and query operators,
+/// evaluated directly against the fixed size document metadata.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CandidateFilter {
+    pub kind: Option<Kind>,
+    pub category: Option<u8>,
+    pub volume_type_mask: u8,
+    /// Inclusive last modified range, in seconds
+    pub modified: (u64, u64),
+    /// Inclusive last accessed range, in seconds
+    pub accessed: (u64, u64),
+    /// Inclusive creation time range, in seconds
+    pub created: (u64, u64),
+    /// Inclusive path depth range
+    pub depth: (u64, u64),
+    /// Inclusive size range, in bytes
+    pub size: (u64, u64),
+    pub owner: Option<u32>,
+    pub group: Option<u32>,
+    pub executable_only: bool,
+    pub include_hidden: bool,
+    pub sort: SortOrder,
+}
+
+impl CandidateFilter {
+    pub fn new(options: &SearchOptions) -> Self {
+        Self {
+            kind: options.kind,
+            category: options.category,
+            volume_type_mask: Self::compile_allowed_volume_mask(options.volume_type),
+            modified: options.modified_range.unwrap_or((0, u64::MAX)),
+            accessed: options.accessed_range.unwrap_or((0, u64::MAX)),
+            created: options.created_range.unwrap_or((0, u64::MAX)),
+            depth: Self::compile_depth_range(options),
+            size: (
+                options.min_size.unwrap_or(0),
+                options.max_size.unwrap_or(u64::MAX),
+            ),
+            owner: options.owner,
+            group: options.group,
+            executable_only: options.executable_only,
+            include_hidden: options.include_hidden,
+            sort: options.sort,
+        }
+    }
+
+    /// Narrow the filter with the operators of a parsed query.
+    /// Returns `None` if no candidate can possibly satisfy both.
+    pub fn with_query(mut self, query: &Query) -> Option<Self> {
+        if let Some(kind) = query.kind {
+            if self.kind.is_some_and(|k| k != kind) {
+                return None;
+            }
+            self.kind = Some(kind);
+        }
+
+        if let Some(category) = query.category {
+            self.category = Some(self.category.map_or(category, |c| c & category));
+        }
+
+        if let Some((lo, hi)) = query.modified {
+            self.modified = (self.modified.0.max(lo), self.modified.1.min(hi));
+        }
+
+        if let Some((lo, hi)) = query.created {
+            self.created = (self.created.0.max(lo), self.created.1.min(hi));
+        }
+
+        if let Some((lo, hi)) = query.depth {
+            self.depth = (self.depth.0.max(lo as u64), self.depth.1.min(hi as u64));
+        }
+
+        if self.modified.0 > self.modified.1
+            || self.created.0 > self.created.1
+            || self.depth.0 > self.depth.1
+        {
             return None;
         }
+
+        Some(self)
     }
-    if let Some(category_filter) = options.category
-        && category & category_filter == 0
-    {
-        return None;
+
+    /// The filter for entries at the path they are stored at. Entries moved by
+    /// a pending prefix rename change depth, which is only checked once their
+    /// path is resolved (see `matches_resolved`).
+    pub fn before_renames(self, renames: &[PrefixRename]) -> Self {
+        if renames.is_empty() {
+            return self;
+        }
+        Self {
+            depth: (0, u64::MAX),
+            ..self
+        }
+    }
+
+    /// Exact kind check for materialized entries, since the document metadata
+    /// can't distinguish files from symlinks.
+    #[inline]
+    pub fn matches_kind(&self, kind: Kind) -> bool {
+        self.kind.is_none_or(|k| k == kind)
+    }
+
+    fn compile_depth_range(options: &SearchOptions) -> (u64, u64) {
+        let base = options.depth_root.map_or(0, |root| {
+            root.trim_end_matches(std::path::is_separator)
+                .bytes()
+                .filter(|&b| std::path::is_separator(b as char))
+                .count() as u64
+        });
+        (
+            base + options.min_depth.unwrap_or(0) as u64,
+            options.max_depth.map_or(u64::MAX, |d| base + d as u64),
+        )
     }
 
-    if (volume_type_mask & (1 << volume_type)) == 0 {
+    fn compile_allowed_volume_mask(allowed_volume_types: Option<&[VolumeType]>) -> u8 {
+        match allowed_volume_types {
+            Some(allowed) => allowed.iter().fold(0, |acc, &vt| acc | (1 << (vt as u8))),
+            None => 0b0000_1111,
+        }
+    }
+}
+
+/// Sort key of a candidate, or `None` if it doesn't pass the filter.
+/// Deletions pass every filter, so that they shadow the older versions of
+/// their entry that do.
+#[inline(always)]
+pub(crate) fn evaluate_candidate(meta: DocMeta, filter: &CandidateFilter) -> Option<u64> {
+    if !meta.deleted && !matches_filter(&meta, filter) {
         return None;
     }
 
+    let DocMeta {
+        last_modified,
+        last_accessed,
+        size,
+        depth,
+        is_dir,
+        ..
+    } = meta;
+    let depth = depth as u64;
+    let is_dir = is_dir as u64; // Yields exactly 1 or 0
+
+    if let Some(size_key) = filter.sort.size_key(size) {
+        return Some(size_key);
+    }
+
     // Intentionally avoiding a call to `max` here, should optimize
     // to a single instruction
     let recent = if last_modified < last_accessed {
@@ -109,10 +354,110 @@ pub(crate) fn evaluate_candidate(
         last_modified
     };
 
-    let sort_key = (is_dir << 63) | ((!depth & 0xFF) << 55) | (recent << 21);
+    // Depths and timestamps saturate to fit in the key
+    let sort_key =
+        (is_dir << 63) | ((!depth.min(0xFF) & 0xFF) << 55) | (recent.min(0x3_FFFF_FFFF) << 21);
     Some(sort_key)
 }
 
+#[inline(always)]
+fn matches_filter(meta: &DocMeta, filter: &CandidateFilter) -> bool {
+    let &DocMeta {
+        last_modified,
+        last_accessed,
+        created,
+        size,
+        uid,
+        gid,
+        mode,
+        depth,
+        is_dir,
+        category,
+        volume_type,
+        hidden: is_hidden,
+        ..
+    } = meta;
+    let depth = depth as u64;
+
+    // Apply fast bitwise filters first
+    if is_hidden && !filter.include_hidden {
+        return false;
+    }
+
+    if let Some(target_kind) = filter.kind
+        && is_dir != (target_kind == crate::Kind::Directory)
+    {
+        return false;
+    }
+    if let Some(category_filter) = filter.category
+        && category & category_filter == 0
+    {
+        return false;
+    }
+
+    if (filter.volume_type_mask & (1 << volume_type)) == 0 {
+        return false;
+    }
+
+    if last_modified < filter.modified.0 || last_modified > filter.modified.1 {
+        return false;
+    }
+
+    if last_accessed < filter.accessed.0 || last_accessed > filter.accessed.1 {
+        return false;
+    }
+
+    if depth < filter.depth.0 || depth > filter.depth.1 {
+        return false;
+    }
+
+    if created < filter.created.0 || created > filter.created.1 {
+        return false;
+    }
+
+    if size < filter.size.0 || size > filter.size.1 {
+        return false;
+    }
+
+    if filter.owner.is_some_and(|owner| owner != uid)
+        || filter.group.is_some_and(|group| group != gid)
+    {
+        return false;
+    }
+
+    // Any of the user, group or other execute bits
+    !(filter.executable_only && (is_dir || mode & 0o111 == 0))
+}
+
+/// Timestamp recency listings order entries by
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Recency {
+    /// Most recent of the last modified and last accessed timestamps
+    Used,
+    /// Creation timestamp
+    Created,
+}
+
+impl Recency {
+    /// Recency of pre-filtering metadata, in seconds
+    #[inline(always)]
+    pub fn of_meta(self, meta: &DocMeta) -> u64 {
+        match self {
+            Self::Used => meta.last_modified.max(meta.last_accessed),
+            Self::Created => meta.created,
+        }
+    }
+
+    /// Recency of a materialized entry
+    #[inline(always)]
+    pub fn of_entry(self, entry: &IndexEntry) -> u64 {
+        match self {
+            Self::Used => entry.last_modified.max(entry.last_accessed),
+            Self::Created => entry.created,
+        }
+    }
+}
+
 #[inline(always)]
 pub(crate) fn retain_top_k<T>(candidates: &mut Vec<(u64, T)>, cap: usize) {
     if candidates.len() > cap {
@@ -120,3 +465,179 @@ pub(crate) fn retain_top_k<T>(candidates: &mut Vec<(u64, T)>, cap: usize) {
         candidates.truncate(cap);
     }
 }
+
+/// Intersects the candidates found so far (if any) with a sorted id list
+pub(crate) fn narrow_candidates(current: Option<Vec<u32>>, ids: &[u32]) -> Vec<u32> {
+    match current {
+        Some(current) => {
+            let mut out = Vec::with_capacity(current.len().min(ids.len()));
+            crate::simd::intersect_arrays(&current, ids, &mut out);
+            out
+        }
+        None => ids.to_vec(),
+    }
+}
+
+/// Collects the sorted ids of documents containing every token of any of the
+/// given groups. `postings` appends the sorted posting list of a token.
+pub(crate) fn collect_matching(
+    groups: &[Vec<String>],
+    mut postings: impl FnMut(&str, &mut Vec<u32>),
+) -> Vec<u32> {
+    let mut matching = Vec::new();
+    let mut token_ids = Vec::new();
+
+    for group in groups {
+        let mut group_ids: Option<Vec<u32>> = None;
+        for token in group {
+            token_ids.clear();
+            postings(token, &mut token_ids);
+            let narrowed = narrow_candidates(group_ids, &token_ids);
+            let is_empty = narrowed.is_empty();
+            group_ids = Some(narrowed);
+            if is_empty {
+                break;
+            }
+        }
+        matching.extend(group_ids.unwrap_or_default());
+    }
+
+    matching.sort_unstable();
+    matching.dedup();
+    matching
+}
+
+/// Token terms scoping results to `SearchOptions::within`,
+/// `SearchOptions::depth_root`, `SearchOptions::tags` and
+/// `SearchOptions::symlink_target_within`. Every term must be satisfied by
+/// one of its alternatives.
+pub(crate) fn scope_terms(options: &SearchOptions) -> Vec<Vec<Vec<String>>> {
+    stored_scope_terms(options, &[])
+}
+
+/// Like `scope_terms`, but directories also match the entries moved under
+/// them by pending prefix renames, which are stored at their previous path
+/// until compaction rewrites them. Entries matched this way may have been
+/// moved elsewhere, so they are checked again once their path is resolved
+/// (see `matches_resolved`).
+pub(crate) fn stored_scope_terms(
+    options: &SearchOptions,
+    renames: &[PrefixRename],
+) -> Vec<Vec<Vec<String>>> {
+    let mut terms = Vec::new();
+
+    // Any entry lives under the root, so a root directory doesn't scope anything
+    if !options.within.is_empty()
+        && let Some(within) = options
+            .within
+            .iter()
+            .map(|dir| stored_prefix_tokens(dir, renames))
+            .collect::<Option<Vec<_>>>()
+    {
+        terms.push(within.into_iter().flatten().collect());
+    }
+
+    if let Some(root) = options.depth_root
+        && let Some(tokens) = stored_prefix_tokens(root, renames)
+    {
+        terms.push(tokens);
+    }
+
+    for tag in options.tags.iter().filter(|tag| !tag.is_empty()) {
+        terms.push(vec![vec![crate::tokenizer::synthesize_token(
+            crate::tokenizer::SYNTH_TAG_TOKEN_TAG,
+            tag,
+        )]]);
+    }
+
+    if !options.symlink_target_within.is_empty() {
+        terms.push(
+            options
+                .symlink_target_within
+                .iter()
+                .map(|dir| vec![crate::tokenizer::target_prefix_token(dir)])
+                .collect(),
+        );
+    }
+
+    terms
+}
+
+/// Path prefix tokens of `dir` and of the directories entries under it were
+/// moved from by `renames`, or `None` if any of them is the root
+fn stored_prefix_tokens(dir: &str, renames: &[PrefixRename]) -> Option<Vec<Vec<String>>> {
+    let sep = std::path::MAIN_SEPARATOR;
+    let normalized = dir.replace(['/', '\\'], std::path::MAIN_SEPARATOR_STR);
+    let mut dirs = vec![match normalized.trim_end_matches(sep) {
+        "" => normalized.clone(),
+        trimmed => trimmed.to_string(),
+    }];
+
+    // Later renames may move entries moved by earlier ones again
+    for rename in renames.iter().rev() {
+        let new_prefix = crate::tokenizer::path_prefix_token(&rename.new_prefix);
+        let mut previous = Vec::new();
+        for dir in &dirs {
+            // Either `dir` was moved itself, or a directory was moved into it
+            let moved_from = rename.revert(dir).or_else(|| {
+                let dir_token = crate::tokenizer::path_prefix_token(dir)?;
+                new_prefix
+                    .as_ref()
+                    .is_some_and(|new_prefix| new_prefix.starts_with(&dir_token))
+                    .then(|| rename.old_prefix.clone())
+            });
+            if let Some(moved_from) = moved_from
+                && !dirs.contains(&moved_from)
+                && !previous.contains(&moved_from)
+            {
+                previous.push(moved_from);
+            }
+        }
+        dirs.extend(previous);
+    }
+
+    dirs.iter()
+        .map(|dir| crate::tokenizer::path_prefix_token(dir).map(|token| vec![token]))
+        .collect()
+}
+
+/// Whether an entry with the given tokens satisfies every scope term
+pub(crate) fn in_scope(terms: &[Vec<Vec<String>>], tokens: &[String]) -> bool {
+    terms.iter().all(|term| {
+        term.iter()
+            .any(|group| group.iter().all(|token| tokens.contains(token)))
+    })
+}
+
+/// Whether an entry, at the path it resolves to after prefix operations,
+/// satisfies the scope terms and metadata filters. Needed for entries moved
+/// by pending prefix renames, which are only matched by their previous
+/// location (and depth) until then.
+pub(crate) fn matches_resolved(
+    terms: &[Vec<Vec<String>>],
+    filter: &CandidateFilter,
+    path: &str,
+    volume: &str,
+    entry: &IndexEntry,
+) -> bool {
+    (terms.is_empty()
+        || in_scope(
+            terms,
+            &crate::tokenizer::extract_all_tokens(path, volume, entry),
+        ))
+        && evaluate_candidate(DocMeta::new(0, path, entry), filter).is_some()
+}
+
+/// Collects the sorted ids of documents satisfying every scope term, or `None`
+/// if there are no terms. `postings` appends the sorted posting list of a token.
+pub(crate) fn collect_scoped(
+    terms: &[Vec<Vec<String>>],
+    mut postings: impl FnMut(&str, &mut Vec<u32>),
+) -> Option<Vec<u32>> {
+    let mut scoped: Option<Vec<u32>> = None;
+    for term in terms {
+        let ids = collect_matching(term, &mut postings);
+        scoped = Some(narrow_candidates(scoped, &ids));
+    }
+    scoped
+}
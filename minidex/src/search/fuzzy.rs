@@ -0,0 +1,357 @@
+use std::{collections::BTreeMap, ops::Bound};
+
+/// Upper bound for the number of edits allowed for a single token
+pub const MAX_FUZZINESS: u8 = 2;
+
+/// Number of edits allowed for a query token, given the requested fuzziness.
+/// Short tokens are kept strict, as a single edit already matches most of
+/// the dictionary: tokens under 3 characters allow no edits, and tokens
+/// under 6 characters allow at most one.
+pub(crate) fn max_edits(token: &str, fuzziness: u8) -> u8 {
+    if crate::tokenizer::is_synthetic(token) {
+        return 0;
+    }
+
+    let allowed = match token.chars().count() {
+        0..3 => 0,
+        3..6 => 1,
+        _ => MAX_FUZZINESS,
+    };
+    fuzziness.min(allowed)
+}
+
+/// Number of cells in the rows of `FuzzyAutomaton`: only the distances for
+/// query prefixes within `MAX_FUZZINESS` characters of the token prefix's
+/// length can be within the allowed edits
+const BAND: usize = 2 * MAX_FUZZINESS as usize + 1;
+
+/// Distances for a token prefix against the query prefixes around its
+/// length, the middle cell being the query prefix of the same length.
+/// Distances are capped at `max_edits + 1`, which cells outside of the band
+/// or past the end of the query always hold.
+type Row = [u8; BAND];
+
+/// `fst::Automaton` accepting tokens starting with a prefix within `max_edits`
+/// edits (insertions, deletions, substitutions and transpositions of adjacent
+/// characters) of the query token. As with regular prefix search, the token
+/// itself may be longer than the query.
+pub(crate) struct FuzzyAutomaton {
+    query: Vec<char>,
+    max_edits: u8,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum FuzzyState {
+    Active {
+        /// Distances for the token prefix before the previous character,
+        /// used to detect transpositions
+        prev_row: Row,
+        row: Row,
+        /// Number of characters in the token prefix
+        len: usize,
+        prev_char: Option<char>,
+        /// Bytes of a partially consumed UTF-8 character
+        pending: [u8; 4],
+        pending_len: usize,
+    },
+    Matched,
+    Dead,
+}
+
+impl FuzzyAutomaton {
+    /// Automaton for the given query, with at most `MAX_FUZZINESS` edits
+    pub fn new(query: &str, max_edits: u8) -> Self {
+        Self {
+            query: query.chars().collect(),
+            max_edits: max_edits.min(MAX_FUZZINESS),
+        }
+    }
+
+    /// Run the automaton over a whole token (for the in-memory index)
+    pub fn matches(&self, token: &str) -> bool {
+        self.run(token) == Ok(true)
+    }
+
+    /// Entries of a sorted map whose keys match the automaton, in order.
+    /// Keys starting with a prefix the automaton rejects are skipped over
+    /// together rather than visited one by one.
+    pub fn matching_entries<'a, V>(
+        &'a self,
+        map: &'a BTreeMap<String, V>,
+    ) -> impl Iterator<Item = (&'a String, &'a V)> + 'a {
+        let mut range = map.range::<str, _>(..);
+
+        std::iter::from_fn(move || {
+            loop {
+                let (token, value) = range.next()?;
+                match self.run(token) {
+                    Ok(true) => return Some((token, value)),
+                    Ok(false) => {}
+                    Err(dead_len) => {
+                        let next = prefix_successor(&token[..dead_len])?;
+                        range =
+                            map.range::<str, _>((Bound::Included(next.as_str()), Bound::Unbounded));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Whether a whole token matches, or `Err` with the length of its
+    /// shortest prefix no token can start with to match
+    fn run(&self, token: &str) -> Result<bool, usize> {
+        use fst::Automaton as _;
+
+        let mut state = self.start();
+        for (i, &b) in token.as_bytes().iter().enumerate() {
+            if self.will_always_match(&state) {
+                return Ok(true);
+            }
+            state = self.accept(&state, b);
+            if !self.can_match(&state) {
+                return Err(i + 1);
+            }
+        }
+        Ok(self.is_match(&state))
+    }
+
+    /// Distance in a row for the token prefix of `len` characters against
+    /// the query prefix of `j` characters
+    fn distance(&self, row: &Row, len: usize, j: usize) -> u8 {
+        let cell = (j + MAX_FUZZINESS as usize).checked_sub(len);
+        match cell {
+            Some(cell) if cell < BAND && j <= self.query.len() => row[cell],
+            _ => self.max_edits + 1,
+        }
+    }
+
+    /// Row for the token prefix of `len` characters, from the rows of the
+    /// two shorter prefixes and its last character
+    fn step(&self, prev_row: &Row, row: &Row, len: usize, prev_char: Option<char>, c: char) -> Row {
+        let cap = self.max_edits + 1;
+        let mut next = [cap; BAND];
+
+        let first = len.saturating_sub(MAX_FUZZINESS as usize);
+        let last = (len + MAX_FUZZINESS as usize).min(self.query.len());
+        for j in first..=last {
+            let cell = j + MAX_FUZZINESS as usize - len;
+            if j == 0 {
+                next[cell] = len.min(cap as usize) as u8;
+                continue;
+            }
+
+            let cost = u8::from(self.query[j - 1] != c);
+            let left = if cell > 0 { next[cell - 1] } else { cap };
+            let mut distance = (self.distance(row, len - 1, j - 1) + cost)
+                .min(self.distance(row, len - 1, j) + 1)
+                .min(left + 1);
+
+            if j > 1 && prev_char == Some(self.query[j - 1]) && c == self.query[j - 2] {
+                distance = distance.min(self.distance(prev_row, len - 2, j - 2) + 1);
+            }
+
+            next[cell] = distance.min(cap);
+        }
+
+        next
+    }
+}
+
+/// Smallest string sorting after every string starting with `prefix`, if any
+fn prefix_successor(prefix: &str) -> Option<String> {
+    let mut successor = prefix.to_owned();
+    while let Some(c) = successor.pop() {
+        let next = (c as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            successor.push(next);
+            return Some(successor);
+        }
+    }
+    None
+}
+
+impl fst::Automaton for FuzzyAutomaton {
+    type State = FuzzyState;
+
+    fn start(&self) -> FuzzyState {
+        if self.query.len() <= self.max_edits as usize {
+            return FuzzyState::Matched;
+        }
+
+        let cap = self.max_edits + 1;
+        let mut row = [cap; BAND];
+        for j in 0..=self.query.len().min(MAX_FUZZINESS as usize) {
+            row[j + MAX_FUZZINESS as usize] = (j as u8).min(cap);
+        }
+        FuzzyState::Active {
+            prev_row: row,
+            row,
+            len: 0,
+            prev_char: None,
+            pending: [0; 4],
+            pending_len: 0,
+        }
+    }
+
+    fn is_match(&self, state: &FuzzyState) -> bool {
+        matches!(state, FuzzyState::Matched)
+    }
+
+    fn can_match(&self, state: &FuzzyState) -> bool {
+        !matches!(state, FuzzyState::Dead)
+    }
+
+    fn will_always_match(&self, state: &FuzzyState) -> bool {
+        matches!(state, FuzzyState::Matched)
+    }
+
+    fn accept(&self, state: &FuzzyState, byte: u8) -> FuzzyState {
+        let FuzzyState::Active {
+            prev_row,
+            row,
+            len,
+            prev_char,
+            mut pending,
+            mut pending_len,
+        } = *state
+        else {
+            return *state;
+        };
+
+        // Synthetic tokens never match query terms
+        if prev_char.is_none() && pending_len == 0 && crate::tokenizer::is_synthetic_byte(byte) {
+            return FuzzyState::Dead;
+        }
+
+        pending[pending_len] = byte;
+        pending_len += 1;
+
+        let expected_len = match pending[0] {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return FuzzyState::Dead,
+        };
+
+        if pending_len < expected_len {
+            return FuzzyState::Active {
+                prev_row,
+                row,
+                len,
+                prev_char,
+                pending,
+                pending_len,
+            };
+        }
+
+        let Some(c) = std::str::from_utf8(&pending[..pending_len])
+            .ok()
+            .and_then(|s| s.chars().next())
+        else {
+            return FuzzyState::Dead;
+        };
+
+        let len = len + 1;
+        let next = self.step(&prev_row, &row, len, prev_char, c);
+
+        if self.distance(&next, len, self.query.len()) <= self.max_edits {
+            FuzzyState::Matched
+        } else if next.iter().all(|&d| d > self.max_edits) {
+            FuzzyState::Dead
+        } else {
+            FuzzyState::Active {
+                prev_row: row,
+                row: next,
+                len,
+                prev_char: Some(c),
+                pending: [0; 4],
+                pending_len: 0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_edits() {
+        assert_eq!(max_edits("ab", 2), 0);
+        assert_eq!(max_edits("abc", 2), 1);
+        assert_eq!(max_edits("recieve", 1), 1);
+        assert_eq!(max_edits("recieve", 2), 2);
+        assert_eq!(max_edits("\x02pdf", 2), 0);
+    }
+
+    #[test]
+    fn test_fuzzy_automaton_edits() {
+        let automaton = FuzzyAutomaton::new("recieve", 1);
+        // Transposition
+        assert!(automaton.matches("receive"));
+        // Prefix of a longer token
+        assert!(automaton.matches("receiver"));
+        // Substitution, insertion and deletion
+        assert!(automaton.matches("recieva"));
+        assert!(automaton.matches("reciieve"));
+        assert!(automaton.matches("recive"));
+        assert!(!automaton.matches("receipt"));
+        assert!(!automaton.matches("\x00recieve"));
+        assert!(!automaton.matches("\x03recieve"));
+        assert!(!automaton.matches("\x04recieve"));
+    }
+
+    #[test]
+    fn test_fuzzy_automaton_unicode() {
+        let automaton = FuzzyAutomaton::new("документ", 1);
+        assert!(automaton.matches("докумнет"));
+        assert!(automaton.matches("документы"));
+        assert!(!automaton.matches("доклад"));
+    }
+
+    #[test]
+    fn test_fuzzy_automaton_sorted_walk() {
+        let map: BTreeMap<String, usize> = [
+            "\x00recieve",
+            "\x02pdf",
+            "ecieve",
+            "receipt",
+            "receive",
+            "receiver",
+            "recipe",
+            "recive",
+            "reciever",
+            "xrecieve",
+            "zebra",
+            "документ",
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, token)| (token.to_string(), i))
+        .collect();
+
+        for (query, edits) in [("recieve", 1), ("recieve", 2), ("докумнет", 1), ("rec", 0)]
+        {
+            let automaton = FuzzyAutomaton::new(query, edits);
+            let walked: Vec<_> = automaton.matching_entries(&map).map(|(k, _)| k).collect();
+            let expected: Vec<_> = map.keys().filter(|k| automaton.matches(k)).collect();
+            assert_eq!(walked, expected, "{query} within {edits} edits");
+        }
+
+        assert_eq!(prefix_successor("ab").as_deref(), Some("ac"));
+        assert_eq!(prefix_successor("a\u{10FFFF}").as_deref(), Some("b"));
+        assert_eq!(prefix_successor("\u{D7FF}").as_deref(), Some("\u{E000}"));
+        assert_eq!(prefix_successor("\u{10FFFF}"), None);
+    }
+
+    #[test]
+    fn test_fuzzy_automaton_fst() {
+        use fst::IntoStreamer as _;
+
+        let map = fst::Map::from_iter([("receipt", 0), ("receive", 1), ("recipe", 2)]).unwrap();
+        let automaton = FuzzyAutomaton::new("recieve", 1);
+        let keys = map.search(automaton).into_stream().into_str_keys().unwrap();
+        assert_eq!(keys, vec!["receive"]);
+    }
+}
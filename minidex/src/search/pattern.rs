@@ -0,0 +1,477 @@
+use regex_automata::{
+    Anchored, Input,
+    dfa::{Automaton as _, dense},
+    util::{primitives::StateID, start, syntax},
+};
+use regex_syntax::hir::{
+    Hir,
+    literal::{ExtractKind, Extractor},
+};
+use unicode_normalization::UnicodeNormalization;
+
+/// Upper bound for the memory used by a compiled pattern DFA
+const DFA_SIZE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// A pattern matched (case-insensitively) against full paths.
+/// Besides the DFA used to confirm matches, a second DFA is built from the
+/// literal fragments every match must contain. It is run over the token
+/// dictionaries to prune candidates before any document is read.
+pub(crate) struct PathPattern {
+    dfa: dense::DFA<Vec<u32>>,
+    prune: Option<dense::DFA<Vec<u32>>>,
+    required_tokens: Vec<String>,
+}
+
+impl PathPattern {
+    /// Compile a regular expression into a path pattern
+    pub fn from_regex(pattern: &str) -> Result<Self, String> {
+        Self::build(pattern, Vec::new())
+    }
+
+    /// Compile a glob into a path pattern.
+    /// A glob without separators matches the file name, e.g. `*.rs`. Absolute
+    /// globs match the whole path, and other globs match trailing components,
+    /// so `src/**/*.toml` matches `/home/me/project/src/a/b.toml`.
+    /// The literal directory prefix and extension of the glob are used as
+    /// exact synthetic tokens to narrow down candidates.
+    pub fn from_glob(glob: &str) -> Result<Self, String> {
+        let glob = glob.replace('/', std::path::MAIN_SEPARATOR_STR);
+        let regex = glob_to_regex(&glob)?;
+
+        Self::build(&regex, glob_required_tokens(&glob))
+    }
+
+    fn build(pattern: &str, required_tokens: Vec<String>) -> Result<Self, String> {
+        let dfa = Self::build_dfa(pattern, true)?;
+
+        let hir = regex_syntax::ParserBuilder::new()
+            .build()
+            .parse(pattern)
+            .map_err(|e| e.to_string())?;
+
+        let prune = match Self::required_fragments(&hir) {
+            Some(fragments) => {
+                let alternation = fragments
+                    .iter()
+                    .map(|f| regex_syntax::escape(f))
+                    .collect::<Vec<_>>()
+                    .join("|");
+                Some(Self::build_dfa(&alternation, false)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            dfa,
+            prune,
+            required_tokens,
+        })
+    }
+
+    fn build_dfa(pattern: &str, case_insensitive: bool) -> Result<dense::DFA<Vec<u32>>, String> {
+        dense::Builder::new()
+            .configure(
+                dense::Config::new()
+                    .dfa_size_limit(Some(DFA_SIZE_LIMIT))
+                    .determinize_size_limit(Some(DFA_SIZE_LIMIT)),
+            )
+            .syntax(syntax::Config::new().case_insensitive(case_insensitive))
+            .build(pattern)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Returns the folded fragments (one of which every match must contain),
+    /// or `None` if candidates can't be pruned for this pattern.
+    fn required_fragments(hir: &Hir) -> Option<Vec<String>> {
+        [ExtractKind::Prefix, ExtractKind::Suffix]
+            .into_iter()
+            .filter_map(|kind| {
+                let seq = Extractor::new().kind(kind).extract(hir);
+                let mut fragments = seq
+                    .literals()?
+                    .iter()
+                    .map(|lit| longest_word_run(&String::from_utf8_lossy(lit.as_bytes())))
+                    .collect::<Option<Vec<_>>>()?;
+                fragments.sort_unstable();
+                fragments.dedup();
+                Some(fragments)
+            })
+            // Prefer the set whose shortest fragment is the most selective
+            .max_by_key(|fragments| fragments.iter().map(|f| f.len()).min().unwrap_or(0))
+    }
+
+    /// Synthetic tokens every match is indexed with
+    pub fn required_tokens(&self) -> &[String] {
+        &self.required_tokens
+    }
+
+    /// Automaton accepting the (non-synthetic) tokens that may lead to a
+    /// match, for use with `fst::Map::search`. `None` if candidates can't be
+    /// pruned, in which case every document needs to be confirmed.
+    pub fn token_automaton(&self) -> Option<TokenAutomaton<'_>> {
+        self.prune.as_ref().map(TokenAutomaton::new)
+    }
+
+    /// Confirm a match against a full path
+    pub fn is_match(&self, path: &str) -> bool {
+        let input = Input::new(path).earliest(true);
+        matches!(self.dfa.try_search_fwd(&input), Ok(Some(_)))
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == std::path::MAIN_SEPARATOR
+}
+
+/// Whether a character has a special meaning in globs
+fn is_glob_special(c: char) -> bool {
+    matches!(c, '*' | '?' | '[' | ']' | '{' | '}') || (c == '\\' && !is_separator(c))
+}
+
+/// Translate a glob (with separators already normalized) into a regex
+fn glob_to_regex(glob: &str) -> Result<String, String> {
+    let sep = regex_syntax::escape(std::path::MAIN_SEPARATOR_STR);
+
+    let mut regex = if std::path::Path::new(glob).is_absolute() || glob.starts_with(is_separator) {
+        String::from("^")
+    } else {
+        format!("(?:^|{})", sep)
+    };
+
+    let mut chars = glob.chars().peekable();
+    let mut prev: Option<char> = None;
+    let mut brace_depth = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let at_boundary = prev.is_none_or(is_separator);
+                match chars.peek() {
+                    Some(&next) if at_boundary && is_separator(next) => {
+                        chars.next();
+                        regex.push_str(&format!("(?:.*{})?", sep));
+                    }
+                    None if at_boundary => regex.push_str(".*"),
+                    _ => regex.push_str(&format!("[^{}]*", sep)),
+                }
+            }
+            '*' => regex.push_str(&format!("[^{}]*", sep)),
+            '?' => regex.push_str(&format!("[^{}]", sep)),
+            '[' => {
+                let mut class = String::new();
+                let negated = matches!(chars.peek(), Some('!' | '^'));
+                if negated {
+                    chars.next();
+                }
+                // A leading ']' is part of the class
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    class.push_str("\\]");
+                }
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some('-') => class.push('-'),
+                        Some(c) => class.push_str(&regex_syntax::escape(&c.to_string())),
+                        None => return Err(format!("unclosed character class in '{}'", glob)),
+                    }
+                }
+                if negated {
+                    regex.push_str(&format!("[^{}{}]", class, sep));
+                } else {
+                    regex.push_str(&format!("[{}]", class));
+                }
+            }
+            '{' => {
+                brace_depth += 1;
+                regex.push_str("(?:");
+            }
+            '}' if brace_depth > 0 => {
+                brace_depth -= 1;
+                regex.push(')');
+            }
+            ',' if brace_depth > 0 => regex.push('|'),
+            '\\' if !is_separator(c) => match chars.next() {
+                Some(escaped) => regex.push_str(&regex_syntax::escape(&escaped.to_string())),
+                None => return Err(format!("dangling escape in '{}'", glob)),
+            },
+            c => regex.push_str(&regex_syntax::escape(&c.to_string())),
+        }
+        prev = Some(c);
+    }
+
+    if brace_depth > 0 {
+        return Err(format!("unclosed alternation in '{}'", glob));
+    }
+
+    regex.push('$');
+    Ok(regex)
+}
+
+/// Returns the synthetic tokens every path matching the glob is indexed with:
+/// its literal directory prefix (for absolute globs) and its extension.
+fn glob_required_tokens(glob: &str) -> Vec<String> {
+    use crate::tokenizer::{SYNTH_EXT_TOKEN_TAG, SYNTH_PATH_TOKEN_TAG, synthesize_token};
+
+    let mut tokens = Vec::new();
+
+    let literal = match glob.find(is_glob_special) {
+        Some(idx) => &glob[..idx],
+        None => glob,
+    };
+    if std::path::Path::new(glob).is_absolute() || glob.starts_with(is_separator) {
+        // Path tokens are only emitted for separators past the root
+        if let Some(idx) = literal.rfind(is_separator)
+            && idx > 0
+        {
+            tokens.push(synthesize_token(SYNTH_PATH_TOKEN_TAG, &literal[..=idx]));
+        }
+    }
+
+    let file_name = match glob.rfind(is_separator) {
+        Some(idx) => &glob[idx + 1..],
+        None => glob,
+    };
+    if let Some((_, ext)) = file_name.rsplit_once('.')
+        && !ext.is_empty()
+        && !ext.contains(is_glob_special)
+    {
+        tokens.push(synthesize_token(SYNTH_EXT_TOKEN_TAG, ext));
+    }
+
+    tokens
+}
+
+/// Returns the longest alphanumeric run (without script transitions) of a
+/// literal, folded the same way as tokens. Any path containing the literal
+/// has a token containing this run, as the tokenizer always emits whole words.
+fn longest_word_run(literal: &str) -> Option<String> {
+    let mut best = String::new();
+    let mut current = String::new();
+    let mut prev_char: Option<char> = None;
+
+    for c in literal.nfkc() {
+        let is_word = c.is_alphanumeric() && c != '\u{2014}';
+        let is_script_boundary =
+            prev_char.is_some_and(|p| crate::tokenizer::is_cjk(p) != crate::tokenizer::is_cjk(c));
+
+        if !is_word || is_script_boundary {
+            if current.chars().count() > best.chars().count() {
+                std::mem::swap(&mut best, &mut current);
+            }
+            current.clear();
+        }
+
+        if is_word {
+            current.push(c);
+        }
+        prev_char = Some(c);
+    }
+
+    if current.chars().count() > best.chars().count() {
+        best = current;
+    }
+
+    if best.is_empty() {
+        None
+    } else {
+        Some(crate::tokenizer::fold_path(&best))
+    }
+}
+
+/// `fst::Automaton` matching any token containing one of the required
+/// fragments of a `PathPattern`.
+pub(crate) struct TokenAutomaton<'a> {
+    dfa: &'a dense::DFA<Vec<u32>>,
+    start: Option<StateID>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum TokenState {
+    Start,
+    Searching(StateID),
+    Matched,
+    Dead,
+}
+
+impl<'a> TokenAutomaton<'a> {
+    fn new(dfa: &'a dense::DFA<Vec<u32>>) -> Self {
+        let start = dfa
+            .start_state(&start::Config::new().anchored(Anchored::No))
+            .ok();
+        Self { dfa, start }
+    }
+
+    /// Run the automaton over a whole token (for the in-memory index)
+    pub fn matches(&self, token: &str) -> bool {
+        use fst::Automaton as _;
+
+        let mut state = self.start();
+        for &b in token.as_bytes() {
+            state = self.accept(&state, b);
+            if !self.can_match(&state) {
+                return false;
+            }
+        }
+        self.is_match(&state)
+    }
+}
+
+impl fst::Automaton for TokenAutomaton<'_> {
+    type State = TokenState;
+
+    fn start(&self) -> TokenState {
+        match self.start {
+            Some(_) => TokenState::Start,
+            None => TokenState::Dead,
+        }
+    }
+
+    fn is_match(&self, state: &TokenState) -> bool {
+        match *state {
+            TokenState::Start => self
+                .start
+                .is_some_and(|id| self.dfa.is_match_state(self.dfa.next_eoi_state(id))),
+            TokenState::Searching(id) => self.dfa.is_match_state(self.dfa.next_eoi_state(id)),
+            TokenState::Matched => true,
+            TokenState::Dead => false,
+        }
+    }
+
+    fn can_match(&self, state: &TokenState) -> bool {
+        !matches!(state, TokenState::Dead)
+    }
+
+    fn will_always_match(&self, state: &TokenState) -> bool {
+        matches!(state, TokenState::Matched)
+    }
+
+    fn accept(&self, state: &TokenState, byte: u8) -> TokenState {
+        let id = match *state {
+            // Synthetic tokens are covered by regular tokens of the same paths
+            TokenState::Start if crate::tokenizer::is_synthetic_byte(byte) => {
+                return TokenState::Dead;
+            }
+            TokenState::Start => match self.start {
+                Some(id) => id,
+                None => return TokenState::Dead,
+            },
+            TokenState::Searching(id) => id,
+            other => return other,
+        };
+
+        let next = self.dfa.next_state(id, byte);
+        if self.dfa.is_match_state(next) {
+            TokenState::Matched
+        } else if self.dfa.is_dead_state(next) || self.dfa.is_quit_state(next) {
+            TokenState::Dead
+        } else {
+            TokenState::Searching(next)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_is_match_case_insensitive() {
+        let pattern = PathPattern::from_regex(r"report_\d{4}\.xlsx").unwrap();
+        assert!(pattern.is_match("/home/me/Report_2024.XLSX"));
+        assert!(!pattern.is_match("/home/me/report_24.xlsx"));
+    }
+
+    #[test]
+    fn test_required_fragments() {
+        let pattern = PathPattern::from_regex(r"report_\d{4}\.xlsx").unwrap();
+        let automaton = pattern
+            .token_automaton()
+            .expect("pattern should be prunable");
+        assert!(automaton.matches("report"));
+        assert!(automaton.matches("myreport"));
+        assert!(!automaton.matches("repo"));
+        assert!(!automaton.matches("\x00/report/"));
+
+        let pattern = PathPattern::from_regex(r"(invoice|receipt)s?").unwrap();
+        let automaton = pattern
+            .token_automaton()
+            .expect("pattern should be prunable");
+        assert!(automaton.matches("invoices"));
+        assert!(automaton.matches("receipt"));
+        assert!(!automaton.matches("report"));
+
+        let pattern = PathPattern::from_regex(r"\d+").unwrap();
+        assert!(pattern.token_automaton().is_none());
+    }
+
+    #[test]
+    fn test_longest_word_run() {
+        assert_eq!(longest_word_run("_report_20"), Some("report".to_string()));
+        assert_eq!(longest_word_run("Café-x"), Some("cafe".to_string()));
+        assert_eq!(longest_word_run("ab測試測"), Some("測試測".to_string()));
+        assert_eq!(longest_word_run("/."), None);
+    }
+
+    #[test]
+    fn test_glob_is_match() {
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let path = |p: &str| p.replace('/', sep);
+
+        let pattern = PathPattern::from_glob("*.rs").unwrap();
+        assert!(pattern.is_match(&path("/home/me/src/Main.RS")));
+        assert!(!pattern.is_match(&path("/home/me/src/main.rs.bak")));
+
+        let pattern = PathPattern::from_glob("**/src/**/*.toml").unwrap();
+        assert!(pattern.is_match(&path("/home/me/src/Cargo.toml")));
+        assert!(pattern.is_match(&path("/home/me/src/a/b/c.toml")));
+        assert!(!pattern.is_match(&path("/home/me/source/c.toml")));
+
+        let pattern = PathPattern::from_glob("/home/*/notes_[0-9]?.{md,txt}").unwrap();
+        assert!(pattern.is_match(&path("/home/me/notes_12.md")));
+        assert!(pattern.is_match(&path("/home/you/notes_3a.txt")));
+        assert!(!pattern.is_match(&path("/home/me/sub/notes_12.md")));
+        assert!(!pattern.is_match(&path("/other/home/me/notes_12.md")));
+        assert!(!pattern.is_match(&path("/home/me/notes_ab.md")));
+
+        let pattern = PathPattern::from_glob("src/*.rs").unwrap();
+        assert!(pattern.is_match(&path("/home/me/src/lib.rs")));
+        assert!(!pattern.is_match(&path("/home/me/mysrc/lib.rs")));
+
+        let pattern = PathPattern::from_glob("[!a]*.txt").unwrap();
+        assert!(pattern.is_match(&path("/home/b.txt")));
+        assert!(!pattern.is_match(&path("/home/a.txt")));
+    }
+
+    #[test]
+    fn test_glob_required_tokens() {
+        let sep = std::path::MAIN_SEPARATOR_STR;
+
+        let pattern = PathPattern::from_glob("/Home/me/*/src/*.RS").unwrap();
+        assert_eq!(
+            pattern.required_tokens(),
+            &[
+                format!("\x00{}home{}me{}", sep, sep, sep),
+                "\x02rs".to_string()
+            ]
+        );
+
+        let pattern = PathPattern::from_glob("**/*.{rs,toml}").unwrap();
+        assert!(pattern.required_tokens().is_empty());
+
+        let pattern = PathPattern::from_glob("/*.tar.gz").unwrap();
+        assert_eq!(pattern.required_tokens(), &["\x02gz".to_string()]);
+    }
+
+    #[test]
+    fn test_invalid_glob() {
+        assert!(PathPattern::from_glob("[abc").is_err());
+        assert!(PathPattern::from_glob("{a,b").is_err());
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        assert!(PathPattern::from_regex(r"report(").is_err());
+    }
+}
@@ -31,6 +31,7 @@ pub struct ScoringWeights {
     // Penalties
     pub penalty_extension: f64,
     pub penalty_midword: f64,
+    pub penalty_fuzzy: f64,
 
     // Final multipliers
     /// Maximum boost for brand new files
@@ -62,6 +63,7 @@ impl Default for ScoringWeights {
 
             penalty_extension: 0.5, // Cuts token score in half
             penalty_midword: 0.8,   // 20% penalty for not starting on a boundary
+            penalty_fuzzy: 0.6,     // 40% penalty for matching with typos
 
             mult_recency_max: 1.2, // 20% max boost for recent files
             recency_decay_rate: 0.1,
@@ -86,6 +88,8 @@ pub struct ScoringInputs<'a> {
     pub last_accessed: u64,
     pub kind: Kind,
     pub now_micros: f64,
+    /// Edits tolerated when matching query tokens (see `SearchOptions::fuzziness`)
+    pub fuzziness: u8,
 }
 
 pub(crate) fn compute_score(weights: &ScoringWeights, inputs: &ScoringInputs) -> f64 {
@@ -187,6 +191,35 @@ pub(crate) fn compute_score(weights: &ScoringWeights, inputs: &ScoringInputs) ->
             }
         }
 
+        // Fuzzy matches only count when there is no exact one
+        let edits = crate::search::max_edits(t_str, inputs.fuzziness);
+        if token_best == 0.0 && edits > 0 {
+            let automaton = crate::search::FuzzyAutomaton::new(t_str, edits);
+
+            for word in trimmed_path.split(|c: char| !c.is_alphanumeric()) {
+                let idx = word.as_ptr() as usize - trimmed_path.as_ptr() as usize;
+
+                let is_fuzzy_match = if is_ascii {
+                    automaton.matches(&word.to_ascii_lowercase())
+                } else {
+                    automaton.matches(word)
+                };
+                if word.is_empty() || !is_fuzzy_match {
+                    continue;
+                }
+
+                unique_matched_indices.push(idx);
+                let match_val = if idx == file_name_start_idx {
+                    weights.base_filename_prefix
+                } else if idx >= file_name_start_idx {
+                    weights.base_in_filename
+                } else {
+                    weights.base_in_path
+                };
+                token_best = token_best.max(match_val * weights.penalty_fuzzy);
+            }
+        }
+
         // Extension Penalty
         let ends_with_ext = if is_ascii {
             h_bytes.len() > n_len
@@ -382,6 +415,7 @@ mod tests {
             last_accessed: 1_000_000,
             kind: Kind::File,
             now_micros: now,
+            fuzziness: 0,
         };
 
         let inputs2 = ScoringInputs {
@@ -392,6 +426,7 @@ mod tests {
             last_accessed: 1_000_000,
             kind: Kind::File,
             now_micros: now,
+            fuzziness: 0,
         };
 
         let score1 = compute_score(&weights, &inputs1);
@@ -418,6 +453,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
         let score2 = compute_score(
@@ -430,6 +466,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
 
@@ -457,6 +494,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
         let score2 = compute_score(
@@ -469,6 +507,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
 
@@ -493,6 +532,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
         let deep = compute_score(
@@ -505,6 +545,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
 
@@ -528,6 +569,7 @@ mod tests {
                 last_accessed: 1_900_000_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
         let score_old = compute_score(
@@ -540,6 +582,7 @@ mod tests {
                 last_accessed: 1_000_000_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
 
@@ -563,6 +606,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
         let score_unordered = compute_score(
@@ -575,6 +619,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
 
@@ -600,6 +645,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
         // Base in-filename is 0.5 * 100 = 50. Coverage penalty applies (1/3 words matched),
@@ -619,6 +665,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
         // Should score fairly high as it matches multiple tokens and gets a proximity boost.
@@ -645,6 +692,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
 
@@ -658,6 +706,7 @@ mod tests {
                 last_accessed: 1_000_000,
                 kind: Kind::File,
                 now_micros: now,
+                fuzziness: 0,
             },
         );
 
@@ -3,7 +3,7 @@ use std::os::windows::fs::OpenOptionsExt;
 use std::{
     collections::BTreeMap,
     fs::{File, OpenOptions},
-    io::{BufWriter, Write},
+    io::{BufWriter, Read, Write},
 };
 
 use crate::sync::{
@@ -11,18 +11,27 @@ use crate::sync::{
     atomic::{AtomicBool, Ordering},
 };
 
-use crate::{Kind, Path, PathBuf, entry::IndexEntry, leb128::DeltaLeb128Iterator};
+use crate::{
+    Kind, Path, PathBuf, VolumeType,
+    crc32c::{crc32c, crc32c_append},
+    entry::IndexEntry,
+    leb128::DeltaLeb128Iterator,
+};
 use fs4::fs_std::FileExt;
 use fst::Map;
 use memmap2::Mmap;
 use thiserror::Error;
 
 pub(crate) mod compactor;
+pub(crate) mod manifest;
+pub(crate) use manifest::Manifest;
 mod utils;
 
 pub(crate) type DocumentId = u32;
 
 const LOCK_FILE: &str = ".minidex.lock";
+/// Directory corrupt segments are moved to, see `SegmentedIndex::quarantined`
+const QUARANTINE_DIR: &str = "quarantine";
 
 /// FTS mapping tokens to posting offsets
 const SEGMENT_EXT: &str = "seg";
@@ -30,18 +39,260 @@ const SEGMENT_EXT: &str = "seg";
 const DATA_EXT: &str = "dat";
 /// Posting (arrays of u32 Document IDs) files
 const POST_EXT: &str = "post";
-/// Flat array of 16-byte u128 integers containing document IDs
+/// Flat array of `META_RECORD_SIZE` byte metadata records, by document ID
 const META_EXT: &str = "meta";
 
-const DATA_MAGIC: &[u8; 4] = b"zMDX";
+/// Data magic of the first format version, with fixed size index entries
+/// and 16-byte metadata records holding only the packed u128 metadata
+const DATA_MAGIC_V1: &[u8; 4] = b"zMDX";
+
+/// Magics of the headers starting each file of a segment since the second
+/// format version, followed by the format version (u32)
+const SEGMENT_MAGIC: &[u8; 4] = b"zMDS";
+const DATA_MAGIC: &[u8; 4] = b"zMDD";
+const POST_MAGIC: &[u8; 4] = b"zMDP";
+const META_MAGIC: &[u8; 4] = b"zMDM";
+const HEADER_SIZE: usize = 8;
+/// Since the second format version, each file of a segment ends with the
+/// CRC32C (u32) of its preceding bytes, and each data record with the
+/// CRC32C of its compressed bytes
+const CHECKSUM_SIZE: usize = size_of::<u32>();
+
+/// Format version of segments written by `build_segment_files`
+const FORMAT_VERSION: u32 = 2;
+
+/// Metadata record: data offset, last modified, last accessed and creation
+/// times in seconds, size in bytes (u64), owner user and group ids, mode
+/// bits (u32), depth (u16), category, volume type, flags (u8) and 7
+/// reserved bytes
+const META_RECORD_SIZE: usize = 64;
+const META_RECORD_SIZE_V1: usize = size_of::<u128>();
+
+const META_FLAG_DIR: u8 = 1 << 0;
+const META_FLAG_HIDDEN: u8 = 1 << 1;
+const META_FLAG_DELETED: u8 = 1 << 2;
+
+/// Fixed size metadata of a document, evaluated when pre-filtering candidates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DocMeta {
+    /// Offset of the document in the data file
+    pub dat_offset: u64,
+    /// Last modified time, in seconds
+    pub last_modified: u64,
+    /// Last accessed time, in seconds
+    pub last_accessed: u64,
+    /// Creation time, in seconds
+    pub created: u64,
+    /// Size in bytes
+    pub size: u64,
+    /// Owner user id, `UNKNOWN_ID` if unknown
+    pub uid: u32,
+    /// Owner group id, `UNKNOWN_ID` if unknown
+    pub gid: u32,
+    /// Permission and file type bits (`0` if unknown)
+    pub mode: u32,
+    /// Number of separators in the path
+    pub depth: u16,
+    pub is_dir: bool,
+    pub category: u8,
+    pub volume_type: u8,
+    pub hidden: bool,
+    /// Whether the document is a deletion
+    pub deleted: bool,
+}
+
+impl DocMeta {
+    /// Owner id of entries whose ownership is unknown. `(uid_t)-1` is
+    /// never a valid owner.
+    pub const UNKNOWN_ID: u32 = u32::MAX;
+
+    pub fn new(dat_offset: u64, path: &str, entry: &IndexEntry) -> Self {
+        let depth = path
+            .bytes()
+            .filter(|&b| std::path::is_separator(b as char))
+            .count()
+            .min(u16::MAX as usize) as u16;
+
+        Self {
+            dat_offset,
+            last_modified: entry.last_modified / 1_000_000,
+            last_accessed: entry.last_accessed / 1_000_000,
+            created: entry.created / 1_000_000,
+            size: entry.size,
+            uid: entry.uid.unwrap_or(Self::UNKNOWN_ID),
+            gid: entry.gid.unwrap_or(Self::UNKNOWN_ID),
+            mode: entry.mode.unwrap_or(0),
+            depth,
+            is_dir: entry.kind == Kind::Directory,
+            category: entry.category,
+            volume_type: entry.volume_type as u8,
+            hidden: entry.is_hidden(path),
+            deleted: entry.opstamp.is_deletion(),
+        }
+    }
+
+    /// Metadata of segments of the first format version, from their packed
+    /// u128 metadata (see `SegmentedIndex::pack_u128`). Sizes, creation times,
+    /// ownership and permissions are unknown.
+    fn legacy(packed: u128) -> Self {
+        let (
+            dat_offset,
+            last_modified,
+            last_accessed,
+            depth,
+            is_dir,
+            category,
+            volume_type,
+            deleted,
+        ) = SegmentedIndex::unpack_u128(packed);
+        Self {
+            dat_offset,
+            last_modified,
+            last_accessed,
+            created: 0,
+            size: 0,
+            uid: Self::UNKNOWN_ID,
+            gid: Self::UNKNOWN_ID,
+            mode: 0,
+            depth,
+            is_dir,
+            category,
+            volume_type,
+            hidden: false,
+            deleted,
+        }
+    }
+
+    fn as_bytes(&self) -> [u8; META_RECORD_SIZE] {
+        let mut flags = 0;
+        if self.is_dir {
+            flags |= META_FLAG_DIR;
+        }
+        if self.hidden {
+            flags |= META_FLAG_HIDDEN;
+        }
+        if self.deleted {
+            flags |= META_FLAG_DELETED;
+        }
+
+        let mut buf = [0u8; META_RECORD_SIZE];
+        buf[0..8].copy_from_slice(&self.dat_offset.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.last_modified.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.last_accessed.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.created.to_le_bytes());
+        buf[32..40].copy_from_slice(&self.size.to_le_bytes());
+        buf[40..44].copy_from_slice(&self.uid.to_le_bytes());
+        buf[44..48].copy_from_slice(&self.gid.to_le_bytes());
+        buf[48..52].copy_from_slice(&self.mode.to_le_bytes());
+        buf[52..54].copy_from_slice(&self.depth.to_le_bytes());
+        buf[54] = self.category;
+        buf[55] = self.volume_type;
+        buf[56] = flags;
+        buf
+    }
+}
+
+/// Header starting each file of a segment since the second format version
+fn header(magic: &[u8; 4]) -> [u8; HEADER_SIZE] {
+    let mut buf = [0u8; HEADER_SIZE];
+    buf[..4].copy_from_slice(magic);
+    buf[4..].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    buf
+}
+
+/// Checks that a segment file starts with the header of the given format
+/// version
+fn check_header(
+    path: &Path,
+    data: &[u8],
+    magic: &[u8; 4],
+    version: u32,
+) -> Result<(), SegmentedIndexError> {
+    if data.len() < HEADER_SIZE || !data.starts_with(magic) {
+        return Err(SegmentedIndexError::InvalidHeader(path.to_path_buf()));
+    }
+    let found = u32::from_le_bytes(data[4..HEADER_SIZE].try_into().unwrap());
+    if found != version {
+        return Err(SegmentedIndexError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            version: found,
+        });
+    }
+    Ok(())
+}
+
+/// Checks the checksum footer of a segment file, returning the length of
+/// the content before it
+fn check_footer(path: &Path, data: &[u8]) -> Result<usize, SegmentedIndexError> {
+    let Some(content_len) = data.len().checked_sub(CHECKSUM_SIZE) else {
+        return Err(SegmentedIndexError::ChecksumMismatch(path.to_path_buf()));
+    };
+    let expected = u32::from_le_bytes(data[content_len..].try_into().unwrap());
+    if crc32c(&data[..content_len]) != expected {
+        return Err(SegmentedIndexError::ChecksumMismatch(path.to_path_buf()));
+    }
+    Ok(content_len)
+}
+
+/// Writer keeping the running checksum of the bytes written through it,
+/// appended as a footer by `finish`
+struct ChecksumWriter<W: Write> {
+    inner: W,
+    crc: u32,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, crc: 0 }
+    }
+
+    fn finish(mut self) -> std::io::Result<W> {
+        self.inner.write_all(&self.crc.to_le_bytes())?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc = crc32c_append(self.crc, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The FST of a segment, between the header and footer of the `.seg` file
+pub(crate) struct SegmentMap {
+    mmap: Mmap,
+    start: usize,
+    end: usize,
+}
+
+impl AsRef<[u8]> for SegmentMap {
+    fn as_ref(&self) -> &[u8] {
+        &self.mmap[self.start..self.end]
+    }
+}
 
 /// A live index segment
 pub(crate) struct Segment {
-    map: Option<Map<Mmap>>,
+    map: Option<Map<SegmentMap>>,
     data: Option<Mmap>,
     dict: Option<Vec<u8>>,
     post: Option<Mmap>,
     meta: Option<Mmap>,
+    /// Format version the segment was written in (1 to `FORMAT_VERSION`)
+    format_version: u32,
+    /// Offsets of the first document and past the last one in the data file
+    docs_start: usize,
+    docs_end: usize,
+    /// Offsets of the first metadata record and past the last one in the
+    /// meta file
+    meta_start: usize,
+    meta_end: usize,
     path: PathBuf,
     deleted: AtomicBool,
 }
@@ -51,41 +302,86 @@ impl Segment {
     pub fn load(path: PathBuf) -> Result<Self, SegmentedIndexError> {
         let (seg_path, dat_path, post_path, meta_path) = Self::to_paths(&path);
 
-        let seg_file = File::open(&seg_path).map_err(SegmentedIndexError::Io)?;
-        let seg = unsafe { Mmap::map(&seg_file).map_err(SegmentedIndexError::Io)? };
-        utils::prefetch_memory(&seg);
-
-        let map = Map::new(seg).map_err(SegmentedIndexError::Fst)?;
-
-        // Load the data file for the same segment
-        let dat_file = File::open(dat_path).map_err(SegmentedIndexError::Io)?;
+        // Load the data file first, since it tells the format version
+        let dat_file = File::open(&dat_path).map_err(SegmentedIndexError::Io)?;
         let data = unsafe { Mmap::map(&dat_file).map_err(SegmentedIndexError::Io)? };
 
-        let mut dict = None;
-        if data.len() >= DATA_MAGIC.len() && &data[0..DATA_MAGIC.len()] == DATA_MAGIC {
-            let dict_len = u32::from_le_bytes(
-                data[DATA_MAGIC.len()..DATA_MAGIC.len() + size_of::<u32>()]
-                    .try_into()
-                    .map_err(|_| {
-                        SegmentedIndexError::Io(std::io::Error::new(
-                            std::io::ErrorKind::InvalidData,
-                            "Invalid dictionary length",
-                        ))
-                    })?,
-            ) as usize;
-            if DATA_MAGIC.len() + size_of::<u32>() + dict_len <= data.len() {
-                dict = Some(
-                    data[DATA_MAGIC.len() + size_of::<u32>()
-                        ..DATA_MAGIC.len() + size_of::<u32>() + dict_len]
-                        .to_vec(),
-                );
+        let (format_version, header_len) = if data.starts_with(DATA_MAGIC) {
+            let version = data
+                .get(DATA_MAGIC.len()..HEADER_SIZE)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or_else(|| SegmentedIndexError::InvalidHeader(dat_path.clone()))?;
+            if version != FORMAT_VERSION {
+                return Err(SegmentedIndexError::UnsupportedVersion {
+                    path: dat_path,
+                    version,
+                });
             }
+            (version, HEADER_SIZE)
+        } else if data.starts_with(DATA_MAGIC_V1) {
+            (1, DATA_MAGIC_V1.len())
+        } else {
+            // Uncompressed data of the first format version
+            (1, 0)
+        };
+        // Files of the first format version have neither headers nor
+        // checksums
+        let has_header = format_version > 1;
+        let has_checksums = has_header;
+
+        // Files are verified in full before anything else is read from them
+        let docs_end = if has_checksums {
+            check_footer(&dat_path, &data)?
+        } else {
+            data.len()
+        };
+
+        let mut dict = None;
+        let mut docs_start = 0;
+        if header_len > 0 {
+            let dict_start = header_len + size_of::<u32>();
+            let dict_len = data
+                .get(header_len..dict_start)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+                .filter(|dict_len| dict_start + dict_len <= docs_end)
+                .ok_or_else(|| SegmentedIndexError::InvalidHeader(dat_path.clone()))?;
+            dict = Some(data[dict_start..dict_start + dict_len].to_vec());
+            docs_start = dict_start + dict_len;
         }
 
-        // Load the postings
+        let seg_file = File::open(&seg_path).map_err(SegmentedIndexError::Io)?;
+        let seg = unsafe { Mmap::map(&seg_file).map_err(SegmentedIndexError::Io)? };
+        utils::prefetch_memory(&seg);
+
+        let seg_start = if has_header {
+            check_header(&seg_path, &seg, SEGMENT_MAGIC, format_version)?;
+            HEADER_SIZE
+        } else {
+            0
+        };
+        let seg_end = if has_checksums {
+            check_footer(&seg_path, &seg)?
+        } else {
+            seg.len()
+        };
+        let map = Map::new(SegmentMap {
+            mmap: seg,
+            start: seg_start,
+            end: seg_end,
+        })
+        .map_err(SegmentedIndexError::Fst)?;
+
+        // Load the postings, whose offsets in the FST already account for
+        // the header
         let post_file =
             Self::open_file_with_random_access(&post_path).map_err(SegmentedIndexError::Io)?;
         let post = unsafe { Mmap::map(&post_file).map_err(SegmentedIndexError::Io)? };
+        if has_header {
+            check_header(&post_path, &post, POST_MAGIC, format_version)?;
+        }
+        if has_checksums {
+            check_footer(&post_path, &post)?;
+        }
         #[cfg(unix)]
         post.advise(memmap2::Advice::Random)?;
 
@@ -93,6 +389,17 @@ impl Segment {
         let meta_file =
             Self::open_file_with_random_access(&meta_path).map_err(SegmentedIndexError::Io)?;
         let meta = unsafe { Mmap::map(&meta_file).map_err(SegmentedIndexError::Io)? };
+        let meta_start = if has_header {
+            check_header(&meta_path, &meta, META_MAGIC, format_version)?;
+            HEADER_SIZE
+        } else {
+            0
+        };
+        let meta_end = if has_checksums {
+            check_footer(&meta_path, &meta)?
+        } else {
+            meta.len()
+        };
         #[cfg(unix)]
         {
             meta.advise(memmap2::Advice::WillNeed)?;
@@ -105,6 +412,11 @@ impl Segment {
             dict,
             post: Some(post),
             meta: Some(meta),
+            format_version,
+            docs_start,
+            docs_end,
+            meta_start,
+            meta_end,
             path,
             deleted: AtomicBool::new(false),
         })
@@ -123,6 +435,15 @@ impl Segment {
         options.open(path)
     }
 
+    /// Name of the segment, as listed in the manifest (e.g. `1234`)
+    pub(crate) fn name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
     pub(crate) fn mark_deleted(&self) {
         self.deleted.store(true, Ordering::SeqCst);
     }
@@ -180,21 +501,20 @@ impl Segment {
         out.extend(iter);
     }
 
+    /// Number of documents in the posting list at `offset`, without decoding it
+    pub(crate) fn posting_len(&self, offset: u64) -> usize {
+        let start = offset as usize;
+        let post = self.post.as_ref().expect("posting should be loaded");
+
+        post.get(start..start + size_of::<u32>())
+            .map_or(0, |count| {
+                u32::from_le_bytes(count.try_into().unwrap()) as usize
+            })
+    }
+
     /// Iterator over the documents in this segment
     pub(crate) fn documents(&self) -> DocumentIterator<'_> {
-        let mut cursor = 0;
-        if let Some(data) = self.data.as_ref()
-            && data.len() >= DATA_MAGIC.len()
-            && &data[0..DATA_MAGIC.len()] == DATA_MAGIC
-        {
-            let dict_len = u32::from_le_bytes(
-                data[DATA_MAGIC.len()..DATA_MAGIC.len() + size_of::<u32>()]
-                    .try_into()
-                    .unwrap_or([0; 4]),
-            ) as usize;
-            cursor = DATA_MAGIC.len() + size_of::<u32>() + dict_len;
-        }
-        DocumentIterator::new(self, cursor)
+        DocumentIterator::new(self, self.docs_start)
     }
 
     /// Reads document data for the given offset.
@@ -203,30 +523,65 @@ impl Segment {
         let data = self.data.as_ref().expect("expected data to be loaded");
 
         if let Some(dict) = &self.dict {
-            if cursor + size_of::<u32>() > data.len() {
-                return None;
-            }
-            let compressed_len =
-                u32::from_le_bytes(data[cursor..cursor + size_of::<u32>()].try_into().unwrap())
-                    as usize;
-            if cursor + size_of::<u32>() + compressed_len > data.len() {
-                return None;
+            let (compressed, _) = self.compressed_record(cursor)?;
+            self.decompress_document(dict, compressed?)
+        } else {
+            self.parse_document_owned(&data[..self.docs_end], cursor)
+                .map(|(p, v, e, _)| (p, v, e))
+        }
+    }
+
+    /// Compressed bytes of the data record at `cursor`, and the offset of
+    /// the next record. `None` past the last record, and no bytes for
+    /// records failing their checksum.
+    fn compressed_record(&self, cursor: usize) -> Option<(Option<&[u8]>, usize)> {
+        let data = &self.data.as_ref().expect("expected data to be loaded")[..self.docs_end];
+
+        let start = cursor.checked_add(size_of::<u32>())?;
+        let compressed_len = u32::from_le_bytes(data.get(cursor..start)?.try_into().unwrap());
+        let mut next = start.checked_add(compressed_len as usize)?;
+        let compressed = data.get(start..next)?;
+
+        if self.format_version > 1 {
+            let checksum = data.get(next..next + CHECKSUM_SIZE)?;
+            next += CHECKSUM_SIZE;
+            if crc32c(compressed) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+                log::error!(
+                    "Checksum mismatch in the data record at {} of segment {:?}",
+                    cursor,
+                    self.path
+                );
+                return Some((None, next));
             }
-            let compressed_data =
-                &data[cursor + size_of::<u32>()..cursor + size_of::<u32>() + compressed_len];
+        }
 
-            // Fast block decompression
-            let mut decompressed = vec![0u8; 8 * 1024];
-            let size = zstd::bulk::Decompressor::with_dictionary(dict)
+        Some((Some(compressed), next))
+    }
+
+    fn decompress_document(
+        &self,
+        dict: &[u8],
+        compressed: &[u8],
+    ) -> Option<(String, String, IndexEntry)> {
+        // Fast block decompression when the frame records its size, which
+        // is the case for records compressed with the segment dictionary
+        let decompressed = match zstd::zstd_safe::get_frame_content_size(compressed) {
+            Ok(Some(size)) => zstd::bulk::Decompressor::with_dictionary(dict)
                 .ok()?
-                .decompress_to_buffer(compressed_data, &mut decompressed)
-                .ok()?;
-            decompressed.truncate(size);
+                .decompress(compressed, usize::try_from(size).ok()?)
+                .ok()?,
+            _ => {
+                let mut decompressed = Vec::new();
+                zstd::stream::read::Decoder::with_dictionary(compressed, dict)
+                    .ok()?
+                    .read_to_end(&mut decompressed)
+                    .ok()?;
+                decompressed
+            }
+        };
 
-            Self::parse_document_owned(&decompressed, 0).map(|(p, v, e, _)| (p, v, e))
-        } else {
-            Self::parse_document_owned(data, cursor).map(|(p, v, e, _)| (p, v, e))
-        }
+        self.parse_document_owned(&decompressed, 0)
+            .map(|(p, v, e, _)| (p, v, e))
     }
 
     /// Stream document IDs into a closure.
@@ -260,6 +615,66 @@ impl Segment {
         self.meta.as_ref().expect("meta should be loaded")
     }
 
+    fn meta_record_size(&self) -> usize {
+        if self.format_version == 1 {
+            META_RECORD_SIZE_V1
+        } else {
+            META_RECORD_SIZE
+        }
+    }
+
+    /// Number of documents in this segment
+    pub(crate) fn doc_count(&self) -> usize {
+        self.meta_end.saturating_sub(self.meta_start) / self.meta_record_size()
+    }
+
+    /// Metadata of a document. Fields missing from segments written by older
+    /// versions are unknown (see `DocMeta::legacy`).
+    #[inline(always)]
+    pub(crate) fn doc_meta(&self, doc_id: DocumentId) -> Option<DocMeta> {
+        let meta = self.meta_map();
+        let record_size = self.meta_record_size();
+        let byte_offset = self.meta_start + doc_id as usize * record_size;
+
+        if byte_offset + record_size > self.meta_end {
+            return None;
+        }
+
+        // Inline reads to avoid incurring slice conversion penalties
+        let record = unsafe { meta.as_ptr().add(byte_offset) };
+        let read_u64 = |offset: usize| {
+            unsafe { std::ptr::read_unaligned(record.add(offset) as *const u64) }.to_le()
+        };
+        let read_u32 = |offset: usize| {
+            unsafe { std::ptr::read_unaligned(record.add(offset) as *const u32) }.to_le()
+        };
+        let read_u8 = |offset: usize| unsafe { *record.add(offset) };
+
+        if self.format_version == 1 {
+            let packed = unsafe { std::ptr::read_unaligned(record as *const u128) }.to_le();
+            return Some(DocMeta::legacy(packed));
+        }
+
+        let flags = read_u8(56);
+        Some(DocMeta {
+            dat_offset: read_u64(0),
+            last_modified: read_u64(8),
+            last_accessed: read_u64(16),
+            created: read_u64(24),
+            size: read_u64(32),
+            uid: read_u32(40),
+            gid: read_u32(44),
+            mode: read_u32(48),
+            depth: unsafe { std::ptr::read_unaligned(record.add(52) as *const u16) }.to_le(),
+            category: read_u8(54),
+            // Unknown volume types of newer writers read as `Unknown`
+            volume_type: VolumeType::from(read_u8(55)) as u8,
+            is_dir: flags & META_FLAG_DIR != 0,
+            hidden: flags & META_FLAG_HIDDEN != 0,
+            deleted: flags & META_FLAG_DELETED != 0,
+        })
+    }
+
     pub(crate) fn remove_files(paths: &(PathBuf, PathBuf, PathBuf, PathBuf)) {
         let _ = std::fs::remove_file(&paths.0);
         let _ = std::fs::remove_file(&paths.1);
@@ -278,10 +693,11 @@ impl Segment {
         Ok(())
     }
 
-    fn parse_document_borrowed(
-        data: &[u8],
+    fn parse_document_borrowed<'a>(
+        &self,
+        data: &'a [u8],
         mut cursor: usize,
-    ) -> Option<(&str, &str, IndexEntry, usize)> {
+    ) -> Option<(&'a str, &'a str, IndexEntry, usize)> {
         let data_len = data.len();
 
         if cursor + size_of::<u32>() > data_len {
@@ -313,27 +729,42 @@ impl Segment {
 
         cursor += volume_len;
 
-        if cursor + IndexEntry::SIZE > data_len {
+        let entry_len = if self.format_version == 1 {
+            IndexEntry::LEGACY_SIZE
+        } else {
+            if cursor + size_of::<u16>() > data_len {
+                return None;
+            }
+            let entry_len =
+                u16::from_le_bytes(data[cursor..cursor + size_of::<u16>()].try_into().unwrap())
+                    as usize;
+            cursor += size_of::<u16>();
+            entry_len
+        };
+
+        if cursor + entry_len > data_len {
             return None;
         }
-        let entry = IndexEntry::from_bytes(&data[cursor..cursor + IndexEntry::SIZE]);
-        cursor += IndexEntry::SIZE;
+        let entry = IndexEntry::from_bytes(&data[cursor..cursor + entry_len])?;
+        cursor += entry_len;
 
         Some((path_str, volume_str, entry, cursor))
     }
 
     fn parse_document_owned(
+        &self,
         data: &[u8],
         cursor: usize,
     ) -> Option<(String, String, IndexEntry, usize)> {
-        Self::parse_document_borrowed(data, cursor).map(|(path, volume, entry, cursor)| {
-            (path.to_owned(), volume.to_owned(), entry, cursor)
-        })
+        self.parse_document_borrowed(data, cursor)
+            .map(|(path, volume, entry, cursor)| {
+                (path.to_owned(), volume.to_owned(), entry, cursor)
+            })
     }
 }
 
-impl AsRef<Map<Mmap>> for Segment {
-    fn as_ref(&self) -> &Map<Mmap> {
+impl AsRef<Map<SegmentMap>> for Segment {
+    fn as_ref(&self) -> &Map<SegmentMap> {
         self.map.as_ref().unwrap()
     }
 }
@@ -358,13 +789,27 @@ impl Drop for Segment {
 #[derive(Clone)]
 pub struct SegmentedIndex {
     segments: Vec<Arc<Segment>>,
+    quarantined: Arc<Vec<PathBuf>>,
     _lockfile: Arc<File>,
 }
 
 impl SegmentedIndex {
-    /// Open an on-disk index, locking the target directory and reading all
-    /// segment files found in it.
+    /// Open an on-disk index, locking the target directory and reading the
+    /// segments listed in its manifest (see `open_with_manifest`).
     pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, SegmentedIndexError> {
+        Self::open_with_manifest(dir).map(|(index, _)| index)
+    }
+
+    /// Open an on-disk index, locking the target directory and reading the
+    /// segments listed in its manifest. Segment files missing from the
+    /// manifest are left over from interrupted flushes or compactions, and
+    /// are removed.
+    /// Indexes written before manifests existed have all the segment files
+    /// found in the directory loaded instead, and an empty manifest listing
+    /// them is returned.
+    pub(crate) fn open_with_manifest<P: AsRef<Path>>(
+        dir: P,
+    ) -> Result<(Self, Manifest), SegmentedIndexError> {
         std::fs::create_dir_all(&dir)?;
         let lock_path = dir.as_ref().join(LOCK_FILE);
         let lockfile = OpenOptions::new()
@@ -379,14 +824,53 @@ impl SegmentedIndex {
             .try_lock_exclusive()
             .map_err(SegmentedIndexError::LockfileError)?;
 
-        let entries = std::fs::read_dir(&dir)?;
-
         let mut result = Self {
             segments: Vec::new(),
+            quarantined: Arc::new(Vec::new()),
             _lockfile: Arc::new(lockfile),
         };
 
-        for entry in entries.flatten() {
+        let Some(manifest) = Manifest::load(dir.as_ref())? else {
+            result.load_all(dir.as_ref())?;
+            let manifest = Manifest {
+                segments: result.segments().map(|segment| segment.name()).collect(),
+                ..Default::default()
+            };
+            return Ok((result, manifest));
+        };
+
+        let _ = std::fs::remove_file(Manifest::tmp_path(dir.as_ref()));
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            let is_segment_file = path.extension().is_some_and(|ext| {
+                [SEGMENT_EXT, DATA_EXT, POST_EXT, META_EXT]
+                    .iter()
+                    .any(|segment_ext| ext == *segment_ext)
+            });
+            let is_live = path
+                .file_stem()
+                .is_some_and(|stem| manifest.segments.iter().any(|name| stem == name.as_str()));
+
+            if is_segment_file && !is_live {
+                log::debug!(
+                    "Removing segment file missing from the manifest: {:?}",
+                    path
+                );
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+
+        for name in &manifest.segments {
+            result.load_or_quarantine(&dir.as_ref().join(name))?;
+        }
+
+        Ok((result, manifest))
+    }
+
+    /// Load all the segment files found in `dir`, for indexes without a
+    /// manifest
+    fn load_all(&mut self, dir: &Path) -> Result<(), SegmentedIndexError> {
+        for entry in std::fs::read_dir(dir)?.flatten() {
             let path = entry.path();
             if path.extension().is_some_and(|ext| ext == SEGMENT_EXT) {
                 let file_name = path.file_name().unwrap_or_default().to_string_lossy();
@@ -401,19 +885,48 @@ impl SegmentedIndex {
 
                     continue; // Skip loading!
                 }
-                result.load(entry.path())?;
+                self.load_or_quarantine(&path.with_extension(""))?;
             }
         }
 
-        Ok(result)
+        Ok(())
     }
 
-    /// Load a segment into the index
-    pub(crate) fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SegmentedIndexError> {
-        let segment = Segment::load(path.as_ref().to_path_buf())?;
+    /// Load a segment into the index, or move its files to the quarantine
+    /// directory if they are corrupt
+    fn load_or_quarantine(&mut self, path: &Path) -> Result<(), SegmentedIndexError> {
+        match Segment::load(path.to_path_buf()) {
+            Ok(segment) => {
+                self.segments.push(Arc::new(segment));
+                Ok(())
+            }
+            Err(e) if e.is_corruption() => {
+                log::error!("Quarantining corrupt segment {:?}: {}", path, e);
+
+                let quarantine_dir = path.with_file_name(QUARANTINE_DIR);
+                std::fs::create_dir_all(&quarantine_dir)?;
+                let (seg, dat, post, meta) = Segment::to_paths(path);
+                for file in [seg, dat, post, meta] {
+                    if let Some(file_name) = file.file_name()
+                        && file.exists()
+                    {
+                        std::fs::rename(&file, quarantine_dir.join(file_name))?;
+                    }
+                }
 
-        self.segments.push(Arc::new(segment));
-        Ok(())
+                let quarantined = quarantine_dir.join(path.file_name().unwrap_or_default());
+                Arc::make_mut(&mut self.quarantined).push(quarantined);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Segments found corrupt when opening the index, moved to the
+    /// `quarantine` directory instead of being served (e.g.
+    /// `quarantine/1234` for the files `quarantine/1234.seg`...)
+    pub fn quarantined(&self) -> &[PathBuf] {
+        &self.quarantined
     }
 
     /// Take a snapshop of all currently living segments
@@ -430,8 +943,9 @@ impl SegmentedIndex {
         self.segments.push(segment);
     }
 
-    /// Atomically swaps out old segments for a newly compacted segment,
-    /// and cleans up the old files from disk.
+    /// Atomically swaps out old segments for a newly compacted segment.
+    /// Their files stay on disk until the caller marks them deleted, once
+    /// the manifest no longer lists them.
     /// Returns `true` if the compaction covered every segment (i.e. the
     /// resulting index contains only the new segment).
     pub(crate) fn apply_compaction(
@@ -446,10 +960,6 @@ impl SegmentedIndex {
 
         self.segments.push(new_segment);
 
-        for old_seg in old_segments {
-            old_seg.mark_deleted();
-        }
-
         was_full
     }
 
@@ -467,10 +977,15 @@ impl SegmentedIndex {
             Segment::paths_with_additional_extension(out_path);
 
         let capacity = 8 * 1024 * 1024;
-        let mut dat_writer = BufWriter::with_capacity(capacity, File::create(&dat_path)?);
-        let mut post_writer = BufWriter::with_capacity(capacity, File::create(&post_path)?);
-        let mut seg_writer = BufWriter::with_capacity(capacity, File::create(&seg_path)?);
-        let mut meta_writer = BufWriter::new(File::create(&meta_path)?);
+        let mut dat_writer =
+            ChecksumWriter::new(BufWriter::with_capacity(capacity, File::create(&dat_path)?));
+        let mut post_writer = ChecksumWriter::new(BufWriter::with_capacity(
+            capacity,
+            File::create(&post_path)?,
+        ));
+        let mut seg_writer =
+            ChecksumWriter::new(BufWriter::with_capacity(capacity, File::create(&seg_path)?));
+        let mut meta_writer = ChecksumWriter::new(BufWriter::new(File::create(&meta_path)?));
 
         let mut inverted_index: BTreeMap<String, Vec<DocumentId>> = BTreeMap::new();
 
@@ -500,12 +1015,14 @@ impl SegmentedIndex {
                     + path_bytes.len()
                     + size_of::<u32>()
                     + volume_bytes.len()
+                    + size_of::<u16>()
                     + entry_bytes.len(),
             );
             serialized.extend_from_slice(&(path_bytes.len() as u32).to_le_bytes());
             serialized.extend_from_slice(path_bytes);
             serialized.extend_from_slice(&(volume_bytes.len() as u32).to_le_bytes());
             serialized.extend_from_slice(volume_bytes);
+            serialized.extend_from_slice(&(entry_bytes.len() as u16).to_le_bytes());
             serialized.extend_from_slice(&entry_bytes);
 
             // Sample records only if we need to build a new dictionary
@@ -531,11 +1048,12 @@ impl SegmentedIndex {
             Vec::new()
         };
 
-        dat_writer.write_all(DATA_MAGIC)?;
+        dat_writer.write_all(&header(DATA_MAGIC))?;
         dat_writer.write_all(&(dict.len() as u32).to_le_bytes())?;
         dat_writer.write_all(&dict)?;
+        meta_writer.write_all(&header(META_MAGIC))?;
 
-        let mut current_dat_offset = (DATA_MAGIC.len() + size_of::<u32>() + dict.len()) as u64;
+        let mut current_dat_offset = (HEADER_SIZE + size_of::<u32>() + dict.len()) as u64;
         let mut doc_id_counter: u32 = 0;
 
         let mut compressor = if !dict.is_empty() {
@@ -564,28 +1082,12 @@ impl SegmentedIndex {
 
             dat_writer.write_all(&(compressed.len() as u32).to_le_bytes())?;
             dat_writer.write_all(&compressed)?;
+            dat_writer.write_all(&crc32c(&compressed).to_le_bytes())?;
 
-            // Pack u128 metadata
-            let depth = path_ref
-                .as_bytes()
-                .iter()
-                .filter(|&&b| b == std::path::MAIN_SEPARATOR as u8)
-                .count() as u16;
-            let is_dir = entry.kind == Kind::Directory;
-
-            let packed_meta = Self::pack_u128(
-                current_dat_offset,
-                entry.last_modified / 1_000_000,
-                entry.last_accessed / 1_000_000,
-                depth,
-                is_dir,
-                entry.category,
-                entry.volume_type as u8,
-            );
+            let doc_meta = DocMeta::new(current_dat_offset, &path_ref, &entry);
+            meta_writer.write_all(&doc_meta.as_bytes())?;
 
-            meta_writer.write_all(&packed_meta.to_le_bytes())?;
-
-            let tokens = crate::tokenizer::extract_all_tokens(&path_ref, &volume_ref);
+            let tokens = crate::tokenizer::extract_all_tokens(&path_ref, &volume_ref, &entry);
             for token in tokens {
                 inverted_index
                     .entry(token)
@@ -593,19 +1095,23 @@ impl SegmentedIndex {
                     .push(doc_id_counter);
             }
 
-            current_dat_offset += (size_of::<u32>() + compressed.len()) as u64;
+            current_dat_offset += (size_of::<u32>() + compressed.len() + CHECKSUM_SIZE) as u64;
             doc_id_counter += 1
         }
 
         dat_writer
+            .finish()?
             .into_inner()
             .map_err(|e| SegmentedIndexError::Io(e.into_error()))?
             .sync_all()?;
 
+        seg_writer.write_all(&header(SEGMENT_MAGIC))?;
+        post_writer.write_all(&header(POST_MAGIC))?;
+
         let mut seg_builder =
             fst::MapBuilder::new(&mut seg_writer).map_err(SegmentedIndexError::Fst)?;
 
-        let mut current_post_offset = 0u64;
+        let mut current_post_offset = HEADER_SIZE as u64;
         let mut compressed_buffer = Vec::new();
 
         for (fst_loop_counter, (token, doc_offsets)) in (0_usize..).zip(inverted_index) {
@@ -646,15 +1152,18 @@ impl SegmentedIndex {
         }
 
         meta_writer
+            .finish()?
             .into_inner()
             .map_err(|e| SegmentedIndexError::Io(e.into_error()))?
             .sync_all()?;
         post_writer
+            .finish()?
             .into_inner()
             .map_err(|e| SegmentedIndexError::Io(e.into_error()))?
             .sync_all()?;
         seg_builder.finish().map_err(SegmentedIndexError::Fst)?;
         seg_writer
+            .finish()?
             .into_inner()
             .map_err(|e| SegmentedIndexError::Io(e.into_error()))?
             .sync_all()?;
@@ -662,7 +1171,8 @@ impl SegmentedIndex {
         Ok(doc_id_counter as u64)
     }
 
-    // Bits 127-128: Reserved
+    // Packed metadata of the first format version:
+    // Bit 127: Deletion (1 bit)
     // Bits 125-126: Volume Type (2 bits)
     // Bits 117-124: File category (8 bits)
     // Bit 116: is_dir (1 bit)
@@ -671,6 +1181,7 @@ impl SegmentedIndex {
     // Bits 40-73: Last Modified Timestamp (Seconds) (34 bits)
     // Bits 0-39: dat_offset
 
+    #[allow(clippy::too_many_arguments)]
     pub fn pack_u128(
         dat_offset: u64,
         last_modified: u64,
@@ -679,6 +1190,7 @@ impl SegmentedIndex {
         is_dir: bool,
         category: u8,
         volume_type: u8,
+        deleted: bool,
     ) -> u128 {
         let mut packed = (dat_offset as u128) & 0x0000_00FF_FFFF_FFFF;
         packed |= ((last_modified as u128) & 0x3_FFFF_FFFF) << 40;
@@ -689,10 +1201,14 @@ impl SegmentedIndex {
         }
         packed |= ((category as u128) & 0xFF) << 117;
         packed |= ((volume_type as u128) & 0b11) << 125;
+        if deleted {
+            packed |= 1 << 127;
+        }
         packed
     }
 
-    pub fn unpack_u128(packed: u128) -> (u64, u64, u64, u16, bool, u8, u8) {
+    #[allow(clippy::type_complexity)]
+    pub fn unpack_u128(packed: u128) -> (u64, u64, u64, u16, bool, u8, u8, bool) {
         let offset = (packed & 0x0000_00FF_FFFF_FFFF) as u64;
         let last_modified = ((packed >> 40) & 0x3_FFFF_FFFF) as u64; // In seconds
         let last_accessed = ((packed >> 74) & 0x3_FFFF_FFFF) as u64; // In seconds
@@ -700,6 +1216,7 @@ impl SegmentedIndex {
         let is_dir = ((packed >> 116) & 1) == 1;
         let category = ((packed >> 117) & 0xFF) as u8;
         let volume_type = ((packed >> 125) & 0b11) as u8;
+        let deleted = (packed >> 127) == 1;
         (
             offset,
             last_modified,
@@ -708,6 +1225,7 @@ impl SegmentedIndex {
             is_dir,
             category,
             volume_type,
+            deleted,
         )
     }
 }
@@ -722,6 +1240,25 @@ pub enum SegmentedIndexError {
     Io(std::io::Error),
     #[error(transparent)]
     Fst(fst::Error),
+    #[error("missing or invalid file header in {}", .0.display())]
+    InvalidHeader(PathBuf),
+    #[error("unsupported format version {version} in {}", path.display())]
+    UnsupportedVersion { path: PathBuf, version: u32 },
+    #[error("corrupt manifest {}", .0.display())]
+    CorruptManifest(PathBuf),
+    #[error("checksum mismatch in {}", .0.display())]
+    ChecksumMismatch(PathBuf),
+}
+
+impl SegmentedIndexError {
+    /// Whether the error comes from damaged segment files, rather than
+    /// from failing to read them or from a newer format
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            Self::ChecksumMismatch(_) | Self::InvalidHeader(_) | Self::Fst(_)
+        )
+    }
 }
 
 impl From<std::io::Error> for SegmentedIndexError {
@@ -745,38 +1282,31 @@ impl Iterator for DocumentIterator<'_> {
     type Item = (String, String, IndexEntry);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let data = self.segment.data.as_ref().expect("expected data");
-        if let Some(dict) = &self.segment.dict {
-            if self.cursor + size_of::<u32>() > data.len() {
-                return None;
-            }
-            let compressed_len = u32::from_le_bytes(
-                data[self.cursor..self.cursor + size_of::<u32>()]
-                    .try_into()
-                    .unwrap(),
-            ) as usize;
-            self.cursor += size_of::<u32>();
-            if self.cursor + compressed_len > data.len() {
-                return None;
-            }
-            let compressed_data = &data[self.cursor..self.cursor + compressed_len];
-            self.cursor += compressed_len;
+        let Some(dict) = &self.segment.dict else {
+            let data = self.segment.data.as_ref().expect("expected data");
+            let (path, volume, entry, new_cursor) = self
+                .segment
+                .parse_document_owned(&data[..self.segment.docs_end], self.cursor)?;
+            self.cursor = new_cursor;
 
-            // Fast block decompression
-            let mut decompressed = vec![0u8; 8 * 1024];
-            let size = zstd::bulk::Decompressor::with_dictionary(dict)
-                .ok()?
-                .decompress_to_buffer(compressed_data, &mut decompressed)
-                .ok()?;
-            decompressed.truncate(size);
+            return Some((path, volume, entry));
+        };
 
-            Segment::parse_document_owned(&decompressed, 0).map(|(p, v, e, _)| (p, v, e))
-        } else {
-            let (path, volume, entry, new_cursor) =
-                Segment::parse_document_owned(data, self.cursor)?;
-            self.cursor = new_cursor;
+        loop {
+            let (compressed, next) = self.segment.compressed_record(self.cursor)?;
+            self.cursor = next;
 
-            Some((path, volume, entry))
+            // Skip corrupt records, keeping the rest of the segment readable
+            if let Some(compressed) = compressed {
+                let document = self.segment.decompress_document(dict, compressed);
+                if document.is_some() {
+                    return document;
+                }
+                log::error!(
+                    "Skipping unreadable data record in segment {:?}",
+                    self.segment.path
+                );
+            }
         }
     }
 }
@@ -784,14 +1314,14 @@ impl Iterator for DocumentIterator<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::VolumeType;
     use crate::opstamp::Opstamp;
 
     #[test]
     fn test_pack_unpack_u128() {
-        let original = (123456789, 456789, 789012, 10, true, 0xAB, 1);
+        let original = (123456789, 456789, 789012, 10, true, 0xAB, 1, true);
         let packed = SegmentedIndex::pack_u128(
             original.0, original.1, original.2, original.3, original.4, original.5, original.6,
+            original.7,
         );
         let unpacked = SegmentedIndex::unpack_u128(packed);
         assert_eq!(original, unpacked);
@@ -808,24 +1338,23 @@ mod tests {
                 "/foo/bar.txt".to_string(),
                 "vol1".to_string(),
                 IndexEntry {
-                    opstamp: Opstamp::insertion(1),
-                    kind: Kind::File,
-                    last_modified: 100,
-                    last_accessed: 100,
                     category: 1,
-                    volume_type: VolumeType::Local,
+                    size: 1234,
+                    created: 50_000_000,
+                    uid: Some(1000),
+                    gid: Some(100),
+                    mode: Some(0o100644),
+                    hidden: false,
+                    ..IndexEntry::file(Opstamp::insertion(1), 100)
                 },
             ),
             (
                 "/foo/baz".to_string(),
                 "vol1".to_string(),
                 IndexEntry {
-                    opstamp: Opstamp::insertion(2),
                     kind: Kind::Directory,
-                    last_modified: 200,
-                    last_accessed: 200,
                     category: 2,
-                    volume_type: VolumeType::Local,
+                    ..IndexEntry::file(Opstamp::insertion(2), 200)
                 },
             ),
         ];
@@ -841,6 +1370,7 @@ mod tests {
         assert_eq!(docs[1].0, "/foo/baz");
         assert_eq!(docs[0].2.opstamp.sequence(), 1);
         assert_eq!(docs[1].2.opstamp.sequence(), 2);
+        assert_eq!(docs[0].2.size, 1234);
 
         // Check FST searches
         let map = segment.as_ref();
@@ -853,15 +1383,239 @@ mod tests {
         }
 
         // Check meta
-        let meta_map = segment.meta_map();
-        assert_eq!(meta_map.len(), 2 * 16);
-        let packed0 = u128::from_le_bytes(meta_map[0..16].try_into()?);
-        let (_, _, _, _, is_dir, _, _) = SegmentedIndex::unpack_u128(packed0);
-        assert!(!is_dir);
-
-        let packed1 = u128::from_le_bytes(meta_map[16..32].try_into()?);
-        let (_, _, _, _, is_dir, _, _) = SegmentedIndex::unpack_u128(packed1);
-        assert!(is_dir);
+        assert_eq!(segment.doc_count(), 2);
+        let meta0 = segment.doc_meta(0).expect("doc 0 should have metadata");
+        assert!(!meta0.is_dir);
+        assert_eq!(meta0.size, 1234);
+        assert_eq!(meta0.created, 50);
+        assert_eq!((meta0.uid, meta0.gid, meta0.mode), (1000, 100, 0o100644));
+        assert_eq!(
+            segment
+                .read_document(meta0.dat_offset)
+                .map(|(path, _, _)| path),
+            Some("/foo/bar.txt".to_string())
+        );
+
+        let meta1 = segment.doc_meta(1).expect("doc 1 should have metadata");
+        assert!(meta1.is_dir);
+        assert_eq!(meta1.uid, DocMeta::UNKNOWN_ID);
+        assert!(segment.doc_meta(2).is_none());
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_segment_load() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_seg_v1_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+        let seg_path = temp_dir.join("0");
+        let (seg, dat, post, meta) = Segment::to_paths(&seg_path);
+
+        let entry = IndexEntry {
+            last_accessed: 200,
+            category: 1,
+            ..IndexEntry::file(Opstamp::insertion(1), 100)
+        };
+
+        // Records of the first format version end with a zero padded entry
+        let mut record = Vec::new();
+        record.extend_from_slice(&4u32.to_le_bytes());
+        record.extend_from_slice(b"/foo");
+        record.extend_from_slice(&4u32.to_le_bytes());
+        record.extend_from_slice(b"vol1");
+        record.extend_from_slice(&entry.as_bytes()[..27]);
+        record.resize(record.len() + IndexEntry::LEGACY_SIZE - 27, 0);
+        let compressed = zstd::encode_all(&record[..], 0)?;
+
+        let mut data = DATA_MAGIC_V1.to_vec();
+        data.extend_from_slice(&0u32.to_le_bytes());
+        let dat_offset = data.len() as u64;
+        data.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        data.extend_from_slice(&compressed);
+        std::fs::write(dat, data)?;
+
+        let packed = SegmentedIndex::pack_u128(dat_offset, 100, 200, 1, false, 1, 0, false);
+        std::fs::write(meta, packed.to_le_bytes())?;
+        std::fs::write(seg, fst::MapBuilder::memory().into_inner()?)?;
+        std::fs::write(post, [])?;
+
+        let segment = Segment::load(seg_path)?;
+        assert_eq!(segment.doc_count(), 1);
+        assert_eq!(segment.doc_meta(0), Some(DocMeta::legacy(packed)));
+
+        let (path, volume, entry) = segment
+            .read_document(dat_offset)
+            .expect("legacy document should be readable");
+        assert_eq!((path.as_str(), volume.as_str()), ("/foo", "vol1"));
+        assert_eq!(entry.last_accessed, 200);
+        assert_eq!(entry.size, 0);
+        assert_eq!(segment.documents().count(), 1);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_segment_wide_metadata() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_seg_wide_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+        let seg_path = temp_dir.join("0");
+
+        // Past the 34 bits of seconds and 255 levels of the packed metadata
+        let far_future = (1u64 << 40) * 1_000_000;
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let path = format!("{sep}d").repeat(300);
+        let entry = IndexEntry {
+            kind: Kind::Directory,
+            last_accessed: far_future + 1_000_000,
+            volume_type: VolumeType::Removable,
+            created: far_future,
+            hidden: true,
+            ..IndexEntry::file(Opstamp::deletion(1), far_future)
+        };
+
+        let out_path = temp_dir.join("0.tmp");
+        SegmentedIndex::build_segment_files(
+            &out_path,
+            [(path.clone(), "vol1".to_string(), entry)],
+            false,
+            None,
+        )?;
+        Segment::rename_files(
+            &Segment::paths_with_additional_extension(&out_path),
+            &Segment::to_paths(&seg_path),
+        )?;
+
+        let segment = Segment::load(seg_path)?;
+        let meta = segment.doc_meta(0).expect("doc 0 should have metadata");
+        assert_eq!(meta.last_modified, 1 << 40);
+        assert_eq!(meta.last_accessed, (1 << 40) + 1);
+        assert_eq!(meta.created, 1 << 40);
+        assert_eq!(meta.depth, 300);
+        assert!(meta.is_dir);
+        assert!(meta.hidden);
+        assert!(meta.deleted);
+        assert_eq!(meta.volume_type, VolumeType::Removable as u8);
+        assert_eq!(
+            segment
+                .read_document(meta.dat_offset)
+                .map(|(path, _, _)| path),
+            Some(path)
+        );
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_segment_version_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_seg_ver_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+        let seg_path = temp_dir.join("0");
+        let out_path = temp_dir.join("0.tmp");
+        SegmentedIndex::build_segment_files(
+            &out_path,
+            Vec::<(String, String, _)>::new(),
+            false,
+            None,
+        )?;
+        let paths = Segment::to_paths(&seg_path);
+        Segment::rename_files(&Segment::paths_with_additional_extension(&out_path), &paths)?;
+        assert!(Segment::load(seg_path.clone()).is_ok());
+
+        // Segments of newer versions are rejected
+        let mut data = std::fs::read(&paths.1)?;
+        data[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&paths.1, &data)?;
+        assert!(matches!(
+            Segment::load(seg_path.clone()),
+            Err(SegmentedIndexError::UnsupportedVersion { version, .. }) if version == FORMAT_VERSION + 1
+        ));
+
+        // And so are files of a segment missing their header
+        data[4..8].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        std::fs::write(&paths.1, &data)?;
+        std::fs::write(&paths.3, [])?;
+        assert!(matches!(
+            Segment::load(seg_path.clone()),
+            Err(SegmentedIndexError::InvalidHeader(path)) if path == paths.3
+        ));
+
+        // Truncated data files fail instead of panicking
+        std::fs::write(&paths.1, DATA_MAGIC_V1)?;
+        assert!(matches!(
+            Segment::load(seg_path),
+            Err(SegmentedIndexError::InvalidHeader(_))
+        ));
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    fn file_entry(seq: u64) -> IndexEntry {
+        IndexEntry {
+            size: seq,
+            ..IndexEntry::file(Opstamp::insertion(seq), seq * 1_000_000)
+        }
+    }
+
+    #[test]
+    fn test_doc_meta_depth() {
+        // Depths of entries and of `depth_root` count the same separators,
+        // backslashes only being one on Windows
+        let sep = std::path::MAIN_SEPARATOR_STR;
+        let root = format!("{sep}a\\b{sep}");
+        let meta = DocMeta::new(0, &format!("{root}c"), &file_entry(1));
+        let filter = crate::search::CandidateFilter::new(&crate::SearchOptions {
+            min_depth: Some(1),
+            max_depth: Some(1),
+            depth_root: Some(&root),
+            ..Default::default()
+        });
+        assert_eq!(filter.depth, (meta.depth as u64, meta.depth as u64));
+    }
+
+    #[test]
+    fn test_segment_checksums() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_seg_crc_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+        let seg_path = temp_dir.join("0");
+        let entries =
+            (1..=3).map(|seq| (format!("/foo/{seq}"), "vol1".to_string(), file_entry(seq)));
+        SegmentedIndex::build_segment_files(&seg_path, entries, false, None)?;
+        let (seg, dat, post, meta) = Segment::to_paths(&seg_path);
+        assert!(Segment::load(seg_path.clone()).is_ok());
+
+        // A flipped bit anywhere in a file fails its footer
+        for path in [&seg, &dat, &post, &meta] {
+            let original = std::fs::read(path)?;
+            let mut corrupt = original.clone();
+            corrupt[HEADER_SIZE] ^= 1;
+            std::fs::write(path, &corrupt)?;
+            assert!(matches!(
+                Segment::load(seg_path.clone()),
+                Err(SegmentedIndexError::ChecksumMismatch(failed)) if failed == *path
+            ));
+            std::fs::write(path, &original)?;
+        }
+
+        // A corrupt record behind a valid footer is skipped, not served
+        let offset = Segment::load(seg_path.clone())?
+            .doc_meta(1)
+            .expect("doc 1 should have metadata")
+            .dat_offset as usize;
+        let mut data = std::fs::read(&dat)?;
+        data[offset + size_of::<u32>()] ^= 1;
+        let content_len = data.len() - CHECKSUM_SIZE;
+        let footer = crc32c(&data[..content_len]).to_le_bytes();
+        data[content_len..].copy_from_slice(&footer);
+        std::fs::write(&dat, &data)?;
+
+        let segment = Segment::load(seg_path)?;
+        assert!(segment.read_document(offset as u64).is_none());
+        let paths: Vec<_> = segment.documents().map(|(path, _, _)| path).collect();
+        assert_eq!(paths, vec!["/foo/1".to_string(), "/foo/3".to_string()]);
 
         std::fs::remove_dir_all(temp_dir)?;
         Ok(())
@@ -1,7 +1,15 @@
 use crate::sync::Arc;
-use std::path::PathBuf;
-
-use crate::{entry::IndexEntry, is_tombstoned, segmented_index::SegmentedIndexError};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, btree_map::Entry},
+    path::PathBuf,
+};
+
+use crate::{
+    common::{PrefixRename, resolve_prefix_ops},
+    entry::IndexEntry,
+    segmented_index::SegmentedIndexError,
+};
 
 use super::{Segment, SegmentedIndex};
 
@@ -12,7 +20,7 @@ pub struct CompactorConfig {
     pub min_merge_count: usize,
     /// Minimum amount of data in memory required to flush
     pub flush_threshold: usize,
-    /// Minimum amount of tombstones written to trigger compaction
+    /// Minimum amount of prefix tombstones and renames written to trigger compaction
     pub tombstone_threshold: usize,
 }
 
@@ -62,7 +70,8 @@ impl CompactorConfigBuilder {
         }
     }
 
-    /// Set the minimum number of prefix tombstones required to trigger compaction
+    /// Set the minimum number of prefix tombstones and renames required to
+    /// trigger compaction
     pub fn tombstone_threshold(self, tombstone_threshold: usize) -> Self {
         Self {
             tombstone_threshold,
@@ -82,12 +91,59 @@ impl CompactorConfigBuilder {
 /// Merge live segments into smaller ones.
 /// Drops data that is outdated - only the latest opstamp wins.
 /// Implemented via a K-Way Merge with zero allocations
+/// Prefix renames are materialized, moving entries to their new path.
 /// Note: atomic replacement of old segment files is done by the caller
 pub(crate) fn merge_segments(
     segments: &[Arc<Segment>],
     prefix_tombstones: Arc<Vec<(Option<String>, String, u64)>>,
+    prefix_renames: Arc<Vec<PrefixRename>>,
     out: PathBuf,
 ) -> Result<u64, SegmentedIndexError> {
+    // Moved entries no longer sort along with their segment, so they are
+    // gathered upfront and merged back in at their new path
+    let mut moved: BTreeMap<String, (String, IndexEntry)> = BTreeMap::new();
+    if !prefix_renames.is_empty() {
+        for segment in segments {
+            for (path, volume, mut entry) in segment.documents() {
+                let Some((Cow::Owned(new_path), sequence)) = resolve_prefix_ops(
+                    &volume,
+                    &path,
+                    entry.opstamp.sequence(),
+                    &prefix_tombstones,
+                    &prefix_renames,
+                ) else {
+                    continue;
+                };
+                entry.opstamp = entry.opstamp.with_sequence(sequence);
+
+                match moved.entry(new_path) {
+                    Entry::Occupied(mut occupied) => {
+                        if sequence > occupied.get().1.opstamp.sequence() {
+                            occupied.insert((volume, entry));
+                        }
+                    }
+                    Entry::Vacant(vacant) => {
+                        vacant.insert((volume, entry));
+                    }
+                }
+            }
+        }
+    }
+
+    // Whether an entry is neither deleted by a tombstone nor moved
+    let stays = move |(path, volume, entry): &(String, String, IndexEntry)| {
+        matches!(
+            resolve_prefix_ops(
+                volume,
+                path,
+                entry.opstamp.sequence(),
+                &prefix_tombstones,
+                &prefix_renames,
+            ),
+            Some((Cow::Borrowed(_), _))
+        )
+    };
+
     let mut iterators: Vec<_> = segments.iter().map(|seg| seg.documents()).collect();
 
     let mut currents: Vec<Option<(String, String, IndexEntry)>> =
@@ -129,16 +185,10 @@ pub(crate) fn merge_segments(
                     if *path == best_item.0 {
                         let item = currents[i].take().unwrap();
 
-                        // Check for tombstones
-                        let path_bytes = item.0.as_bytes();
-                        let is_dead = is_tombstoned(
-                            &item.1,
-                            path_bytes,
-                            item.2.opstamp.sequence(),
-                            &prefix_tombstones,
-                        );
-
-                        if !is_dead && item.2.opstamp.sequence() > best_item.2.opstamp.sequence() {
+                        // Check for tombstones and renames
+                        if stays(&item)
+                            && item.2.opstamp.sequence() > best_item.2.opstamp.sequence()
+                        {
                             best_item = item;
                         }
 
@@ -150,15 +200,7 @@ pub(crate) fn merge_segments(
                 }
             }
 
-            let best_bytes = best_item.0.as_bytes();
-            let best_is_dead = is_tombstoned(
-                &best_item.1,
-                best_bytes,
-                best_item.2.opstamp.sequence(),
-                &prefix_tombstones,
-            );
-
-            if best_is_dead {
+            if !stays(&best_item) {
                 continue;
             }
 
@@ -166,6 +208,30 @@ pub(crate) fn merge_segments(
         }
     });
 
+    let mut merged_iterator = merged_iterator.peekable();
+    let mut moved = moved.into_iter().peekable();
+    let merged_iterator = std::iter::from_fn(move || {
+        let moved_item = |(path, (volume, entry))| (path, volume, entry);
+
+        match (merged_iterator.peek(), moved.peek()) {
+            (Some((path, _, _)), Some((moved_path, _))) => match path.cmp(moved_path) {
+                std::cmp::Ordering::Less => merged_iterator.next(),
+                std::cmp::Ordering::Greater => moved.next().map(moved_item),
+                std::cmp::Ordering::Equal => {
+                    let item = merged_iterator.next()?;
+                    let moved_item = moved.next().map(moved_item)?;
+                    if moved_item.2.opstamp.sequence() > item.2.opstamp.sequence() {
+                        Some(moved_item)
+                    } else {
+                        Some(item)
+                    }
+                }
+            },
+            (Some(_), None) => merged_iterator.next(),
+            (None, _) => moved.next().map(moved_item),
+        }
+    });
+
     // Strategy: reuse the dictionary from the first segment being merged.
     // In tiered compaction, segments being merged are usually from the same tier
     // or include a newly flushed segment. Reusing an existing dictionary
@@ -178,8 +244,6 @@ pub(crate) fn merge_segments(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Kind;
-    use crate::VolumeType;
     use crate::opstamp::Opstamp;
 
     #[test]
@@ -192,26 +256,12 @@ mod tests {
             (
                 "/foo/a".to_string(),
                 "vol1".to_string(),
-                IndexEntry {
-                    opstamp: Opstamp::insertion(1),
-                    kind: Kind::File,
-                    last_modified: 100,
-                    last_accessed: 100,
-                    category: 0,
-                    volume_type: VolumeType::Local,
-                },
+                IndexEntry::file(Opstamp::insertion(1), 100),
             ),
             (
                 "/foo/b".to_string(),
                 "vol1".to_string(),
-                IndexEntry {
-                    opstamp: Opstamp::insertion(1),
-                    kind: Kind::File,
-                    last_modified: 100,
-                    last_accessed: 100,
-                    category: 0,
-                    volume_type: VolumeType::Local,
-                },
+                IndexEntry::file(Opstamp::insertion(1), 100),
             ),
         ];
         SegmentedIndex::build_segment_files(&seg1_path, entries1, false, None)?;
@@ -221,26 +271,13 @@ mod tests {
             (
                 "/foo/a".to_string(),
                 "vol1".to_string(),
-                IndexEntry {
-                    opstamp: Opstamp::insertion(2), // Newer version
-                    kind: Kind::File,
-                    last_modified: 200,
-                    last_accessed: 200,
-                    category: 0,
-                    volume_type: VolumeType::Local,
-                },
+                // Newer version
+                IndexEntry::file(Opstamp::insertion(2), 200),
             ),
             (
                 "/foo/c".to_string(),
                 "vol1".to_string(),
-                IndexEntry {
-                    opstamp: Opstamp::insertion(1),
-                    kind: Kind::File,
-                    last_modified: 100,
-                    last_accessed: 100,
-                    category: 0,
-                    volume_type: VolumeType::Local,
-                },
+                IndexEntry::file(Opstamp::insertion(1), 100),
             ),
         ];
         SegmentedIndex::build_segment_files(&seg2_path, entries2, false, None)?;
@@ -249,7 +286,12 @@ mod tests {
         let s2 = Arc::new(Segment::load(seg2_path)?);
 
         let out_path = temp_dir.join("merged");
-        merge_segments(&[s1, s2], Arc::new(vec![]), out_path.clone())?;
+        merge_segments(
+            &[s1, s2],
+            Arc::new(vec![]),
+            Arc::new(vec![]),
+            out_path.clone(),
+        )?;
 
         let merged_seg = Segment::load(out_path)?;
         let docs: Vec<_> = merged_seg.documents().collect();
@@ -281,26 +323,12 @@ mod tests {
             (
                 format!("/foo{}a", sep),
                 "vol1".to_string(),
-                IndexEntry {
-                    opstamp: Opstamp::insertion(10),
-                    kind: Kind::File,
-                    last_modified: 100,
-                    last_accessed: 100,
-                    category: 0,
-                    volume_type: VolumeType::Local,
-                },
+                IndexEntry::file(Opstamp::insertion(10), 100),
             ),
             (
                 format!("/bar{}b", sep),
                 "vol1".to_string(),
-                IndexEntry {
-                    opstamp: Opstamp::insertion(10),
-                    kind: Kind::File,
-                    last_modified: 100,
-                    last_accessed: 100,
-                    category: 0,
-                    volume_type: VolumeType::Local,
-                },
+                IndexEntry::file(Opstamp::insertion(10), 100),
             ),
         ];
         SegmentedIndex::build_segment_files(&seg_path, entries, false, None)?;
@@ -310,7 +338,12 @@ mod tests {
         let out_path = temp_dir.join("merged");
         // Tombstone for /foo on vol1
         let tombstones = vec![(Some("vol1".to_string()), "/foo".to_string(), 50)];
-        merge_segments(&[s1], Arc::new(tombstones), out_path.clone())?;
+        merge_segments(
+            &[s1],
+            Arc::new(tombstones),
+            Arc::new(vec![]),
+            out_path.clone(),
+        )?;
 
         let merged_seg = Segment::load(out_path)?;
         let docs: Vec<_> = merged_seg.documents().collect();
@@ -323,6 +356,77 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_merge_segments_with_renames() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_comp_ren_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let p = |path: &str| path.replace('/', std::path::MAIN_SEPARATOR_STR);
+        let doc = |path: &str, seq| {
+            (
+                p(path),
+                "vol1".to_string(),
+                IndexEntry {
+                    size: seq,
+                    ..IndexEntry::file(Opstamp::insertion(seq), 100)
+                },
+            )
+        };
+
+        let seg1_path = temp_dir.join("1");
+        SegmentedIndex::build_segment_files(
+            &seg1_path,
+            vec![
+                doc("/a/x", 10),
+                doc("/a/y", 10),
+                doc("/b/x", 15),
+                doc("/c", 10),
+            ],
+            false,
+            None,
+        )?;
+        let seg2_path = temp_dir.join("2");
+        SegmentedIndex::build_segment_files(&seg2_path, vec![doc("/a/y", 60)], false, None)?;
+
+        let s1 = Arc::new(Segment::load(seg1_path)?);
+        let s2 = Arc::new(Segment::load(seg2_path)?);
+
+        let out_path = temp_dir.join("merged");
+        let renames = vec![PrefixRename {
+            volume: None,
+            old_prefix: p("/a"),
+            new_prefix: p("/b"),
+            seq: 50,
+        }];
+        merge_segments(
+            &[s1, s2],
+            Arc::new(vec![]),
+            Arc::new(renames),
+            out_path.clone(),
+        )?;
+
+        let merged_seg = Segment::load(out_path)?;
+        let docs: Vec<_> = merged_seg
+            .documents()
+            .map(|(path, _, entry)| (path, entry.opstamp.sequence(), entry.size))
+            .collect();
+
+        // Moved entries replace what was at their new path, and take the
+        // sequence of the rename. /a/y was written again after the move.
+        assert_eq!(
+            docs,
+            vec![
+                (p("/a/y"), 60, 60),
+                (p("/b/x"), 50, 10),
+                (p("/b/y"), 50, 10),
+                (p("/c"), 10, 10),
+            ]
+        );
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
     fn rand_id() -> u64 {
         crate::sync::time::SystemTime::now()
             .duration_since(crate::sync::time::UNIX_EPOCH)
@@ -0,0 +1,244 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{Tombstone, common::PrefixRename};
+
+use super::SegmentedIndexError;
+
+pub(crate) const MANIFEST_FILE: &str = "MANIFEST";
+const MANIFEST_TMP_FILE: &str = "MANIFEST.tmp";
+
+/// Magic of the header starting the manifest, followed by its format
+/// version (u32)
+const MANIFEST_MAGIC: &[u8; 4] = b"zMDF";
+const MANIFEST_VERSION: u32 = 1;
+
+/// The durable state of an index that isn't held by its segments or WAL:
+/// which segments are live, the prefix tombstones and renames not yet
+/// applied to them, and the opstamp high-water mark.
+///
+/// The manifest is replaced atomically (written to a temporary file, synced,
+/// then renamed over the previous one) after every change to the segment
+/// set, so a crash at any point leaves either the old or the new set.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Manifest {
+    /// Names of the live segments, e.g. `1234` for `1234.seg`
+    pub segments: Vec<String>,
+    pub tombstones: Vec<Tombstone>,
+    pub renames: Vec<PrefixRename>,
+    /// Opstamp sequences handed out before the manifest was written are
+    /// all lower than this
+    pub next_op_seq: u64,
+}
+
+impl Manifest {
+    /// Reads the manifest of the index in `dir`, `None` if it has none yet
+    pub fn load(dir: &Path) -> Result<Option<Self>, SegmentedIndexError> {
+        let path = dir.join(MANIFEST_FILE);
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(SegmentedIndexError::Io(e)),
+        };
+
+        if bytes.len() < 8 || !bytes.starts_with(MANIFEST_MAGIC) {
+            return Err(SegmentedIndexError::InvalidHeader(path));
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != MANIFEST_VERSION {
+            return Err(SegmentedIndexError::UnsupportedVersion { path, version });
+        }
+
+        Self::from_bytes(&bytes[8..])
+            .map(Some)
+            .ok_or(SegmentedIndexError::CorruptManifest(path))
+    }
+
+    /// Atomically replaces the manifest of the index in `dir`
+    pub fn store(&self, dir: &Path) -> std::io::Result<()> {
+        let tmp_path = dir.join(MANIFEST_TMP_FILE);
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(MANIFEST_MAGIC)?;
+        file.write_all(&MANIFEST_VERSION.to_le_bytes())?;
+        file.write_all(&self.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+
+        std::fs::rename(&tmp_path, dir.join(MANIFEST_FILE))?;
+
+        // Persist the rename itself
+        #[cfg(unix)]
+        File::open(dir)?.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Path of the temporary file a crash may leave behind while storing
+    pub fn tmp_path(dir: &Path) -> PathBuf {
+        dir.join(MANIFEST_TMP_FILE)
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.next_op_seq.to_le_bytes());
+
+        buf.extend_from_slice(&(self.segments.len() as u32).to_le_bytes());
+        for segment in &self.segments {
+            put_str(&mut buf, segment);
+        }
+
+        buf.extend_from_slice(&(self.tombstones.len() as u32).to_le_bytes());
+        for (volume, prefix, seq) in &self.tombstones {
+            buf.extend_from_slice(&seq.to_le_bytes());
+            put_volume(&mut buf, volume.as_deref());
+            put_str(&mut buf, prefix);
+        }
+
+        buf.extend_from_slice(&(self.renames.len() as u32).to_le_bytes());
+        for rename in &self.renames {
+            buf.extend_from_slice(&rename.seq.to_le_bytes());
+            put_volume(&mut buf, rename.volume.as_deref());
+            put_str(&mut buf, &rename.old_prefix);
+            put_str(&mut buf, &rename.new_prefix);
+        }
+
+        buf
+    }
+
+    fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let rest = &mut bytes;
+        let next_op_seq = u64::from_le_bytes(take(rest)?);
+
+        let count = u32::from_le_bytes(take(rest)?);
+        let segments = (0..count)
+            .map(|_| take_str(rest))
+            .collect::<Option<Vec<_>>>()?;
+
+        let count = u32::from_le_bytes(take(rest)?);
+        let tombstones = (0..count)
+            .map(|_| {
+                let seq = u64::from_le_bytes(take(rest)?);
+                let volume = take_volume(rest)?;
+                Some((volume, take_str(rest)?, seq))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let count = u32::from_le_bytes(take(rest)?);
+        let renames = (0..count)
+            .map(|_| {
+                let seq = u64::from_le_bytes(take(rest)?);
+                let volume = take_volume(rest)?;
+                Some(PrefixRename {
+                    volume,
+                    old_prefix: take_str(rest)?,
+                    new_prefix: take_str(rest)?,
+                    seq,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        rest.is_empty().then_some(Self {
+            segments,
+            tombstones,
+            renames,
+            next_op_seq,
+        })
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn put_volume(buf: &mut Vec<u8>, volume: Option<&str>) {
+    match volume {
+        Some(volume) => {
+            buf.push(1);
+            put_str(buf, volume);
+        }
+        None => buf.push(0),
+    }
+}
+
+fn take<const N: usize>(rest: &mut &[u8]) -> Option<[u8; N]> {
+    let (head, tail) = rest.split_first_chunk::<N>()?;
+    *rest = tail;
+    Some(*head)
+}
+
+fn take_str(rest: &mut &[u8]) -> Option<String> {
+    let len = u32::from_le_bytes(take(rest)?) as usize;
+    let s = rest.get(..len)?;
+    *rest = &rest[len..];
+    String::from_utf8(s.to_vec()).ok()
+}
+
+fn take_volume(rest: &mut &[u8]) -> Option<Option<String>> {
+    match take::<1>(rest)? {
+        [0] => Some(None),
+        [1] => take_str(rest).map(Some),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_store_and_load() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!(
+            "minidex_test_manifest_{}",
+            crate::sync::time::SystemTime::now()
+                .duration_since(crate::sync::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        assert_eq!(Manifest::load(&dir)?, None);
+
+        let manifest = Manifest {
+            segments: vec!["100".to_string(), "200".to_string()],
+            tombstones: vec![
+                (None, "/foo".to_string(), 10),
+                (Some("vol1".to_string()), "/bar".to_string(), 20),
+            ],
+            renames: vec![PrefixRename {
+                volume: Some("vol1".to_string()),
+                old_prefix: "/old".to_string(),
+                new_prefix: "/new".to_string(),
+                seq: 30,
+            }],
+            next_op_seq: 40,
+        };
+        manifest.store(&dir)?;
+        assert_eq!(Manifest::load(&dir)?, Some(manifest.clone()));
+        assert!(!Manifest::tmp_path(&dir).exists());
+
+        // Stores replace the previous manifest
+        let empty = Manifest::default();
+        empty.store(&dir)?;
+        assert_eq!(Manifest::load(&dir)?, Some(empty));
+
+        // Truncated and unknown manifests fail to load
+        let mut bytes = std::fs::read(dir.join(MANIFEST_FILE))?;
+        std::fs::write(dir.join(MANIFEST_FILE), &bytes[..bytes.len() - 1])?;
+        assert!(matches!(
+            Manifest::load(&dir),
+            Err(SegmentedIndexError::CorruptManifest(_))
+        ));
+        bytes[4] = 9;
+        std::fs::write(dir.join(MANIFEST_FILE), &bytes)?;
+        assert!(matches!(
+            Manifest::load(&dir),
+            Err(SegmentedIndexError::UnsupportedVersion { version: 9, .. })
+        ));
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
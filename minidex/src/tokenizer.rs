@@ -1,5 +1,7 @@
 use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
 
+use crate::entry::{FileId, IndexEntry};
+
 /// A basic Unicode-aware tokenizer.
 pub fn tokenize(input: &str) -> Vec<String> {
     let mut tokens = Vec::new();
@@ -99,7 +101,16 @@ pub fn tokenize(input: &str) -> Vec<String> {
 }
 
 /// Generate all tokens, including synthetic tokens.
-pub(crate) fn extract_all_tokens(path: &str, volume: &str) -> Vec<String> {
+pub(crate) fn extract_all_tokens(path: &str, volume: &str, entry: &IndexEntry) -> Vec<String> {
+    let mut tokens = extract_path_tokens(path);
+    tokens.extend(extract_entry_tokens(path, volume, entry));
+    tokens
+}
+
+/// Tokens derived from the path alone, which every version of an entry
+/// carries. Paths without any other token are given their own synthetic
+/// path token, so that every document can be looked up by its path's tokens.
+pub(crate) fn extract_path_tokens(path: &str) -> Vec<String> {
     let mut tokens = tokenize(path); // Base tokens
 
     for (i, c) in path.char_indices() {
@@ -108,10 +119,6 @@ pub(crate) fn extract_all_tokens(path: &str, volume: &str) -> Vec<String> {
         }
     }
 
-    if !volume.is_empty() {
-        tokens.push(synthesize_token(SYNTH_VOLUME_TOKEN_TAG, volume));
-    }
-
     let path_obj = std::path::Path::new(path);
     let file_name_str = path_obj.file_name().and_then(|n| n.to_str()).unwrap_or("");
 
@@ -155,11 +162,65 @@ pub(crate) fn extract_all_tokens(path: &str, volume: &str) -> Vec<String> {
         }
     }
 
+    if tokens.is_empty() {
+        tokens.push(synthesize_token(SYNTH_PATH_TOKEN_TAG, path));
+    }
+
+    tokens
+}
+
+/// Synthetic tokens derived from the entry rather than its path (deletion,
+/// volume, tags, content hash, symlink targets and file identity), which can
+/// change between versions of an entry
+pub(crate) fn extract_entry_tokens(path: &str, volume: &str, entry: &IndexEntry) -> Vec<String> {
+    let mut tokens = Vec::new();
+
+    // Deletions carry nothing but their path, and are looked up by it to
+    // shadow older versions found through other tokens (e.g. tags)
+    if entry.opstamp.is_deletion() {
+        tokens.push(deletion_token(path));
+        return tokens;
+    }
+
+    if !volume.is_empty() {
+        tokens.push(synthesize_token(SYNTH_VOLUME_TOKEN_TAG, volume));
+    }
+
+    for tag in entry.tags.iter().filter(|tag| !tag.is_empty()) {
+        tokens.push(synthesize_token(SYNTH_TAG_TOKEN_TAG, tag));
+    }
+
+    if let Some(hash) = entry.content_hash {
+        tokens.push(content_hash_token(hash));
+    }
+
+    // Symlinks can be found by any directory their target lies under,
+    // and by their exact target
+    if let Some(target) = &entry.symlink_target {
+        let resolved = crate::entry::resolve_symlink_target(
+            std::path::Path::new(path),
+            std::path::Path::new(target),
+        );
+        let resolved = resolved.to_string_lossy();
+        for (i, c) in resolved.char_indices() {
+            if c == '/' || c == '\\' {
+                tokens.push(target_prefix_token(&resolved[..=i]));
+            }
+        }
+        if !resolved.ends_with(['/', '\\']) {
+            tokens.push(target_prefix_token(&resolved));
+        }
+    }
+
+    if let Some(file_id) = entry.file_id {
+        tokens.push(file_id_token(file_id));
+    }
+
     tokens
 }
 
 /// A fast, rough check for Chinese, Japanese, and Korean Unicode blocks.
-fn is_cjk(c: char) -> bool {
+pub(crate) fn is_cjk(c: char) -> bool {
     let u = c as u32;
     // Ranges cover Hiragana, Katakana, CJK Unified Ideographs, and Hangul
     (0x3040..=0x309F).contains(&u) || // Hiragana
@@ -179,9 +240,77 @@ pub(crate) fn fold_path(input: &str) -> String {
         .to_lowercase()
 }
 
-const SYNTH_PATH_TOKEN_TAG: char = '\x00';
+/// Normalizes text for phrase matching: folds it like `fold_path`, and turns
+/// every word boundary the tokenizer knows about (camelCase, letter/digit and
+/// script transitions, punctuation) into a single space. Path separators are
+/// kept as `/`, so phrases don't span directories.
+pub(crate) fn normalize_phrase(input: &str) -> String {
+    let mut normalized = String::with_capacity(input.len());
+    let mut prev_char: Option<char> = None;
+    let mut pending: Option<char> = None;
+
+    for c in input.nfkc().nfd().filter(|ch| !is_combining_mark(*ch)) {
+        if c == '/' || c == '\\' {
+            pending = Some('/');
+            prev_char = None;
+            continue;
+        }
+
+        if !c.is_alphanumeric() || c == '\u{2014}' {
+            pending = pending.or(Some(' '));
+            prev_char = None;
+            continue;
+        }
+
+        if let Some(p) = prev_char {
+            let is_boundary = (p.is_lowercase() && c.is_uppercase())
+                || (p.is_alphabetic() && c.is_numeric())
+                || (p.is_numeric() && c.is_alphabetic())
+                || is_cjk(p) != is_cjk(c);
+            if is_boundary {
+                pending = Some(' ');
+            }
+        }
+
+        if let Some(boundary) = pending.take()
+            && !normalized.is_empty()
+        {
+            normalized.push(boundary);
+        }
+
+        normalized.extend(c.to_lowercase());
+        prev_char = Some(c);
+    }
+
+    normalized
+}
+
+pub(crate) const SYNTH_PATH_TOKEN_TAG: char = '\x00';
 pub(crate) const SYNTH_VOLUME_TOKEN_TAG: char = '\x01';
 pub(crate) const SYNTH_EXT_TOKEN_TAG: char = '\x02';
+pub(crate) const SYNTH_TAG_TOKEN_TAG: char = '\x03';
+pub(crate) const SYNTH_DELETION_TOKEN_TAG: char = '\x04';
+pub(crate) const SYNTH_CONTENT_HASH_TOKEN_TAG: char = '\x05';
+pub(crate) const SYNTH_TARGET_TOKEN_TAG: char = '\x06';
+pub(crate) const SYNTH_FILE_ID_TOKEN_TAG: char = '\x07';
+
+/// Highest synthetic token tag, tags are allocated contiguously from `\x00`
+pub(crate) const SYNTH_MAX_TAG: char = SYNTH_FILE_ID_TOKEN_TAG;
+
+/// Synthetic tokens are always matched exactly, never by prefix
+#[inline(always)]
+pub(crate) fn is_synthetic(token: &str) -> bool {
+    token
+        .as_bytes()
+        .first()
+        .is_some_and(|&b| is_synthetic_byte(b))
+}
+
+/// Whether a token starting with `byte` is synthetic
+#[inline(always)]
+pub(crate) fn is_synthetic_byte(byte: u8) -> bool {
+    byte <= SYNTH_MAX_TAG as u8
+}
 
 #[inline(always)]
 pub(crate) fn synthesize_token(tag: char, orig: &str) -> String {
@@ -199,6 +328,54 @@ pub(crate) fn synthesize_token(tag: char, orig: &str) -> String {
     token
 }
 
+/// Synthetic token for all entries under a directory, or `None` for the root
+/// (which every entry lives under).
+pub(crate) fn path_prefix_token(dir: &str) -> Option<String> {
+    let mut prefix = dir.replace(['/', '\\'], std::path::MAIN_SEPARATOR_STR);
+    if prefix
+        .trim_end_matches(std::path::MAIN_SEPARATOR)
+        .is_empty()
+    {
+        return None;
+    }
+    if !prefix.ends_with(std::path::MAIN_SEPARATOR) {
+        prefix.push(std::path::MAIN_SEPARATOR);
+    }
+    Some(synthesize_token(SYNTH_PATH_TOKEN_TAG, &prefix))
+}
+
+/// Synthetic token for all symlinks whose resolved target is `dir` or lies
+/// under it. Unlike path prefixes, the root is a valid prefix, matching
+/// every symlink with an absolute target.
+pub(crate) fn target_prefix_token(dir: &str) -> String {
+    let mut prefix = dir.replace(['/', '\\'], std::path::MAIN_SEPARATOR_STR);
+    if !prefix.ends_with(std::path::MAIN_SEPARATOR) {
+        prefix.push(std::path::MAIN_SEPARATOR);
+    }
+    synthesize_token(SYNTH_TARGET_TOKEN_TAG, &prefix)
+}
+
+/// Synthetic token for the deletion of the entry at `path`
+pub(crate) fn deletion_token(path: &str) -> String {
+    synthesize_token(SYNTH_DELETION_TOKEN_TAG, path)
+}
+
+/// Synthetic token for all entries with the given content hash
+pub(crate) fn content_hash_token(hash: u128) -> String {
+    format!("{SYNTH_CONTENT_HASH_TOKEN_TAG}{hash:032x}")
+}
+
+/// Range covering the content hash tokens of all entries
+pub(crate) fn content_hash_token_range() -> std::ops::Range<String> {
+    let next_tag = char::from(SYNTH_CONTENT_HASH_TOKEN_TAG as u8 + 1);
+    SYNTH_CONTENT_HASH_TOKEN_TAG.to_string()..next_tag.to_string()
+}
+
+/// Synthetic token for all entries with the given file identity
+pub(crate) fn file_id_token(file_id: FileId) -> String {
+    format!("{SYNTH_FILE_ID_TOKEN_TAG}{}:{}", file_id.dev, file_id.inode)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -266,11 +443,43 @@ mod tests {
         assert_eq!(fold_path("Café/Report_2023"), "cafe/report_2023");
     }
 
+    #[test]
+    fn test_normalize_phrase() {
+        assert_eq!(normalize_phrase("Annual  Report"), "annual report");
+        assert_eq!(
+            normalize_phrase("/docs/AnnualReport_2023.pdf"),
+            "docs/annual report 2023 pdf"
+        );
+        assert_eq!(
+            normalize_phrase("C:\\Users\\me - notes"),
+            "c/users/me notes"
+        );
+        assert_eq!(normalize_phrase("Café-menu"), "cafe menu");
+        assert_eq!(normalize_phrase(" - "), "");
+    }
+
     #[test]
     fn test_synthetic_tokens() {
         assert_eq!(synthesize_token(SYNTH_PATH_TOKEN_TAG, "abc"), "\x00abc");
         assert_eq!(synthesize_token(SYNTH_VOLUME_TOKEN_TAG, "c:"), "\x01c:");
         assert_eq!(synthesize_token(SYNTH_EXT_TOKEN_TAG, "pdf"), "\x02pdf");
+        assert!(is_synthetic(&synthesize_token(
+            SYNTH_TAG_TOKEN_TAG,
+            "starred"
+        )));
+        assert!(is_synthetic(&synthesize_token(
+            SYNTH_TARGET_TOKEN_TAG,
+            "/mnt/"
+        )));
+        assert_eq!(deletion_token("/Docs/a.txt"), "\x04/docs/a.txt");
+        assert_eq!(
+            content_hash_token(0xab),
+            "\x05000000000000000000000000000000ab"
+        );
+        assert!(content_hash_token_range().contains(&content_hash_token(u128::MAX)));
+        assert_eq!(file_id_token(FileId { dev: 1, inode: 42 }), "\x071:42");
+        assert!(is_synthetic(&file_id_token(FileId { dev: 1, inode: 42 })));
+        assert!(!is_synthetic("pdf"));
     }
 
     #[test]
@@ -1,37 +1,97 @@
 use std::{
     fs::{File, OpenOptions},
-    io::{BufReader, BufWriter, Read, Write},
+    io::{BufRead, BufReader, BufWriter, Read, Seek, Write},
     path::{Path, PathBuf},
 };
 
-use crate::entry::IndexEntry;
+use crate::{
+    common::PrefixRename,
+    crc32c::crc32c,
+    entry::{EntryUpdate, IndexEntry},
+};
 
-const WAL_RECORD_INSERT: u8 = 0;
+/// Insert with a fixed size entry, as written by older versions
+const WAL_RECORD_INSERT_V1: u8 = 0;
 const WAL_RECORD_TOMBSTONE: u8 = 1;
+/// Insert with a length-prefixed entry
+const WAL_RECORD_INSERT: u8 = 2;
+const WAL_RECORD_RENAME_PREFIX: u8 = 3;
+/// Partial update of an entry's fields
+const WAL_RECORD_UPDATE: u8 = 4;
+
+/// Magic of the header starting WAL files, followed by the format version
+/// (u32). Files written by older versions start directly with a record.
+const WAL_MAGIC: &[u8; 4] = b"zMDW";
+const WAL_HEADER_SIZE: u64 = 8;
+/// Records are framed by their length (u32) and CRC32C checksum (u32)
+const WAL_FORMAT_VERSION: u32 = 1;
+/// Version of the WALs written before headers existed, with unframed
+/// records
+const WAL_LEGACY_VERSION: u32 = 0;
 
 pub struct Wal {
     path: PathBuf,
     writer: Option<BufWriter<File>>,
+    /// Whether records are framed, false when appending to a WAL of an
+    /// older version
+    framed: bool,
 }
 
 impl Wal {
+    /// Opens the WAL at `path` for appending. An existing WAL is replayed
+    /// first, which truncates any torn or corrupt tail, and keeps its
+    /// format version.
     pub(crate) fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
         let path = path.as_ref().to_path_buf();
-        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        let replay = Self::replay(&path)?;
+        if replay.dropped_bytes > 0 {
+            log::warn!(
+                "Dropped {} bytes of corrupt records at the end of WAL {:?}",
+                replay.dropped_bytes,
+                path
+            );
+        }
+        let writer = Self::open_writer(&path)?;
 
         Ok(Self {
             path,
-            writer: Some(BufWriter::new(file)),
+            writer: Some(writer),
+            framed: replay.version != WAL_LEGACY_VERSION,
         })
     }
 
+    /// Opens a WAL file for appending, writing the header if it's new
+    fn open_writer(path: &Path) -> std::io::Result<BufWriter<File>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_new = file.metadata()?.len() == 0;
+
+        let mut writer = BufWriter::new(file);
+        if is_new {
+            writer.write_all(WAL_MAGIC)?;
+            writer.write_all(&WAL_FORMAT_VERSION.to_le_bytes())?;
+        }
+        Ok(writer)
+    }
+
+    /// Appends a record, framed unless the WAL is of an older version
+    fn write_record(&mut self, record: &[u8]) -> std::io::Result<()> {
+        let writer = self.writer.as_mut().expect("WAL writer missing");
+
+        if self.framed {
+            writer.write_all(&(record.len() as u32).to_le_bytes())?;
+            writer.write_all(&crc32c(record).to_le_bytes())?;
+        }
+        writer.write_all(record)
+    }
+
     pub(crate) fn append(
         &mut self,
         path: &str,
         volume: &str,
         entry: &IndexEntry,
     ) -> std::io::Result<()> {
-        let writer = self.writer.as_mut().expect("WAL writer missing");
+        let mut record = Vec::new();
 
         let path_bytes = path.as_bytes();
         let path_len = path_bytes.len() as u32;
@@ -39,13 +99,16 @@ impl Wal {
         let volume_bytes = volume.as_bytes();
         let volume_len = volume_bytes.len() as u32;
 
-        writer.write_all(&[WAL_RECORD_INSERT])?;
-        writer.write_all(&path_len.to_le_bytes())?;
-        writer.write_all(path_bytes)?;
-        writer.write_all(&volume_len.to_le_bytes())?;
-        writer.write_all(volume_bytes)?;
-        writer.write_all(&entry.as_bytes())?;
-        Ok(())
+        record.write_all(&[WAL_RECORD_INSERT])?;
+        record.write_all(&path_len.to_le_bytes())?;
+        record.write_all(path_bytes)?;
+        record.write_all(&volume_len.to_le_bytes())?;
+        record.write_all(volume_bytes)?;
+        let entry_bytes = entry.as_bytes();
+        record.write_all(&(entry_bytes.len() as u16).to_le_bytes())?;
+        record.write_all(&entry_bytes)?;
+
+        self.write_record(&record)
     }
 
     pub fn write_prefix_tombstone(
@@ -54,28 +117,73 @@ impl Wal {
         prefix: &str,
         seq: u64,
     ) -> std::io::Result<()> {
-        let writer = self.writer.as_mut().expect("WAL writer missing");
+        let mut record = Vec::new();
         let prefix_bytes = prefix.as_bytes();
 
-        writer.write_all(&[WAL_RECORD_TOMBSTONE])?;
+        record.write_all(&[WAL_RECORD_TOMBSTONE])?;
 
-        writer.write_all(&seq.to_le_bytes())?;
+        record.write_all(&seq.to_le_bytes())?;
 
         if let Some(volume) = volume {
-            writer.write_all(&[1])?;
+            record.write_all(&[1])?;
 
             let volume_bytes = volume.as_bytes();
-            writer.write_all(&(volume_bytes.len() as u32).to_le_bytes())?;
-            writer.write_all(volume_bytes)?;
+            record.write_all(&(volume_bytes.len() as u32).to_le_bytes())?;
+            record.write_all(volume_bytes)?;
         } else {
-            writer.write_all(&[0])?;
+            record.write_all(&[0])?;
         }
 
-        writer.write_all(&(prefix_bytes.len() as u32).to_le_bytes())?;
+        record.write_all(&(prefix_bytes.len() as u32).to_le_bytes())?;
 
-        writer.write_all(prefix_bytes)?;
+        record.write_all(prefix_bytes)?;
 
-        Ok(())
+        self.write_record(&record)
+    }
+
+    pub(crate) fn write_prefix_rename(&mut self, rename: &PrefixRename) -> std::io::Result<()> {
+        let mut record = Vec::new();
+
+        record.write_all(&[WAL_RECORD_RENAME_PREFIX])?;
+
+        record.write_all(&rename.seq.to_le_bytes())?;
+
+        if let Some(volume) = &rename.volume {
+            record.write_all(&[1])?;
+
+            let volume_bytes = volume.as_bytes();
+            record.write_all(&(volume_bytes.len() as u32).to_le_bytes())?;
+            record.write_all(volume_bytes)?;
+        } else {
+            record.write_all(&[0])?;
+        }
+
+        for prefix in [&rename.old_prefix, &rename.new_prefix] {
+            record.write_all(&(prefix.len() as u32).to_le_bytes())?;
+            record.write_all(prefix.as_bytes())?;
+        }
+
+        self.write_record(&record)
+    }
+
+    pub(crate) fn write_update(
+        &mut self,
+        path: &str,
+        seq: u64,
+        update: &EntryUpdate,
+    ) -> std::io::Result<()> {
+        let mut record = Vec::new();
+        let path_bytes = path.as_bytes();
+
+        record.write_all(&[WAL_RECORD_UPDATE])?;
+        record.write_all(&(path_bytes.len() as u32).to_le_bytes())?;
+        record.write_all(path_bytes)?;
+        record.write_all(&seq.to_le_bytes())?;
+        let update_bytes = update.as_bytes();
+        record.write_all(&[update_bytes.len() as u8])?;
+        record.write_all(&update_bytes)?;
+
+        self.write_record(&record)
     }
 
     pub(crate) fn flush(&mut self) -> std::io::Result<()> {
@@ -87,146 +195,90 @@ impl Wal {
         Ok(())
     }
 
+    /// Reads back the records of a WAL. Replay stops at the first torn or
+    /// corrupt record, and truncates the file there so that later appends
+    /// follow the last valid record; `ReplayData::dropped_bytes` tells how
+    /// much was cut.
     pub(crate) fn replay<P: AsRef<Path>>(path: P) -> std::io::Result<ReplayData> {
-        let file = match File::open(&path) {
+        let path = path.as_ref();
+        let file = match File::open(path) {
             Ok(f) => f,
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ReplayData::new()),
             Err(e) => return Err(e),
         };
+        let file_len = file.metadata()?.len();
 
         let mut reader = BufReader::new(file);
         let mut results = ReplayData::new();
-        let mut len_buf = [0u8; 4];
-
-        loop {
-            let mut type_buf = [0u8; 1];
-
-            if let Err(e) = reader.read_exact(&mut type_buf) {
-                if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                    break; // Reached the end of the WAL normally
-                }
-                return Err(e);
-            }
-
-            match type_buf[0] {
-                WAL_RECORD_INSERT => {
-                    match reader.read_exact(&mut len_buf) {
-                        Ok(_) => {}
-                        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
-                        Err(e) => return Err(e),
-                    }
-                    let len = u32::from_le_bytes(len_buf) as usize;
-                    let mut path_buf = vec![0u8; len];
-
-                    if let Err(e) = reader.read_exact(&mut path_buf) {
-                        if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                            break;
-                        }
-                        return Err(e);
+        let mut valid_len = 0;
+
+        // No record type starts with the magic's first byte, so WALs
+        // without a header are told apart by it
+        match reader.fill_buf()?.first() {
+            None => return Ok(results),
+            Some(&byte) if byte == WAL_MAGIC[0] => match read_array::<8>(&mut reader) {
+                Ok(header) => {
+                    if !header.starts_with(WAL_MAGIC) {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "Invalid WAL header",
+                        ));
                     }
-                    let path = String::from_utf8_lossy(&path_buf).to_string();
-
-                    if let Err(e) = reader.read_exact(&mut len_buf) {
-                        if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                            log::warn!(
-                                "Wal parser hit EOF early! Stopped at {}",
-                                results.inserts.len()
-                            );
-                            break;
-                        }
-                        return Err(e);
-                    }
-                    let len = u32::from_le_bytes(len_buf) as usize;
-                    let mut vol_buf = vec![0u8; len];
-
-                    if let Err(e) = reader.read_exact(&mut vol_buf) {
-                        if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                            break;
-                        }
-                        return Err(e);
-                    }
-
-                    let volume = String::from_utf8_lossy(&vol_buf).to_string();
-
-                    let mut entry_buf = [0u8; IndexEntry::SIZE];
-                    if let Err(e) = reader.read_exact(&mut entry_buf) {
-                        if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                            log::warn!(
-                                "Wal parser hit EOF early! Stopped at {}",
-                                results.inserts.len()
-                            );
-                            break;
-                        }
-                        return Err(e);
+                    let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+                    if version != WAL_FORMAT_VERSION {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("Unsupported WAL format version {version}"),
+                        ));
                     }
-                    let entry = IndexEntry::from_bytes(&entry_buf);
-
-                    results.inserts.push((path, volume, entry));
+                    results.version = version;
+                    valid_len = WAL_HEADER_SIZE;
                 }
-                WAL_RECORD_TOMBSTONE => {
-                    let mut read_or_break = |buf: &mut [u8]| -> Result<bool, std::io::Error> {
-                        match reader.read_exact(buf) {
-                            Ok(_) => Ok(true),
-                            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
-                            Err(e) => Err(e),
-                        }
-                    };
-                    let mut seq_buf = [0u8; 8];
-                    if !read_or_break(&mut seq_buf)? {
-                        break;
-                    }
-                    let seq = u64::from_le_bytes(seq_buf);
-
-                    let mut len_buf = [0u8; 4];
-
-                    let mut flag_buf = [0u8; 1];
-                    if !read_or_break(&mut flag_buf)? {
-                        break;
-                    }
-                    let has_volume = flag_buf[0] == 1;
-
-                    let volume =
-                        if has_volume {
-                            if !read_or_break(&mut len_buf)? {
-                                break;
-                            }
-                            let len = u32::from_le_bytes(len_buf) as usize;
-
-                            let mut volume_buf = vec![0u8; len];
-                            if !read_or_break(&mut volume_buf)? {
-                                break;
-                            }
-
-                            Some(String::from_utf8(volume_buf).map_err(|e| {
-                                std::io::Error::new(std::io::ErrorKind::InvalidData, e)
-                            })?)
-                        } else {
-                            None
-                        };
-
-                    if !read_or_break(&mut len_buf)? {
-                        break;
-                    }
-                    let len = u32::from_le_bytes(len_buf) as usize;
+                // A torn header is dropped like a torn record
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+                Err(e) => return Err(e),
+            },
+            Some(_) => results.version = WAL_LEGACY_VERSION,
+        }
 
-                    let mut prefix_buf = vec![0u8; len];
-                    if !read_or_break(&mut prefix_buf)? {
-                        break;
-                    }
-                    let prefix = String::from_utf8(prefix_buf)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        while valid_len > 0 || results.version == WAL_LEGACY_VERSION {
+            let record = if results.version == WAL_FORMAT_VERSION {
+                read_frame(&mut reader)
+            } else {
+                read_record(&mut reader)
+            };
 
-                    results.tombstones.push((volume, prefix, seq));
+            match record {
+                Ok(Some(record)) => {
+                    results.push(record);
+                    valid_len = reader.stream_position()?;
                 }
-                _ => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        "Corrupted WAL",
-                    ));
+                Ok(None) => break, // Reached the end of the WAL normally
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::InvalidData
+                    ) =>
+                {
+                    log::warn!(
+                        "Stopping replay of WAL {:?} at offset {}: {}",
+                        path,
+                        valid_len,
+                        e
+                    );
+                    break;
                 }
+                Err(e) => return Err(e),
             }
         }
 
+        if valid_len < file_len {
+            results.dropped_bytes = file_len - valid_len;
+            let file = OpenOptions::new().write(true).open(path)?;
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+
         log::debug!(
             "Wal replay complete, recovered {} entries",
             results.inserts.len()
@@ -242,20 +294,149 @@ impl Wal {
 
         std::fs::rename(&self.path, path)?;
 
-        let new_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.path)?;
-
-        self.writer = Some(BufWriter::new(new_file));
+        self.writer = Some(Self::open_writer(&self.path)?);
+        self.framed = true;
 
         Ok(())
     }
 }
 
+/// A record read back from a WAL
+enum WalRecord {
+    Insert(String, String, IndexEntry),
+    Tombstone(Option<String>, String, u64),
+    Rename(PrefixRename),
+    Update(String, u64, EntryUpdate),
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Reads a framed record, `None` at the end of the WAL. Frames failing
+/// their checksum, or holding anything but a single record, are invalid.
+fn read_frame(reader: &mut impl Read) -> std::io::Result<Option<WalRecord>> {
+    let mut frame = [0u8; 8];
+    let read = reader.read(&mut frame)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if read < frame.len() {
+        reader.read_exact(&mut frame[read..])?;
+    }
+
+    let len = u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(frame[4..].try_into().unwrap());
+    let payload = read_bytes(reader, len)?;
+    if crc32c(&payload) != checksum {
+        return Err(invalid_data("WAL record checksum mismatch"));
+    }
+
+    let mut rest = payload.as_slice();
+    match read_record(&mut rest)? {
+        Some(record) if rest.is_empty() => Ok(Some(record)),
+        _ => Err(invalid_data("Corrupted WAL frame")),
+    }
+}
+
+/// Reads an unframed record, `None` at the end of the WAL. Records cut
+/// short fail with `UnexpectedEof`, and malformed ones with `InvalidData`.
+fn read_record(reader: &mut impl Read) -> std::io::Result<Option<WalRecord>> {
+    let mut type_buf = [0u8; 1];
+    if reader.read(&mut type_buf)? == 0 {
+        return Ok(None);
+    }
+
+    let record = match type_buf[0] {
+        record_type @ (WAL_RECORD_INSERT_V1 | WAL_RECORD_INSERT) => {
+            let path = read_string(reader)?;
+            let volume = read_string(reader)?;
+
+            let entry_len = if record_type == WAL_RECORD_INSERT {
+                u16::from_le_bytes(read_array(reader)?) as usize
+            } else {
+                IndexEntry::LEGACY_SIZE
+            };
+            let entry = IndexEntry::from_bytes(&read_bytes(reader, entry_len)?)
+                .ok_or_else(|| invalid_data("Corrupted WAL entry"))?;
+
+            WalRecord::Insert(path, volume, entry)
+        }
+        WAL_RECORD_TOMBSTONE => {
+            let seq = u64::from_le_bytes(read_array(reader)?);
+            let volume = read_volume(reader)?;
+            let prefix = read_string(reader)?;
+
+            WalRecord::Tombstone(volume, prefix, seq)
+        }
+        WAL_RECORD_RENAME_PREFIX => {
+            let seq = u64::from_le_bytes(read_array(reader)?);
+            let volume = read_volume(reader)?;
+
+            WalRecord::Rename(PrefixRename {
+                volume,
+                old_prefix: read_string(reader)?,
+                new_prefix: read_string(reader)?,
+                seq,
+            })
+        }
+        WAL_RECORD_UPDATE => {
+            let path = read_string(reader)?;
+            let seq = u64::from_le_bytes(read_array(reader)?);
+            let [update_len] = read_array(reader)?;
+            let update = EntryUpdate::from_bytes(&read_bytes(reader, update_len as usize)?)
+                .ok_or_else(|| invalid_data("Corrupted WAL update"))?;
+
+            WalRecord::Update(path, seq, update)
+        }
+        _ => return Err(invalid_data("Corrupted WAL")),
+    };
+
+    Ok(Some(record))
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads `len` bytes, only allocating for the bytes actually there so that
+/// corrupt lengths fail with `UnexpectedEof` instead of exhausting memory
+fn read_bytes(reader: &mut impl Read, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+/// Reads a `u32` length-prefixed UTF-8 string
+fn read_string(reader: &mut impl Read) -> std::io::Result<String> {
+    let len = u32::from_le_bytes(read_array(reader)?) as usize;
+    String::from_utf8(read_bytes(reader, len)?)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Reads an optional volume, as a presence flag then the string
+fn read_volume(reader: &mut impl Read) -> std::io::Result<Option<String>> {
+    match read_array(reader)? {
+        [0] => Ok(None),
+        [1] => read_string(reader).map(Some),
+        _ => Err(invalid_data("Corrupted WAL volume")),
+    }
+}
+
 pub(crate) struct ReplayData {
     pub inserts: Vec<(String, String, IndexEntry)>,
     pub tombstones: Vec<(Option<String>, String, u64)>,
+    pub renames: Vec<PrefixRename>,
+    pub updates: Vec<(String, u64, EntryUpdate)>,
+    /// Format version of the WAL, `WAL_LEGACY_VERSION` if it has no header
+    pub version: u32,
+    /// Bytes of torn or corrupt records cut from the end of the WAL
+    pub dropped_bytes: u64,
 }
 
 impl ReplayData {
@@ -263,6 +444,21 @@ impl ReplayData {
         Self {
             inserts: Vec::new(),
             tombstones: Vec::new(),
+            renames: Vec::new(),
+            updates: Vec::new(),
+            version: WAL_FORMAT_VERSION,
+            dropped_bytes: 0,
+        }
+    }
+
+    fn push(&mut self, record: WalRecord) {
+        match record {
+            WalRecord::Insert(path, volume, entry) => self.inserts.push((path, volume, entry)),
+            WalRecord::Tombstone(volume, prefix, seq) => {
+                self.tombstones.push((volume, prefix, seq))
+            }
+            WalRecord::Rename(rename) => self.renames.push(rename),
+            WalRecord::Update(path, seq, update) => self.updates.push((path, seq, update)),
         }
     }
 }
@@ -270,7 +466,6 @@ impl ReplayData {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Kind;
     use crate::opstamp::Opstamp;
 
     #[test]
@@ -282,16 +477,32 @@ mod tests {
         {
             let mut wal = Wal::open(&wal_path)?;
             let entry = IndexEntry {
-                opstamp: Opstamp::insertion(10),
-                kind: Kind::File,
-                last_modified: 100,
-                last_accessed: 100,
-                category: 0,
-                volume_type: crate::common::VolumeType::Local,
+                size: 4096,
+                ..IndexEntry::file(Opstamp::insertion(10), 100)
             };
             wal.append("/foo", "vol1", &entry)?;
             wal.write_prefix_tombstone(None, "/bar", 20)?;
             wal.write_prefix_tombstone(Some("vol1"), "/baz", 30)?;
+            wal.write_prefix_rename(&PrefixRename {
+                volume: Some("vol1".to_string()),
+                old_prefix: "/old".to_string(),
+                new_prefix: "/new".to_string(),
+                seq: 40,
+            })?;
+            wal.write_prefix_rename(&PrefixRename {
+                volume: None,
+                old_prefix: "/a".to_string(),
+                new_prefix: "/b".to_string(),
+                seq: 50,
+            })?;
+            wal.write_update(
+                "/foo",
+                60,
+                &EntryUpdate {
+                    last_accessed: Some(600),
+                    ..Default::default()
+                },
+            )?;
             wal.flush()?;
         }
 
@@ -300,6 +511,7 @@ mod tests {
         assert_eq!(replay.inserts[0].0, "/foo");
         assert_eq!(replay.inserts[0].1, "vol1");
         assert_eq!(replay.inserts[0].2.opstamp.sequence(), 10);
+        assert_eq!(replay.inserts[0].2.size, 4096);
 
         assert_eq!(replay.tombstones.len(), 2);
         assert_eq!(replay.tombstones[0].0, None);
@@ -310,6 +522,135 @@ mod tests {
         assert_eq!(replay.tombstones[1].1, "/baz");
         assert_eq!(replay.tombstones[1].2, 30);
 
+        assert_eq!(replay.renames.len(), 2);
+        assert_eq!(replay.renames[0].volume.as_deref(), Some("vol1"));
+        assert_eq!(replay.renames[0].old_prefix, "/old");
+        assert_eq!(replay.renames[0].new_prefix, "/new");
+        assert_eq!(replay.renames[0].seq, 40);
+        assert_eq!(replay.renames[1].volume, None);
+        assert_eq!(replay.renames[1].seq, 50);
+
+        assert_eq!(replay.updates.len(), 1);
+        assert_eq!(replay.updates[0].0, "/foo");
+        assert_eq!(replay.updates[0].1, 60);
+        assert_eq!(replay.updates[0].2.last_accessed, Some(600));
+        assert_eq!(replay.updates[0].2.size, None);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_wal_header() -> std::io::Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_wal_hdr_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+        let wal_path = temp_dir.join("test.wal");
+
+        // Reopening an existing WAL appends to it without a second header
+        for seq in [10, 20] {
+            let mut wal = Wal::open(&wal_path)?;
+            wal.write_prefix_tombstone(None, "/foo", seq)?;
+            wal.flush()?;
+        }
+        let bytes = std::fs::read(&wal_path)?;
+        assert!(bytes.starts_with(WAL_MAGIC));
+        assert_eq!(&bytes[4..8], &WAL_FORMAT_VERSION.to_le_bytes());
+        assert_eq!(Wal::replay(&wal_path)?.tombstones.len(), 2);
+
+        // WALs of older versions have no header
+        let legacy_path = temp_dir.join("legacy.wal");
+        let mut legacy = vec![WAL_RECORD_TOMBSTONE];
+        legacy.extend_from_slice(&30u64.to_le_bytes());
+        legacy.push(0);
+        legacy.extend_from_slice(&4u32.to_le_bytes());
+        legacy.extend_from_slice(b"/bar");
+        std::fs::write(&legacy_path, &legacy)?;
+        let replay = Wal::replay(&legacy_path)?;
+        assert_eq!(replay.tombstones, vec![(None, "/bar".to_string(), 30)]);
+
+        // Unknown versions are rejected
+        let mut newer = bytes.clone();
+        newer[4..8].copy_from_slice(&(WAL_FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&wal_path, newer)?;
+        let err = Wal::replay(&wal_path).err().expect("newer WAL should fail");
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_wal_corrupt_tail() -> std::io::Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_wal_tail_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+        let wal_path = temp_dir.join("test.wal");
+
+        let write_tombstones = |seqs: &[u64]| -> std::io::Result<Vec<u8>> {
+            std::fs::remove_file(&wal_path).ok();
+            let mut wal = Wal::open(&wal_path)?;
+            for seq in seqs {
+                wal.write_prefix_tombstone(None, "/foo", *seq)?;
+            }
+            wal.flush()?;
+            std::fs::read(&wal_path)
+        };
+        let seqs = |replay: &ReplayData| -> Vec<u64> {
+            replay.tombstones.iter().map(|(_, _, seq)| *seq).collect()
+        };
+
+        let bytes = write_tombstones(&[10, 20, 30])?;
+        let record_len = (bytes.len() - WAL_HEADER_SIZE as usize) / 3;
+
+        // A torn record is dropped, and the file cut after the last valid one
+        std::fs::write(&wal_path, &bytes[..bytes.len() - 3])?;
+        let replay = Wal::replay(&wal_path)?;
+        assert_eq!(seqs(&replay), vec![10, 20]);
+        assert_eq!(replay.dropped_bytes, record_len as u64 - 3);
+        assert_eq!(
+            std::fs::metadata(&wal_path)?.len(),
+            (bytes.len() - record_len) as u64
+        );
+
+        // So that appends after reopening it are read back
+        {
+            let mut wal = Wal::open(&wal_path)?;
+            wal.write_prefix_tombstone(None, "/foo", 40)?;
+            wal.flush()?;
+        }
+        let replay = Wal::replay(&wal_path)?;
+        assert_eq!(seqs(&replay), vec![10, 20, 40]);
+        assert_eq!(replay.dropped_bytes, 0);
+
+        // Replay stops at the first record failing its checksum
+        let mut corrupt = write_tombstones(&[10, 20, 30])?;
+        corrupt[WAL_HEADER_SIZE as usize + record_len + 12] ^= 1;
+        std::fs::write(&wal_path, &corrupt)?;
+        let replay = Wal::replay(&wal_path)?;
+        assert_eq!(seqs(&replay), vec![10]);
+        assert_eq!(replay.dropped_bytes, 2 * record_len as u64);
+
+        // Corrupt lengths and zeroed tails don't go any further
+        let mut huge = write_tombstones(&[10])?;
+        huge.extend_from_slice(&u32::MAX.to_le_bytes());
+        huge.extend_from_slice(&[0xAB; 12]);
+        std::fs::write(&wal_path, &huge)?;
+        let replay = Wal::replay(&wal_path)?;
+        assert_eq!(seqs(&replay), vec![10]);
+        assert_eq!(replay.dropped_bytes, 16);
+
+        let mut zeroed = write_tombstones(&[10])?;
+        zeroed.extend_from_slice(&[0; 64]);
+        std::fs::write(&wal_path, &zeroed)?;
+        let replay = Wal::replay(&wal_path)?;
+        assert_eq!(seqs(&replay), vec![10]);
+        assert_eq!(replay.dropped_bytes, 64);
+
+        // Torn headers leave an empty WAL
+        std::fs::write(&wal_path, &WAL_MAGIC[..3])?;
+        let replay = Wal::replay(&wal_path)?;
+        assert_eq!(replay.dropped_bytes, 3);
+        assert_eq!(std::fs::metadata(&wal_path)?.len(), 0);
+
         std::fs::remove_dir_all(temp_dir)?;
         Ok(())
     }
@@ -323,25 +664,11 @@ mod tests {
 
         {
             let mut wal = Wal::open(&wal_path)?;
-            let entry = IndexEntry {
-                opstamp: Opstamp::insertion(10),
-                kind: Kind::File,
-                last_modified: 100,
-                last_accessed: 100,
-                category: 0,
-                volume_type: crate::common::VolumeType::Local,
-            };
+            let entry = IndexEntry::file(Opstamp::insertion(10), 100);
             wal.append("/foo", "vol1", &entry)?;
             wal.rotate(&rot_path)?;
 
-            let entry2 = IndexEntry {
-                opstamp: Opstamp::insertion(20),
-                kind: Kind::File,
-                last_modified: 200,
-                last_accessed: 200,
-                category: 0,
-                volume_type: crate::common::VolumeType::Local,
-            };
+            let entry2 = IndexEntry::file(Opstamp::insertion(20), 200);
             wal.append("/bar", "vol1", &entry2)?;
             wal.flush()?;
         }
@@ -360,6 +687,49 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_wal_replay_legacy_inserts() -> std::io::Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("minidex_test_wal_v1_{}", rand_id()));
+        std::fs::create_dir_all(&temp_dir)?;
+        let wal_path = temp_dir.join("journal.wal");
+
+        let entry = IndexEntry {
+            last_accessed: 200,
+            ..IndexEntry::file(Opstamp::insertion(10), 100)
+        };
+        let mut entry_bytes = [0u8; IndexEntry::LEGACY_SIZE];
+        entry_bytes[..27].copy_from_slice(&entry.as_bytes()[..27]);
+
+        let mut record = vec![WAL_RECORD_INSERT_V1];
+        record.extend_from_slice(&4u32.to_le_bytes());
+        record.extend_from_slice(b"/foo");
+        record.extend_from_slice(&4u32.to_le_bytes());
+        record.extend_from_slice(b"vol1");
+        record.extend_from_slice(&entry_bytes);
+        // Followed by a record of an unknown type
+        record.push(9);
+        std::fs::write(&wal_path, &record)?;
+
+        // New records are appended after the legacy ones, once the
+        // corrupt tail is cut
+        {
+            let mut wal = Wal::open(&wal_path)?;
+            wal.append("/bar", "vol1", &IndexEntry { size: 10, ..entry })?;
+            wal.flush()?;
+        }
+
+        let replay = Wal::replay(&wal_path)?;
+        assert_eq!(replay.inserts.len(), 2);
+        assert_eq!(replay.inserts[0].0, "/foo");
+        assert_eq!(replay.inserts[0].2.last_accessed, 200);
+        assert_eq!(replay.inserts[0].2.size, 0);
+        assert_eq!(replay.inserts[1].0, "/bar");
+        assert_eq!(replay.inserts[1].2.size, 10);
+
+        std::fs::remove_dir_all(temp_dir)?;
+        Ok(())
+    }
+
     fn rand_id() -> u64 {
         crate::sync::time::SystemTime::now()
             .duration_since(crate::sync::time::UNIX_EPOCH)